@@ -0,0 +1,10 @@
+//! Shared building blocks for the MCP router and the bundled stdio servers:
+//! the JSON-RPC 2.0 wire types and a small newline-delimited stdio serve loop.
+
+pub mod rpc;
+pub mod stdio;
+
+pub use rpc::{Id, Request, Response, RpcError};
+
+/// MCP protocol revision spoken by the bundled servers.
+pub const PROTOCOL_VERSION: &str = "2024-05-13";
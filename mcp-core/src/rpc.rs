@@ -0,0 +1,178 @@
+//! JSON-RPC 2.0 message types as used by MCP.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A JSON-RPC request id. MCP allows string or integer ids; `Null` shows up
+/// in error responses to unparseable requests.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Int(i64),
+    Str(String),
+    Null,
+}
+
+/// A JSON-RPC request (or notification, when `id` is absent on the wire —
+/// notifications are deserialized with `id: Id::Null`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default = "Id::null", skip_serializing_if = "Id::is_null")]
+    pub id: Id,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// A JSON-RPC response carrying either `result` or `error`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// Standard JSON-RPC error codes plus the router's own range.
+pub mod code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+
+    /// The router is in maintenance mode and not forwarding calls.
+    pub const MAINTENANCE: i64 = -32000;
+    /// A call to an upstream server failed.
+    pub const UPSTREAM_ERROR: i64 = -32001;
+    /// The caller's subscription quota was exhausted.
+    pub const QUOTA_EXCEEDED: i64 = -32002;
+    /// The upstream's circuit breaker is open.
+    pub const CIRCUIT_OPEN: i64 = -32003;
+    /// The request has bounced between routers too many times.
+    pub const LOOP_DETECTED: i64 = -32004;
+    /// A `tools/call` result exceeded the router's configured size cap.
+    pub const RESULT_TOO_LARGE: i64 = -32005;
+    /// The router is at its concurrent-request limit; retry later.
+    pub const SERVER_BUSY: i64 = -32006;
+}
+
+impl Id {
+    pub fn null() -> Id {
+        Id::Null
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Id::Null)
+    }
+}
+
+impl Request {
+    /// Build a router-originated request.
+    pub fn new(method: impl Into<String>, params: Value) -> Request {
+        Request {
+            jsonrpc: "2.0".into(),
+            id: Id::Int(0),
+            method: method.into(),
+            params,
+        }
+    }
+
+    /// A notification: a request without an id, which must not be answered.
+    pub fn notification(method: impl Into<String>, params: Value) -> Request {
+        Request {
+            jsonrpc: "2.0".into(),
+            id: Id::Null,
+            method: method.into(),
+            params,
+        }
+    }
+
+    pub fn is_notification(&self) -> bool {
+        self.id.is_null()
+    }
+}
+
+impl Response {
+    pub fn success(id: Id, result: Value) -> Response {
+        Response {
+            jsonrpc: "2.0".into(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(id: Id, code: i64, message: impl Into<String>) -> Response {
+        Response {
+            jsonrpc: "2.0".into(),
+            id,
+            result: None,
+            error: Some(RpcError {
+                code,
+                message: message.into(),
+                data: None,
+            }),
+        }
+    }
+
+    pub fn error_with_data(id: Id, code: i64, message: impl Into<String>, data: Value) -> Response {
+        Response {
+            jsonrpc: "2.0".into(),
+            id,
+            result: None,
+            error: Some(RpcError {
+                code,
+                message: message.into(),
+                data: Some(data),
+            }),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn request_roundtrip() {
+        let req = Request::new("tools/list", json!({}));
+        let wire = serde_json::to_string(&req).unwrap();
+        let back: Request = serde_json::from_str(&wire).unwrap();
+        assert_eq!(back.method, "tools/list");
+        assert_eq!(back.id, Id::Int(0));
+    }
+
+    #[test]
+    fn notification_omits_id() {
+        let note = Request::notification("notifications/tools/list_changed", json!({}));
+        let wire = serde_json::to_value(&note).unwrap();
+        assert!(wire.get("id").is_none());
+        let back: Request = serde_json::from_value(wire).unwrap();
+        assert!(back.is_notification());
+    }
+
+    #[test]
+    fn error_response_shape() {
+        let resp = Response::error(Id::Int(7), code::METHOD_NOT_FOUND, "unknown method: nope");
+        let wire = serde_json::to_value(&resp).unwrap();
+        assert_eq!(wire["error"]["code"], json!(code::METHOD_NOT_FOUND));
+        assert!(wire.get("result").is_none());
+    }
+}
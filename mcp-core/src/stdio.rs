@@ -0,0 +1,39 @@
+//! Newline-delimited JSON-RPC over stdin/stdout, the transport shared by the
+//! bundled `mcp-*` servers.
+
+use std::future::Future;
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::rpc::{code, Id, Request, Response};
+
+/// Run a JSON-RPC server over stdin/stdout until stdin closes.
+///
+/// Each input line is parsed as a [`Request`] and passed to `handler`; the
+/// returned [`Response`] is written back as a single line. Parse failures are
+/// answered with `-32700` so a confused client still gets a frame back.
+pub async fn serve_lines<F, Fut>(handler: F) -> Result<()>
+where
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let stdin = BufReader::new(tokio::io::stdin());
+    let mut stdout = tokio::io::stdout();
+    let mut lines = stdin.lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handler(request).await,
+            Err(err) => Response::error(Id::Null, code::PARSE_ERROR, format!("parse error: {err}")),
+        };
+        let mut out = serde_json::to_vec(&response)?;
+        out.push(b'\n');
+        stdout.write_all(&out).await?;
+        stdout.flush().await?;
+    }
+    Ok(())
+}
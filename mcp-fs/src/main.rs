@@ -0,0 +1,470 @@
+//! `mcp-fs`: a filesystem MCP server scoped to a root directory.
+//!
+//! Exposes `fs/read`, `fs/write` and `fs/list` tools plus the files under the
+//! root as `file://` resources, over newline-delimited JSON-RPC on stdio.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Parser;
+use mcp_core::rpc::{code, Request, Response};
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(name = "mcp-fs", about = "Filesystem MCP server")]
+struct Args {
+    /// Directory the server is allowed to touch.
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+    /// Most bytes a single read may return. Oversized files are rejected with
+    /// a pointer at `fs/read_range`, so a huge file cannot OOM the server.
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    max_read_bytes: u64,
+}
+
+struct FsServer {
+    root: PathBuf,
+    max_read_bytes: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let server = FsServer {
+        root: args.root.canonicalize()?,
+        max_read_bytes: args.max_read_bytes,
+    };
+    mcp_core::stdio::serve_lines(|req| server.handle(req)).await
+}
+
+impl FsServer {
+    async fn handle(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        match req.method.as_str() {
+            "initialize" => Response::success(
+                id,
+                json!({
+                    "protocolVersion": mcp_core::PROTOCOL_VERSION,
+                    "serverInfo": {"name": "mcp-fs", "version": env!("CARGO_PKG_VERSION")},
+                    "capabilities": {"tools": {}, "resources": {}, "completions": {}},
+                }),
+            ),
+            "tools/list" => Response::success(id, json!({"tools": self.tools()})),
+            "tools/call" => self.tool_call(req),
+            "resources/list" => self.resources_list(id),
+            "resources/templates/list" => self.resource_templates_list(id),
+            "resources/read" => self.resources_read(req),
+            "completion/complete" => self.complete(req),
+            "prompts/list" => Response::success(id, json!({"prompts": []})),
+            other => Response::error(
+                id,
+                code::METHOD_NOT_FOUND,
+                format!("unknown method: {other}"),
+            ),
+        }
+    }
+
+    fn tools(&self) -> Value {
+        json!([
+            {
+                "name": "fs/read",
+                "description": "Read a UTF-8 text file relative to the server root",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {"path": {"type": "string"}},
+                    "required": ["path"],
+                },
+            },
+            {
+                "name": "fs/read_range",
+                "description": "Read a byte range out of a file, for files over the read cap",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {"type": "string"},
+                        "offset": {"type": "integer", "minimum": 0},
+                        "length": {"type": "integer", "minimum": 0},
+                    },
+                    "required": ["path"],
+                },
+            },
+            {
+                "name": "fs/write",
+                "description": "Write a UTF-8 text file relative to the server root",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {"type": "string"},
+                        "content": {"type": "string"},
+                    },
+                    "required": ["path", "content"],
+                },
+            },
+            {
+                "name": "fs/list",
+                "description": "List directory entries relative to the server root",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {"path": {"type": "string"}},
+                },
+            },
+        ])
+    }
+
+    fn tool_call(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let name = req.params.get("name").and_then(Value::as_str).unwrap_or("");
+        let args = req
+            .params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        let as_text =
+            |text: String| json!({"content": [{"type": "text", "text": text}]});
+        let result = match name {
+            "fs/read" => self.read_file(&args).map(as_text),
+            "fs/read_range" => self.read_range(&args),
+            "fs/write" => self.write_file(&args).map(as_text),
+            "fs/list" => self.list_dir(&args).map(as_text),
+            other => Err(format!("unknown tool: {other}")),
+        };
+        match result {
+            Ok(result) => Response::success(id, result),
+            Err(message) => Response::error(id, code::INTERNAL_ERROR, message),
+        }
+    }
+
+    fn read_file(&self, args: &Value) -> Result<String, String> {
+        let path = self.resolve(args.get("path").and_then(Value::as_str))?;
+        self.check_size(&path)?;
+        std::fs::read_to_string(&path).map_err(|e| format!("read {}: {e}", path.display()))
+    }
+
+    /// Reject a file over the read cap before any of it is loaded.
+    fn check_size(&self, path: &Path) -> Result<(), String> {
+        let size = std::fs::metadata(path)
+            .map_err(|e| format!("stat {}: {e}", path.display()))?
+            .len();
+        if size > self.max_read_bytes {
+            return Err(format!(
+                "{} is {size} bytes, over the {}-byte read cap; use fs/read_range",
+                path.display(),
+                self.max_read_bytes,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read a slice of a file without loading the rest: `offset` defaults to
+    /// the start, `length` to the read cap (its maximum). UTF-8 slices come
+    /// back as text, anything else as base64, alongside the total file size
+    /// so callers can page through.
+    fn read_range(&self, args: &Value) -> Result<Value, String> {
+        use std::io::{Read, Seek, SeekFrom};
+        let path = self.resolve(args.get("path").and_then(Value::as_str))?;
+        let offset = args.get("offset").and_then(Value::as_u64).unwrap_or(0);
+        let length = args
+            .get("length")
+            .and_then(Value::as_u64)
+            .unwrap_or(self.max_read_bytes);
+        if length > self.max_read_bytes {
+            return Err(format!(
+                "length {length} is over the {}-byte read cap",
+                self.max_read_bytes,
+            ));
+        }
+        let mut file =
+            std::fs::File::open(&path).map_err(|e| format!("open {}: {e}", path.display()))?;
+        let size = file
+            .metadata()
+            .map_err(|e| format!("stat {}: {e}", path.display()))?
+            .len();
+        file.seek(SeekFrom::Start(offset.min(size)))
+            .map_err(|e| format!("seek {}: {e}", path.display()))?;
+        let mut bytes = Vec::new();
+        file.take(length)
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("read {}: {e}", path.display()))?;
+        let (text, encoding) = match String::from_utf8(bytes) {
+            Ok(text) => (text, "utf-8"),
+            Err(raw) => (
+                base64::Engine::encode(
+                    &base64::engine::general_purpose::STANDARD,
+                    raw.into_bytes(),
+                ),
+                "base64",
+            ),
+        };
+        Ok(json!({
+            "content": [{"type": "text", "text": text}],
+            "size": size,
+            "offset": offset,
+            "encoding": encoding,
+        }))
+    }
+
+    fn write_file(&self, args: &Value) -> Result<String, String> {
+        let path = self.resolve(args.get("path").and_then(Value::as_str))?;
+        let content = args
+            .get("content")
+            .and_then(Value::as_str)
+            .ok_or("missing content")?;
+        std::fs::write(&path, content).map_err(|e| format!("write {}: {e}", path.display()))?;
+        Ok(format!("wrote {} bytes", content.len()))
+    }
+
+    fn list_dir(&self, args: &Value) -> Result<String, String> {
+        let path = self.resolve(Some(args.get("path").and_then(Value::as_str).unwrap_or(".")))?;
+        let mut names = Vec::new();
+        let entries = std::fs::read_dir(&path).map_err(|e| e.to_string())?;
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names.join("\n"))
+    }
+
+    /// Resolve a client-supplied relative path, refusing escapes from the root.
+    fn resolve(&self, path: Option<&str>) -> Result<PathBuf, String> {
+        let rel = path.ok_or("missing path")?;
+        let rel = Path::new(rel);
+        if rel.is_absolute() || rel.components().any(|c| c.as_os_str() == "..") {
+            return Err(format!("path escapes root: {}", rel.display()));
+        }
+        Ok(self.root.join(rel))
+    }
+
+    fn resources_list(&self, id: mcp_core::Id) -> Response {
+        let mut resources = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(&self.root) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_file() {
+                    resources.push(json!({
+                        "uri": format!("file://{}", path.display()),
+                        "name": entry.file_name().to_string_lossy(),
+                        "mimeType": "text/plain",
+                    }));
+                }
+            }
+        }
+        resources.sort_by(|a, b| a["uri"].as_str().cmp(&b["uri"].as_str()));
+        Response::success(id, json!({"resources": resources}))
+    }
+
+    /// Any file under the root is addressable, not just the top-level listing,
+    /// so expose that as a URI template.
+    fn resource_templates_list(&self, id: mcp_core::Id) -> Response {
+        Response::success(
+            id,
+            json!({
+                "resourceTemplates": [{
+                    "uriTemplate": format!("file://{}/{{path}}", self.root.display()),
+                    "name": "file",
+                    "description": "A file under the server root, by relative path",
+                    "mimeType": "text/plain",
+                }],
+            }),
+        )
+    }
+
+    /// Complete the `path` argument of the file template: split the typed
+    /// value into a directory prefix and a name fragment, then offer the
+    /// entries under that directory whose names start with the fragment.
+    /// Directories get a trailing `/` so clients can keep drilling down.
+    fn complete(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let value = req
+            .params
+            .get("argument")
+            .and_then(|arg| arg.get("value"))
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let (dir, fragment) = match value.rsplit_once('/') {
+            Some((dir, fragment)) => (dir, fragment),
+            None => ("", value),
+        };
+        let mut values = Vec::new();
+        // A value that escapes the root simply has no completions.
+        if let Ok(base) = self.resolve(Some(if dir.is_empty() { "." } else { dir })) {
+            if let Ok(entries) = std::fs::read_dir(&base) {
+                for entry in entries.flatten() {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if !name.starts_with(fragment) {
+                        continue;
+                    }
+                    let mut candidate = if dir.is_empty() {
+                        name
+                    } else {
+                        format!("{dir}/{name}")
+                    };
+                    if entry.path().is_dir() {
+                        candidate.push('/');
+                    }
+                    values.push(candidate);
+                }
+            }
+        }
+        values.sort();
+        let total = values.len();
+        values.truncate(100);
+        Response::success(
+            id,
+            json!({"completion": {"values": values, "total": total, "hasMore": total > 100}}),
+        )
+    }
+
+    fn resources_read(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let Some(uri) = req.params.get("uri").and_then(Value::as_str) else {
+            return Response::error(id, code::INVALID_PARAMS, "missing uri");
+        };
+        let Some(path) = uri.strip_prefix("file://") else {
+            return Response::error(id, code::INVALID_PARAMS, format!("unsupported uri: {uri}"));
+        };
+        if !Path::new(path).starts_with(&self.root) {
+            return Response::error(id, code::INVALID_PARAMS, "uri escapes root");
+        }
+        // Resources are whole-file reads, so the same cap applies.
+        if let Err(message) = self.check_size(Path::new(path)) {
+            return Response::error(id, code::INTERNAL_ERROR, message);
+        }
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                return Response::error(id, code::INTERNAL_ERROR, format!("read {path}: {err}"))
+            }
+        };
+        // Text comes back as `text`, anything else as a base64 `blob`.
+        let content = match String::from_utf8(bytes) {
+            Ok(text) => json!({
+                "uri": uri,
+                "mimeType": mime_for(Path::new(path)).unwrap_or("text/plain"),
+                "text": text,
+            }),
+            Err(raw) => json!({
+                "uri": uri,
+                "mimeType": mime_for(Path::new(path)).unwrap_or("application/octet-stream"),
+                "blob": base64::Engine::encode(
+                    &base64::engine::general_purpose::STANDARD,
+                    raw.into_bytes(),
+                ),
+            }),
+        };
+        Response::success(id, json!({"contents": [content]}))
+    }
+}
+
+/// Guess a MIME type from the file extension; `None` when the extension is
+/// unknown, so the caller can fall back on whether the bytes were UTF-8.
+fn mime_for(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()? {
+        "txt" | "log" => Some("text/plain"),
+        "md" => Some("text/markdown"),
+        "json" => Some("application/json"),
+        "html" | "htm" => Some("text/html"),
+        "css" => Some("text/css"),
+        "csv" => Some("text/csv"),
+        "xml" => Some("application/xml"),
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "pdf" => Some("application/pdf"),
+        "zip" => Some("application/zip"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_with(files: &[(&str, &[u8])]) -> (tempfile::TempDir, FsServer) {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        for (name, bytes) in files {
+            std::fs::write(dir.path().join(name), bytes).unwrap();
+        }
+        let root = dir.path().canonicalize().unwrap();
+        let server = FsServer {
+            root,
+            max_read_bytes: 10 * 1024 * 1024,
+        };
+        (dir, server)
+    }
+
+    fn read(server: &FsServer, uri: String) -> Value {
+        let resp = server.resources_read(Request::new("resources/read", json!({"uri": uri})));
+        resp.result.expect("successful read")["contents"][0].clone()
+    }
+
+    #[test]
+    fn text_files_come_back_as_text() {
+        let (_dir, server) = server_with(&[("notes.txt", b"hello")]);
+        let uri = format!("file://{}/notes.txt", server.root.display());
+        let content = read(&server, uri);
+        assert_eq!(content["mimeType"], "text/plain");
+        assert_eq!(content["text"], "hello");
+        assert!(content.get("blob").is_none());
+    }
+
+    #[test]
+    fn binary_files_come_back_as_base64_blobs() {
+        let bytes: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        let (_dir, server) = server_with(&[("pixel.png", bytes)]);
+        let uri = format!("file://{}/pixel.png", server.root.display());
+        let content = read(&server, uri);
+        assert_eq!(content["mimeType"], "image/png");
+        assert!(content.get("text").is_none());
+        let blob = content["blob"].as_str().unwrap();
+        let decoded =
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, blob).unwrap();
+        assert_eq!(decoded, bytes);
+    }
+
+    fn call(server: &FsServer, name: &str, args: Value) -> Response {
+        server.tool_call(Request::new(
+            "tools/call",
+            json!({"name": name, "arguments": args}),
+        ))
+    }
+
+    #[test]
+    fn ranges_come_out_of_large_files_without_loading_them() {
+        let big = "0123456789".repeat(1000);
+        let (_dir, mut server) = server_with(&[("big.txt", big.as_bytes())]);
+        server.max_read_bytes = 100;
+
+        let resp = call(
+            &server,
+            "fs/read_range",
+            json!({"path": "big.txt", "offset": 9995, "length": 10}),
+        );
+        let result = resp.result.expect("successful range read");
+        assert_eq!(result["content"][0]["text"], "56789");
+        assert_eq!(result["size"], 10_000);
+        assert_eq!(result["offset"], 9995);
+        assert_eq!(result["encoding"], "utf-8");
+    }
+
+    #[test]
+    fn files_over_the_cap_are_rejected_with_a_pointer_at_read_range() {
+        let (_dir, mut server) = server_with(&[("big.txt", [b'x'; 200].as_slice())]);
+        server.max_read_bytes = 100;
+
+        let resp = call(&server, "fs/read", json!({"path": "big.txt"}));
+        let error = resp.error.expect("read rejected");
+        assert!(error.message.contains("over the 100-byte read cap"), "{}", error.message);
+        assert!(error.message.contains("fs/read_range"), "{}", error.message);
+
+        // An explicit length over the cap is refused too.
+        let resp = call(
+            &server,
+            "fs/read_range",
+            json!({"path": "big.txt", "length": 500}),
+        );
+        let error = resp.error.expect("oversized length rejected");
+        assert!(error.message.contains("read cap"), "{}", error.message);
+    }
+}
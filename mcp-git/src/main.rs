@@ -0,0 +1,254 @@
+//! `mcp-git`: a read-only git MCP server scoped to one repository.
+//!
+//! Exposes `git/status`, `git/log`, `git/diff` and `git/show` tools over
+//! newline-delimited JSON-RPC on stdio, shelling out to the `git` binary with
+//! argument arrays (never a shell), so tool arguments cannot inject commands.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::Result;
+use clap::Parser;
+use mcp_core::rpc::{code, Request, Response};
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(name = "mcp-git", about = "Git MCP server")]
+struct Args {
+    /// Repository the server operates on.
+    #[arg(long, default_value = ".")]
+    repo: PathBuf,
+}
+
+struct GitServer {
+    repo: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let server = GitServer {
+        repo: args.repo.canonicalize()?,
+    };
+    mcp_core::stdio::serve_lines(|req| server.handle(req)).await
+}
+
+impl GitServer {
+    async fn handle(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        match req.method.as_str() {
+            "initialize" => Response::success(
+                id,
+                json!({
+                    "protocolVersion": mcp_core::PROTOCOL_VERSION,
+                    "serverInfo": {"name": "mcp-git", "version": env!("CARGO_PKG_VERSION")},
+                    "capabilities": {"tools": {}},
+                }),
+            ),
+            "tools/list" => Response::success(id, json!({"tools": self.tools()})),
+            "tools/call" => self.tool_call(req),
+            "prompts/list" => Response::success(id, json!({"prompts": []})),
+            "resources/list" => Response::success(id, json!({"resources": []})),
+            other => Response::error(
+                id,
+                code::METHOD_NOT_FOUND,
+                format!("unknown method: {other}"),
+            ),
+        }
+    }
+
+    fn tools(&self) -> Value {
+        json!([
+            {
+                "name": "git/status",
+                "description": "Working tree status in porcelain format",
+                "inputSchema": {"type": "object", "properties": {}},
+            },
+            {
+                "name": "git/log",
+                "description": "One-line commit log, newest first",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "ref": {"type": "string"},
+                        "max_count": {"type": "integer"},
+                    },
+                },
+            },
+            {
+                "name": "git/diff",
+                "description": "Diff of the working tree, or against a ref",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "ref": {"type": "string"},
+                        "path": {"type": "string"},
+                    },
+                },
+            },
+            {
+                "name": "git/show",
+                "description": "Show a commit (message and patch)",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {"ref": {"type": "string"}},
+                    "required": ["ref"],
+                },
+            },
+        ])
+    }
+
+    fn tool_call(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let name = req.params.get("name").and_then(Value::as_str).unwrap_or("");
+        let args = req
+            .params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        let result = match name {
+            "git/status" => self.status(),
+            "git/log" => self.log(&args),
+            "git/diff" => self.diff(&args),
+            "git/show" => self.show(&args),
+            other => Err(format!("unknown tool: {other}")),
+        };
+        match result {
+            Ok(text) => Response::success(
+                id,
+                json!({"content": [{"type": "text", "text": text}]}),
+            ),
+            Err(message) => Response::error(id, code::INTERNAL_ERROR, message),
+        }
+    }
+
+    fn status(&self) -> Result<String, String> {
+        self.git(&["status", "--porcelain"])
+    }
+
+    fn log(&self, args: &Value) -> Result<String, String> {
+        let max_count = args.get("max_count").and_then(Value::as_u64).unwrap_or(20);
+        let count = format!("-n{max_count}");
+        let mut argv = vec!["log", "--oneline", &count];
+        let reference;
+        if let Some(r) = args.get("ref").and_then(Value::as_str) {
+            reference = validated(r)?;
+            argv.push(reference);
+        }
+        argv.push("--");
+        self.git(&argv)
+    }
+
+    fn diff(&self, args: &Value) -> Result<String, String> {
+        let mut argv = vec!["diff"];
+        if let Some(r) = args.get("ref").and_then(Value::as_str) {
+            argv.push(validated(r)?);
+        }
+        argv.push("--");
+        if let Some(path) = args.get("path").and_then(Value::as_str) {
+            argv.push(validated(path)?);
+        }
+        self.git(&argv)
+    }
+
+    fn show(&self, args: &Value) -> Result<String, String> {
+        let reference = args
+            .get("ref")
+            .and_then(Value::as_str)
+            .ok_or("missing ref")?;
+        self.git(&["show", validated(reference)?, "--"])
+    }
+
+    /// Run git with a fixed argument array inside the repo and return stdout.
+    fn git(&self, argv: &[&str]) -> Result<String, String> {
+        let output = Command::new("git")
+            .args(argv)
+            .current_dir(&self.repo)
+            .output()
+            .map_err(|e| format!("running git: {e}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("git {}: {}", argv[0], stderr.trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Refuse ref/path arguments that git would parse as options (`--hard`,
+/// `-p`): the argument array already prevents shell injection, this closes
+/// off option injection too.
+fn validated(arg: &str) -> Result<&str, String> {
+    if arg.is_empty() || arg.starts_with('-') {
+        return Err(format!("invalid ref or path: {arg:?}"));
+    }
+    Ok(arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A repo with one commit of `a.txt` and an uncommitted `b.txt`.
+    fn temp_repo() -> (tempfile::TempDir, GitServer) {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let run = |argv: &[&str]| {
+            let status = Command::new("git")
+                .args(argv)
+                .current_dir(dir.path())
+                .env("GIT_AUTHOR_NAME", "test")
+                .env("GIT_AUTHOR_EMAIL", "test@example.com")
+                .env("GIT_COMMITTER_NAME", "test")
+                .env("GIT_COMMITTER_EMAIL", "test@example.com")
+                .status()
+                .expect("run git");
+            assert!(status.success(), "git {argv:?} failed");
+        };
+        run(&["init", "-q"]);
+        std::fs::write(dir.path().join("a.txt"), "hello\n").unwrap();
+        run(&["add", "a.txt"]);
+        run(&["commit", "-q", "-m", "add a.txt"]);
+        std::fs::write(dir.path().join("b.txt"), "new\n").unwrap();
+        let server = GitServer {
+            repo: dir.path().canonicalize().unwrap(),
+        };
+        (dir, server)
+    }
+
+    fn text(response: Response) -> String {
+        response.result.expect("tool result")["content"][0]["text"]
+            .as_str()
+            .expect("text content")
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn status_reports_untracked_files() {
+        let (_dir, server) = temp_repo();
+        let req = Request::new("tools/call", json!({"name": "git/status"}));
+        let out = text(server.handle(req).await);
+        assert!(out.contains("?? b.txt"), "status: {out:?}");
+    }
+
+    #[tokio::test]
+    async fn log_lists_commits() {
+        let (_dir, server) = temp_repo();
+        let req = Request::new(
+            "tools/call",
+            json!({"name": "git/log", "arguments": {"max_count": 5}}),
+        );
+        let out = text(server.handle(req).await);
+        assert!(out.contains("add a.txt"), "log: {out:?}");
+    }
+
+    #[tokio::test]
+    async fn option_lookalike_refs_are_rejected() {
+        let (_dir, server) = temp_repo();
+        let req = Request::new(
+            "tools/call",
+            json!({"name": "git/show", "arguments": {"ref": "--help"}}),
+        );
+        let response = server.handle(req).await;
+        let err = response.error.expect("rejected");
+        assert!(err.message.contains("invalid ref"), "{}", err.message);
+    }
+}
@@ -0,0 +1,180 @@
+//! `mcp-openai`: expose OpenAI chat completions as an `openai/chat` MCP tool.
+//!
+//! Reads newline-delimited JSON-RPC from stdin and answers on stdout. The API
+//! key comes from `OPENAI_API_KEY` (or `--api-key-env`).
+
+use anyhow::Result;
+use clap::Parser;
+use mcp_core::rpc::{code, Id, Request, Response};
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(name = "mcp-openai", about = "OpenAI chat MCP server")]
+struct Args {
+    /// Environment variable holding the API key.
+    #[arg(long, default_value = "OPENAI_API_KEY")]
+    api_key_env: String,
+    /// Chat completions endpoint.
+    #[arg(long, default_value = "https://api.openai.com/v1/chat/completions")]
+    endpoint: String,
+    /// Default model when the caller does not pass one.
+    #[arg(long, default_value = "gpt-4o-mini")]
+    model: String,
+}
+
+struct OpenAi {
+    args: Args,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let server = OpenAi {
+        api_key: std::env::var(&args.api_key_env).unwrap_or_default(),
+        client: reqwest::Client::new(),
+        args,
+    };
+    mcp_core::stdio::serve_lines(|req| server.handle(req)).await
+}
+
+impl OpenAi {
+    async fn handle(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        match req.method.as_str() {
+            "initialize" => Response::success(
+                id,
+                json!({
+                    "protocolVersion": mcp_core::PROTOCOL_VERSION,
+                    "serverInfo": {"name": "mcp-openai", "version": env!("CARGO_PKG_VERSION")},
+                    "capabilities": {"tools": {}},
+                }),
+            ),
+            "tools/list" => Response::success(
+                id,
+                json!({"tools": [{
+                    "name": "openai/chat",
+                    "description": "Send a chat completion request to OpenAI",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "prompt": {"type": "string"},
+                            "model": {"type": "string"},
+                        },
+                        "required": ["prompt"],
+                    },
+                }]}),
+            ),
+            "tools/call" => self.chat(req).await,
+            "prompts/list" => Response::success(id, json!({"prompts": []})),
+            "resources/list" => Response::success(id, json!({"resources": []})),
+            other => Response::error(
+                id,
+                code::METHOD_NOT_FOUND,
+                format!("unknown method: {other}"),
+            ),
+        }
+    }
+
+    async fn chat(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let name = req.params.get("name").and_then(Value::as_str).unwrap_or("");
+        if name != "openai/chat" {
+            return Response::error(id, code::METHOD_NOT_FOUND, format!("unknown tool: {name}"));
+        }
+        let Some(prompt) = req.params.pointer("/arguments/prompt").and_then(Value::as_str) else {
+            return Response::error(id, code::INVALID_PARAMS, "missing prompt");
+        };
+        let model = req
+            .params
+            .pointer("/arguments/model")
+            .and_then(Value::as_str)
+            .unwrap_or(&self.args.model);
+
+        let body = json!({
+            "model": model,
+            "messages": [{"role": "user", "content": prompt}],
+        });
+        let result = match self
+            .client
+            .post(&self.args.endpoint)
+            .bearer_auth(&self.api_key)
+            .json(&body)
+            .send()
+            .await
+        {
+            Ok(resp) => resp.json::<Value>().await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(json) => match classify_error(&id, &json) {
+                Some(error) => error,
+                None => Response::success(id, json!({"response": json})),
+            },
+            Err(err) => Response::error(id, code::INTERNAL_ERROR, format!("openai: {err}")),
+        }
+    }
+}
+
+/// Map an OpenAI error body (`{"error": {"type", "code", "message"}}`) to a
+/// JSON-RPC error, or `None` for a normal completion. The HTTP status is gone
+/// by the time the body is decoded, so the body is the only error signal —
+/// surfacing it as a real error lets the router's breaker and metrics see it.
+fn classify_error(id: &Id, body: &Value) -> Option<Response> {
+    let error = body.get("error").filter(|e| !e.is_null())?;
+    let kind = error
+        .get("type")
+        .and_then(Value::as_str)
+        .or_else(|| error.get("code").and_then(Value::as_str))
+        .unwrap_or("unknown");
+    let message = error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or("provider error");
+    let code = match kind {
+        k if k.contains("rate_limit") || k == "insufficient_quota" => code::QUOTA_EXCEEDED,
+        "invalid_request_error" => code::INVALID_PARAMS,
+        _ => code::UPSTREAM_ERROR,
+    };
+    Some(Response::error_with_data(
+        id.clone(),
+        code,
+        format!("openai: {message}"),
+        json!({"provider_error_type": kind}),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_bodies_become_quota_errors() {
+        let body = json!({"error": {
+            "type": "rate_limit_exceeded",
+            "message": "Rate limit reached for gpt-4o-mini",
+        }});
+        let response = classify_error(&Id::Int(1), &body).expect("classified as error");
+        let error = response.error.expect("error member");
+        assert_eq!(error.code, code::QUOTA_EXCEEDED);
+        assert!(error.message.contains("Rate limit reached"), "{}", error.message);
+        assert_eq!(error.data.unwrap()["provider_error_type"], "rate_limit_exceeded");
+    }
+
+    #[test]
+    fn invalid_request_bodies_become_invalid_params() {
+        let body = json!({"error": {
+            "type": "invalid_request_error",
+            "message": "you must provide a model parameter",
+        }});
+        let response = classify_error(&Id::Int(1), &body).expect("classified as error");
+        assert_eq!(response.error.unwrap().code, code::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn completions_pass_through() {
+        let body = json!({"choices": [{"message": {"content": "hi"}}]});
+        assert!(classify_error(&Id::Int(1), &body).is_none());
+    }
+}
@@ -0,0 +1,42 @@
+//! Several requests piped in one go must all be answered, in order: the
+//! stdin loop reads asynchronously and must not stall between frames.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// The `mcp-openai` binary built alongside this suite by
+/// `cargo test --workspace`.
+fn binary() -> PathBuf {
+    let mut path = std::env::current_exe().expect("test executable path");
+    path.pop();
+    path.pop();
+    path.push("mcp-openai");
+    path
+}
+
+#[test]
+fn piped_requests_all_get_answers() {
+    let mut child = Command::new(binary())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn mcp-openai");
+
+    let mut stdin = child.stdin.take().expect("child stdin");
+    writeln!(stdin, r#"{{"jsonrpc":"2.0","id":1,"method":"initialize"}}"#).unwrap();
+    writeln!(stdin, r#"{{"jsonrpc":"2.0","id":2,"method":"tools/list"}}"#).unwrap();
+    writeln!(stdin, r#"{{"jsonrpc":"2.0","id":3,"method":"prompts/list"}}"#).unwrap();
+    drop(stdin);
+
+    let stdout = BufReader::new(child.stdout.take().expect("child stdout"));
+    let ids: Vec<i64> = stdout
+        .lines()
+        .map(|line| {
+            let frame: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+            frame["id"].as_i64().expect("numeric id")
+        })
+        .collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert!(child.wait().unwrap().success());
+}
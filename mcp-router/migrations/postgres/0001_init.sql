@@ -0,0 +1,66 @@
+-- The full schema in one step: Postgres deployments start here, there is no
+-- pre-tenancy history to migrate. Types are chosen so the store's
+-- dialect-neutral SQL reads identically to SQLite: TEXT timestamps, BIGINT
+-- counters, and an `applied` flag compared as a number.
+CREATE TABLE users (
+    tenant_id  TEXT NOT NULL DEFAULT 'default',
+    user_id    TEXT NOT NULL,
+    name       TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL DEFAULT (now()::text),
+    active     BOOLEAN NOT NULL DEFAULT TRUE,
+    PRIMARY KEY (tenant_id, user_id)
+);
+
+CREATE TABLE subscriptions (
+    tenant_id     TEXT NOT NULL DEFAULT 'default',
+    user_id       TEXT NOT NULL,
+    tier          TEXT NOT NULL DEFAULT 'free',
+    max_tokens    BIGINT NOT NULL DEFAULT 0,
+    tokens_used   BIGINT NOT NULL DEFAULT 0,
+    max_requests  BIGINT NOT NULL DEFAULT 0,
+    requests_used BIGINT NOT NULL DEFAULT 0,
+    reset_at      TEXT,
+    PRIMARY KEY (tenant_id, user_id)
+);
+
+CREATE TABLE usage_log (
+    id         BIGSERIAL PRIMARY KEY,
+    tenant_id  TEXT NOT NULL DEFAULT 'default',
+    user_id    TEXT NOT NULL,
+    tool       TEXT NOT NULL,
+    tokens     BIGINT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (now()::text)
+);
+
+CREATE TABLE usage_outbox (
+    id               BIGSERIAL PRIMARY KEY,
+    tenant_id        TEXT NOT NULL DEFAULT 'default',
+    user_id          TEXT NOT NULL,
+    tool             TEXT NOT NULL,
+    actual_tokens    BIGINT NOT NULL,
+    estimated_tokens BIGINT NOT NULL,
+    applied          BIGINT NOT NULL DEFAULT 0,
+    created_at       TEXT NOT NULL DEFAULT (now()::text)
+);
+
+CREATE INDEX usage_outbox_pending ON usage_outbox (id) WHERE applied = 0;
+
+CREATE TABLE providers (
+    tenant_id   TEXT NOT NULL DEFAULT 'default',
+    slug        TEXT NOT NULL,
+    kind        TEXT NOT NULL,
+    api_key_enc TEXT,
+    metadata    TEXT,
+    created_at  TEXT NOT NULL DEFAULT (now()::text),
+    PRIMARY KEY (tenant_id, slug)
+);
+
+CREATE TABLE api_tokens (
+    id         TEXT PRIMARY KEY,
+    tenant_id  TEXT NOT NULL DEFAULT 'default',
+    user_id    TEXT NOT NULL,
+    token_hash TEXT NOT NULL,
+    scopes     TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL DEFAULT (now()::text),
+    revoked_at TEXT
+);
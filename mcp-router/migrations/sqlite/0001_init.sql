@@ -0,0 +1,39 @@
+CREATE TABLE users (
+    user_id    TEXT PRIMARY KEY,
+    name       TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TABLE subscriptions (
+    user_id       TEXT PRIMARY KEY REFERENCES users (user_id),
+    tier          TEXT NOT NULL DEFAULT 'free',
+    max_tokens    INTEGER NOT NULL DEFAULT 0,
+    tokens_used   INTEGER NOT NULL DEFAULT 0,
+    max_requests  INTEGER NOT NULL DEFAULT 0,
+    requests_used INTEGER NOT NULL DEFAULT 0,
+    reset_at      TEXT
+);
+
+CREATE TABLE usage_log (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id    TEXT NOT NULL,
+    tool       TEXT NOT NULL,
+    tokens     INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TABLE providers (
+    slug        TEXT PRIMARY KEY,
+    kind        TEXT NOT NULL,
+    api_key_enc TEXT,
+    metadata    TEXT,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TABLE api_tokens (
+    id         TEXT PRIMARY KEY,
+    user_id    TEXT NOT NULL,
+    token_hash TEXT NOT NULL,
+    scopes     TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
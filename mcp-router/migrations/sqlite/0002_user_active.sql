@@ -0,0 +1,3 @@
+-- Soft-delete for users: deactivated accounts keep their data but are
+-- rejected at enforcement time and hidden from the default listing.
+ALTER TABLE users ADD COLUMN active INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,15 @@
+-- Durable outbox for usage accounting: a pending row is written before the
+-- subscription is touched, then claimed and applied in one transaction. Rows
+-- left pending by a crash or DB hiccup are swept by the reconciler, so usage
+-- is neither lost nor double-counted.
+CREATE TABLE usage_outbox (
+    id               INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id          TEXT NOT NULL,
+    tool             TEXT NOT NULL,
+    actual_tokens    INTEGER NOT NULL,
+    estimated_tokens INTEGER NOT NULL,
+    applied          INTEGER NOT NULL DEFAULT 0,
+    created_at       TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX usage_outbox_pending ON usage_outbox (id) WHERE applied = 0;
@@ -0,0 +1,3 @@
+-- Soft-delete for API tokens: a revoked token keeps its row for audit but no
+-- longer authenticates and is hidden from the default listing.
+ALTER TABLE api_tokens ADD COLUMN revoked_at TEXT;
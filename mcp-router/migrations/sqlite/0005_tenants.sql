@@ -0,0 +1,54 @@
+-- Logical multi-tenant isolation: every core row carries a tenant_id and all
+-- store queries are scoped to one tenant. Existing rows land in the 'default'
+-- tenant. The user and provider keys are rebuilt to be unique per tenant, so
+-- two tenants can each have their own 'alice' or 'openai'.
+CREATE TABLE users_new (
+    tenant_id  TEXT NOT NULL DEFAULT 'default',
+    user_id    TEXT NOT NULL,
+    name       TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    active     INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (tenant_id, user_id)
+);
+INSERT INTO users_new (tenant_id, user_id, name, created_at, active)
+    SELECT 'default', user_id, name, created_at, active FROM users;
+DROP TABLE users;
+ALTER TABLE users_new RENAME TO users;
+
+CREATE TABLE subscriptions_new (
+    tenant_id     TEXT NOT NULL DEFAULT 'default',
+    user_id       TEXT NOT NULL,
+    tier          TEXT NOT NULL DEFAULT 'free',
+    max_tokens    INTEGER NOT NULL DEFAULT 0,
+    tokens_used   INTEGER NOT NULL DEFAULT 0,
+    max_requests  INTEGER NOT NULL DEFAULT 0,
+    requests_used INTEGER NOT NULL DEFAULT 0,
+    reset_at      TEXT,
+    PRIMARY KEY (tenant_id, user_id)
+);
+INSERT INTO subscriptions_new
+        (tenant_id, user_id, tier, max_tokens, tokens_used, max_requests, requests_used, reset_at)
+    SELECT 'default', user_id, tier, max_tokens, tokens_used, max_requests, requests_used, reset_at
+    FROM subscriptions;
+DROP TABLE subscriptions;
+ALTER TABLE subscriptions_new RENAME TO subscriptions;
+
+CREATE TABLE providers_new (
+    tenant_id   TEXT NOT NULL DEFAULT 'default',
+    slug        TEXT NOT NULL,
+    kind        TEXT NOT NULL,
+    api_key_enc TEXT,
+    metadata    TEXT,
+    created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (tenant_id, slug)
+);
+INSERT INTO providers_new (tenant_id, slug, kind, api_key_enc, metadata, created_at)
+    SELECT 'default', slug, kind, api_key_enc, metadata, created_at FROM providers;
+DROP TABLE providers;
+ALTER TABLE providers_new RENAME TO providers;
+
+-- Token ids and hashes are globally unique already; the outbox and log only
+-- need the column so accounting lands on the right tenant's subscription.
+ALTER TABLE api_tokens ADD COLUMN tenant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE usage_outbox ADD COLUMN tenant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE usage_log ADD COLUMN tenant_id TEXT NOT NULL DEFAULT 'default';
@@ -0,0 +1,12 @@
+-- Rolling per-day request cap: the limit lives on the subscription, the
+-- counter in usage_counters keyed by UTC day number. Rows for past days are
+-- swept opportunistically on the next consume.
+ALTER TABLE subscriptions ADD COLUMN max_requests_per_day INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE usage_counters (
+    tenant_id TEXT NOT NULL DEFAULT 'default',
+    user_id   TEXT NOT NULL,
+    day       INTEGER NOT NULL,
+    requests  INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (tenant_id, user_id, day)
+);
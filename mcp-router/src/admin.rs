@@ -0,0 +1,599 @@
+//! The `/api` admin surface: upstream, user, subscription, provider and token
+//! management. Every route requires the configured bearer token.
+
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::{BearerToken, Tenant};
+use crate::config::UpstreamConfig;
+use crate::router::RouterState;
+use crate::store::{
+    tier_quotas, validate_slug, ProviderStore, SubscriptionRecord, SubscriptionStore,
+};
+use crate::upstream::UpstreamError;
+use mcp_core::rpc::Request;
+
+/// An admin API failure: status code plus a JSON `{"error": ...}` body.
+pub struct ApiError(pub StatusCode, pub String);
+
+impl ApiError {
+    pub fn not_found(what: impl Into<String>) -> Self {
+        ApiError(StatusCode::NOT_FOUND, what.into())
+    }
+
+    pub fn bad_request(what: impl Into<String>) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, what.into())
+    }
+
+    pub fn unavailable(what: impl Into<String>) -> Self {
+        ApiError(StatusCode::SERVICE_UNAVAILABLE, what.into())
+    }
+}
+
+/// The subscription store scoped to the caller's tenant, or 503 when the
+/// router runs without persistence.
+fn store(state: &RouterState, tenant: &Tenant) -> Result<SubscriptionStore, ApiError> {
+    Ok(state
+        .store
+        .as_ref()
+        .ok_or_else(|| ApiError::unavailable("persistence is disabled"))?
+        .for_tenant(&tenant.0))
+}
+
+/// The provider store scoped to the caller's tenant, or 503 when the router
+/// runs without persistence.
+fn providers(state: &RouterState, tenant: &Tenant) -> Result<ProviderStore, ApiError> {
+    Ok(state
+        .providers
+        .as_ref()
+        .ok_or_else(|| ApiError::unavailable("persistence is disabled"))?
+        .for_tenant(&tenant.0))
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({"error": self.1}))).into_response()
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+
+pub fn routes() -> Router<Arc<RouterState>> {
+    Router::new()
+        .route("/upstreams", get(list_upstreams).post(create_upstream))
+        .route("/upstreams/{name}", axum::routing::delete(delete_upstream))
+        .route("/upstreams/{name}/call", post(call_upstream))
+        .route("/upstreams/{name}/tools", get(list_upstream_tools))
+        .route("/users", get(list_users).post(create_user))
+        .route("/users/{user_id}/deactivate", post(deactivate_user))
+        .route("/subscriptions", post(upsert_subscription))
+        .route("/subscriptions/bulk", post(bulk_upsert_subscriptions))
+        .route("/subscriptions/{user_id}", get(get_subscription))
+        .route("/providers", get(list_providers).post(put_provider))
+        .route("/maintenance", post(set_maintenance))
+        .route("/providers/reload", post(reload_providers))
+        .route("/providers/{slug}", axum::routing::delete(delete_provider))
+        .route("/providers/{slug}/test", post(test_provider))
+        .route("/tokens", get(list_tokens).post(issue_token))
+        .route("/tokens/{id}", axum::routing::delete(revoke_token))
+        .route("/calls/recent", get(recent_calls))
+        .route("/config", get(get_config))
+}
+
+#[derive(Deserialize)]
+struct RecentQuery {
+    limit: Option<usize>,
+}
+
+/// `GET /api/calls/recent?limit=N`: the most recent `tools/call` invocations,
+/// newest first. Arguments are withheld unless `recent_calls_arguments` is
+/// enabled, and even then string values arrive masked.
+async fn recent_calls(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    Query(query): Query<RecentQuery>,
+) -> Json<Value> {
+    Json(json!({"calls": state.recent_calls(query.limit.unwrap_or(50))}))
+}
+
+/// `GET /api/config`: the effective running config — after env interpolation,
+/// defaults, and any `--no-persistence` override — with credentials redacted
+/// (see [`crate::config::Config::redacted`]).
+async fn get_config(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+) -> Json<crate::config::Config> {
+    Json(state.config.redacted())
+}
+
+async fn list_upstreams(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+) -> Json<Value> {
+    let mut upstreams: Vec<Value> = state
+        .registry
+        .handles()
+        .into_iter()
+        .map(|handle| {
+            let health = handle.health();
+            json!({
+                "name": handle.name,
+                "kind": handle.kind,
+                "config": handle.describe(),
+                "circuit_open": handle.breaker.is_open(),
+                "stats": handle.stats.summary(),
+                "health": {
+                    "status": match health.healthy {
+                        None => "unknown",
+                        Some(true) => "healthy",
+                        Some(false) => "unhealthy",
+                    },
+                    "last_check_secs_ago": health.checked_at.map(|t| t.elapsed().as_secs()),
+                },
+            })
+        })
+        .collect();
+    upstreams.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    Json(json!({"upstreams": upstreams}))
+}
+
+async fn create_upstream(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    Json(cfg): Json<UpstreamConfig>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .registry
+        .register_config(&cfg)
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+    state.invalidate_tools_cache(&cfg.name).await;
+    Ok(StatusCode::CREATED)
+}
+
+async fn delete_upstream(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if !state.registry.remove(&name) {
+        return Err(ApiError::not_found(format!("unknown upstream: {name}")));
+    }
+    state.invalidate_tools_cache(&name).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct RawCall {
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// `POST /api/upstreams/{name}/call`: send a raw JSON-RPC request to one
+/// upstream, bypassing aggregation and namespacing. For troubleshooting an
+/// upstream before exposing its tools. Upstream-side JSON-RPC errors are
+/// forwarded verbatim in the response body.
+async fn call_upstream(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    Path(name): Path<String>,
+    Json(body): Json<RawCall>,
+) -> Result<Json<Value>, ApiError> {
+    match state
+        .registry
+        .call(&name, Request::new(body.method, body.params))
+        .await
+    {
+        Ok(response) => Ok(Json(
+            serde_json::to_value(response).expect("serialize response"),
+        )),
+        Err(UpstreamError::Unknown(name)) => {
+            Err(ApiError::not_found(format!("unknown upstream: {name}")))
+        }
+        Err(err) => Err(ApiError(StatusCode::BAD_GATEWAY, err.to_string())),
+    }
+}
+
+/// `GET /api/upstreams/{name}/tools`: what one upstream exposes, with the raw
+/// un-namespaced tool names — handy for writing `allow_tools` lists. Goes
+/// through the registry so the usual timeout and breaker apply.
+async fn list_upstream_tools(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    match state
+        .registry
+        .call(&name, Request::new("tools/list", json!({})))
+        .await
+    {
+        Ok(response) => match response.result {
+            Some(result) => Ok(Json(result)),
+            None => Err(ApiError(
+                StatusCode::BAD_GATEWAY,
+                response
+                    .error
+                    .map(|err| err.message)
+                    .unwrap_or_else(|| "empty response".into()),
+            )),
+        },
+        Err(UpstreamError::Unknown(name)) => {
+            Err(ApiError::not_found(format!("unknown upstream: {name}")))
+        }
+        Err(err) => Err(ApiError(StatusCode::BAD_GATEWAY, err.to_string())),
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateUser {
+    user_id: String,
+    #[serde(default)]
+    name: String,
+}
+
+async fn create_user(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Json(body): Json<CreateUser>,
+) -> Result<StatusCode, ApiError> {
+    store(&state, &tenant)?.create_user(&body.user_id, &body.name).await?;
+    Ok(StatusCode::CREATED)
+}
+
+#[derive(Deserialize)]
+struct ListUsersQuery {
+    #[serde(default)]
+    include_inactive: bool,
+}
+
+async fn list_users(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Query(query): Query<ListUsersQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let users = store(&state, &tenant)?.list_users(query.include_inactive).await?;
+    Ok(Json(json!({"users": users})))
+}
+
+async fn deactivate_user(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Path(user_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if !store(&state, &tenant)?.deactivate_user(&user_id).await? {
+        return Err(ApiError::not_found(format!("unknown user: {user_id}")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct SubscriptionRequest {
+    user_id: String,
+    tier: String,
+    max_tokens: Option<i64>,
+    max_requests: Option<i64>,
+    max_requests_per_day: Option<i64>,
+    reset_at: Option<String>,
+}
+
+async fn upsert_subscription(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Json(body): Json<SubscriptionRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let defaults = tier_quotas(&body.tier);
+    let (max_tokens, max_requests) = match (body.max_tokens, body.max_requests) {
+        (Some(tokens), Some(requests)) => (tokens, requests),
+        _ => defaults.ok_or_else(|| {
+            ApiError::bad_request(format!(
+                "unknown tier {} and no explicit quotas given",
+                body.tier
+            ))
+        })?,
+    };
+    store(&state, &tenant)?.ensure_user(&body.user_id).await?;
+    let record = SubscriptionRecord {
+        user_id: body.user_id,
+        tier: body.tier,
+        max_tokens,
+        tokens_used: 0,
+        max_requests,
+        requests_used: 0,
+        max_requests_per_day: body.max_requests_per_day.unwrap_or(0),
+        reset_at: body.reset_at,
+    };
+    store(&state, &tenant)?.upsert_subscription(&record).await?;
+    Ok(Json(serde_json::to_value(&record).expect("serialize record")))
+}
+
+#[derive(Deserialize)]
+struct BulkSubscriptionRequest {
+    user_ids: Vec<String>,
+    tier: String,
+    max_tokens: Option<i64>,
+    max_requests: Option<i64>,
+    max_requests_per_day: Option<i64>,
+    reset_at: Option<String>,
+}
+
+/// `POST /api/subscriptions/bulk`: give a cohort of existing users the same
+/// tier and quotas in one transaction. Unlike the single-user endpoint this
+/// does not create missing users — they come back as `"unknown user"` in the
+/// per-user results so a typo in one id can't silently mint an account.
+async fn bulk_upsert_subscriptions(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Json(body): Json<BulkSubscriptionRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let defaults = tier_quotas(&body.tier);
+    let (max_tokens, max_requests) = match (body.max_tokens, body.max_requests) {
+        (Some(tokens), Some(requests)) => (tokens, requests),
+        _ => defaults.ok_or_else(|| {
+            ApiError::bad_request(format!(
+                "unknown tier {} and no explicit quotas given",
+                body.tier
+            ))
+        })?,
+    };
+    let records: Vec<SubscriptionRecord> = body
+        .user_ids
+        .iter()
+        .map(|user_id| SubscriptionRecord {
+            user_id: user_id.clone(),
+            tier: body.tier.clone(),
+            max_tokens,
+            tokens_used: 0,
+            max_requests,
+            requests_used: 0,
+            max_requests_per_day: body.max_requests_per_day.unwrap_or(0),
+            reset_at: body.reset_at.clone(),
+        })
+        .collect();
+    let applied = store(&state, &tenant)?.bulk_upsert_subscriptions(&records).await?;
+    let results: Vec<Value> = records
+        .iter()
+        .zip(&applied)
+        .map(|(record, ok)| match ok {
+            true => json!({"user_id": record.user_id, "status": "ok"}),
+            false => json!({"user_id": record.user_id, "status": "unknown user"}),
+        })
+        .collect();
+    Ok(Json(json!({"results": results})))
+}
+
+async fn get_subscription(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Path(user_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let record = store(&state, &tenant)?
+        .get_subscription(&user_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("no subscription for {user_id}")))?;
+    Ok(Json(serde_json::to_value(&record).expect("serialize record")))
+}
+
+#[derive(Deserialize)]
+pub struct ProviderRequest {
+    pub slug: String,
+    pub kind: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    /// Reject metadata keys outside the known schema instead of storing them.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// Check provider metadata against the known schema: `rate_limit_rpm`
+/// (integer), `default_model`, `region` and `endpoint` (strings). Known keys
+/// are always type-checked; unknown keys are only rejected under `strict`, so
+/// existing callers with free-form metadata keep working.
+fn validate_provider_metadata(metadata: &Value, strict: bool) -> Result<(), String> {
+    let Some(object) = metadata.as_object() else {
+        return Err("metadata must be an object".into());
+    };
+    for (key, value) in object {
+        match key.as_str() {
+            "rate_limit_rpm" if !value.is_u64() => {
+                return Err(format!("metadata.{key} must be a non-negative integer"));
+            }
+            "default_model" | "region" | "endpoint" if !value.is_string() => {
+                return Err(format!("metadata.{key} must be a string"));
+            }
+            "rate_limit_rpm" | "default_model" | "region" | "endpoint" => {}
+            other if strict => {
+                return Err(format!("unknown metadata key: {other}"));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+async fn put_provider(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Json(body): Json<ProviderRequest>,
+) -> Result<StatusCode, ApiError> {
+    validate_slug(&body.slug).map_err(ApiError::bad_request)?;
+    if let Some(metadata) = &body.metadata {
+        validate_provider_metadata(metadata, body.strict).map_err(ApiError::bad_request)?;
+    }
+    providers(&state, &tenant)?
+        .put_provider(
+            &body.slug,
+            &body.kind,
+            body.api_key.as_deref(),
+            body.metadata.as_ref(),
+        )
+        .await?;
+    Ok(StatusCode::CREATED)
+}
+
+async fn list_providers(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+) -> Result<Json<Value>, ApiError> {
+    let providers = providers(&state, &tenant)?.list_providers().await?;
+    Ok(Json(json!({"providers": providers})))
+}
+
+#[derive(Deserialize)]
+struct MaintenanceRequest {
+    enabled: bool,
+}
+
+/// Flip maintenance mode: while on, upstream-forwarding methods on `/mcp`
+/// answer `-32000` but health, metrics, listings and this API keep working.
+async fn set_maintenance(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    Json(body): Json<MaintenanceRequest>,
+) -> Json<Value> {
+    state.set_maintenance(body.enabled);
+    tracing::info!(enabled = body.enabled, "maintenance mode toggled");
+    Json(json!({"maintenance": body.enabled}))
+}
+
+/// Re-apply the `[[providers]]` seeds from the loaded config, picking up any
+/// changed API key environment variables.
+async fn reload_providers(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+) -> Result<Json<Value>, ApiError> {
+    if state.providers.is_none() {
+        return Err(ApiError::unavailable("persistence is disabled"));
+    }
+    let seeded = state.seed_providers().await?;
+    Ok(Json(json!({"seeded": seeded})))
+}
+
+/// `POST /api/providers/{slug}/test`: verify a stored key works before
+/// relying on it, by making the smallest authenticated call the provider
+/// `kind` supports (listing models). `metadata.endpoint` overrides the probe
+/// URL. The response carries only the outcome and HTTP status — never the
+/// key.
+async fn test_provider(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Path(slug): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let record = providers(&state, &tenant)?
+        .get_provider(&slug)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("unknown provider: {slug}")))?;
+    let key = ProviderStore::decrypt_key(&record)
+        .ok_or_else(|| ApiError::bad_request("provider has no decryptable API key"))?;
+    let endpoint = record
+        .metadata
+        .as_deref()
+        .and_then(|m| serde_json::from_str::<Value>(m).ok())
+        .and_then(|m| m.get("endpoint").and_then(Value::as_str).map(str::to_string));
+    let client = reqwest::Client::new();
+    let probe = match record.kind.as_str() {
+        "openai" => client
+            .get(endpoint.unwrap_or_else(|| "https://api.openai.com/v1/models".into()))
+            .bearer_auth(&key),
+        "claude" | "anthropic" => client
+            .get(endpoint.unwrap_or_else(|| "https://api.anthropic.com/v1/models".into()))
+            .header("x-api-key", &key)
+            .header("anthropic-version", "2023-06-01"),
+        other => {
+            return Err(ApiError::bad_request(format!(
+                "don't know how to test provider kind {other:?}"
+            )))
+        }
+    };
+    match probe
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+    {
+        Ok(resp) => Ok(Json(json!({
+            "ok": resp.status().is_success(),
+            "status": resp.status().as_u16(),
+        }))),
+        // `without_url` so a copied-in endpoint with embedded credentials is
+        // not echoed back.
+        Err(err) => Ok(Json(json!({
+            "ok": false,
+            "error": err.without_url().to_string(),
+        }))),
+    }
+}
+
+async fn delete_provider(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Path(slug): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if !providers(&state, &tenant)?.delete_provider(&slug).await? {
+        return Err(ApiError::not_found(format!("unknown provider: {slug}")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct IssueToken {
+    user_id: String,
+    #[serde(default)]
+    scopes: String,
+}
+
+async fn issue_token(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Json(body): Json<IssueToken>,
+) -> Result<Json<Value>, ApiError> {
+    store(&state, &tenant)?.ensure_user(&body.user_id).await?;
+    let (id, secret) = store(&state, &tenant)?.issue_token(&body.user_id, &body.scopes).await?;
+    Ok(Json(json!({"id": id, "token": secret})))
+}
+
+async fn list_tokens(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+) -> Result<Json<Value>, ApiError> {
+    let tokens = store(&state, &tenant)?.list_tokens().await?;
+    Ok(Json(json!({"tokens": tokens})))
+}
+
+/// `DELETE /api/tokens/{id}`: revoke a token immediately. The row is kept
+/// (soft-deleted) for audit, but the token stops authenticating and drops out
+/// of listings.
+async fn revoke_token(
+    State(state): State<Arc<RouterState>>,
+    _auth: BearerToken,
+    tenant: Tenant,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if !store(&state, &tenant)?.revoke_token(&id).await? {
+        return Err(ApiError::not_found(format!("unknown token: {id}")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,159 @@
+//! Bearer-token authentication for `/mcp` and the admin API.
+
+use std::sync::Arc;
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+use crate::router::RouterState;
+
+/// Extractor that enforces bearer auth on `/mcp` and the admin API. The
+/// configured static `bearer` always passes; with persistence on, a
+/// database-issued API token (see `POST /api/tokens`) passes too and carries
+/// its scopes. When no static token is configured the router is open and
+/// extraction always succeeds.
+pub struct BearerToken {
+    /// Scopes of the presented database-issued token. `None` for the static
+    /// bearer and for an open router — both are unrestricted.
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// No `Authorization` header was sent.
+    Missing,
+    /// The `Authorization` header is not a `Bearer` credential.
+    Malformed,
+    /// A token was sent but does not match.
+    Invalid,
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        // RFC 6750 §3: a bare challenge when no credentials were sent, an
+        // `error` attribute describing what was wrong with the ones that were.
+        let challenge = match self {
+            AuthError::Missing => r#"Bearer realm="mcp-router""#,
+            AuthError::Malformed => r#"Bearer realm="mcp-router", error="invalid_request""#,
+            AuthError::Invalid => r#"Bearer realm="mcp-router", error="invalid_token""#,
+        };
+        (
+            StatusCode::UNAUTHORIZED,
+            [(axum::http::header::WWW_AUTHENTICATE, challenge)],
+            "unauthorized",
+        )
+            .into_response()
+    }
+}
+
+impl FromRequestParts<Arc<RouterState>> for BearerToken {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<RouterState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(expected) = state.config.server.bearer.as_deref() else {
+            return Ok(BearerToken { scopes: None });
+        };
+        match check_bearer(parts, expected) {
+            Ok(()) => Ok(BearerToken { scopes: None }),
+            // Not the static bearer: maybe a database-issued token.
+            Err(AuthError::Invalid) => {
+                let presented = bearer_credential(parts).ok_or(AuthError::Invalid)?;
+                let store = state.store.as_ref().ok_or(AuthError::Invalid)?;
+                let scopes = store
+                    .token_scopes(presented)
+                    .await
+                    .map_err(|err| {
+                        tracing::warn!(%err, "token lookup failed");
+                        AuthError::Invalid
+                    })?
+                    .ok_or(AuthError::Invalid)?;
+                Ok(BearerToken {
+                    scopes: Some(parse_scopes(&scopes)),
+                })
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Split a stored scopes string (`"read tools"` or `"read,tools"`) into its
+/// individual scopes.
+fn parse_scopes(scopes: &str) -> Vec<String> {
+    scopes
+        .split([' ', ','])
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// The tenant an admin request operates in, from the `x-mcp-tenant` header.
+/// Absent (or unreadable) means the default tenant, so single-tenant
+/// deployments never need to send it.
+pub struct Tenant(pub String);
+
+impl<S: Send + Sync> FromRequestParts<S> for Tenant {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let tenant = parts
+            .headers
+            .get("x-mcp-tenant")
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .unwrap_or(crate::store::DEFAULT_TENANT);
+        Ok(Tenant(tenant.to_string()))
+    }
+}
+
+/// Extractor guarding `/metrics`. A no-op unless `metrics_require_auth` is
+/// set, in which case `metrics_bearer` (falling back to the main `bearer`)
+/// must be presented; requiring auth with no token configured fails closed.
+pub struct MetricsToken;
+
+impl FromRequestParts<Arc<RouterState>> for MetricsToken {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<RouterState>,
+    ) -> Result<Self, Self::Rejection> {
+        let server = &state.config.server;
+        if !server.metrics_require_auth {
+            return Ok(MetricsToken);
+        }
+        let expected = server
+            .metrics_bearer
+            .as_deref()
+            .or(server.bearer.as_deref())
+            .ok_or(AuthError::Invalid)?;
+        check_bearer(parts, expected)?;
+        Ok(MetricsToken)
+    }
+}
+
+fn check_bearer(parts: &Parts, expected: &str) -> Result<(), AuthError> {
+    let header = parts
+        .headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(AuthError::Missing)?;
+    match header.strip_prefix("Bearer ") {
+        Some(token) if token == expected => Ok(()),
+        Some(_) => Err(AuthError::Invalid),
+        None => Err(AuthError::Malformed),
+    }
+}
+
+/// The raw `Bearer` credential from the request, if one was sent.
+fn bearer_credential(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())?
+        .strip_prefix("Bearer ")
+}
@@ -0,0 +1,657 @@
+//! TOML configuration with `${VAR}` environment interpolation.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::transform::TransformRule;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamConfig>,
+    /// Providers seeded into the provider store (API-key-backed backends).
+    #[serde(default)]
+    pub providers: Vec<ProviderSeed>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address the HTTP listener binds to. A `unix:` prefix
+    /// (`unix:/run/mcp-router.sock`) binds a Unix domain socket instead of
+    /// TCP, for sidecar deployments.
+    pub bind: String,
+    /// Static bearer token protecting `/mcp` and the admin API. Unset means
+    /// no authentication (local development).
+    pub bearer: Option<String>,
+    /// Origins allowed for CORS: exact origins, a bare `*` allowing any, or
+    /// single-wildcard patterns like `https://*.example.com`.
+    pub allow_origins: Vec<String>,
+    /// Require bearer auth on `/metrics`. Off by default so a plain
+    /// Prometheus scrape keeps working.
+    pub metrics_require_auth: bool,
+    /// Token accepted on `/metrics` when [`Self::metrics_require_auth`] is
+    /// set. Falls back to `bearer` when unset.
+    pub metrics_bearer: Option<String>,
+    /// Speak MCP on stdin/stdout instead of binding the HTTP listener, for
+    /// embedding the router as a child of another MCP client.
+    pub stdio_server: bool,
+    /// How long aggregated upstream catalogs are cached, in seconds.
+    pub cache_ttl_secs: u64,
+    /// How long `resources/read` results are cached, in seconds. Zero
+    /// disables the content cache.
+    pub resource_cache_ttl_secs: u64,
+    /// Most entries the `resources/read` cache holds; the least recently
+    /// used entry is evicted beyond this.
+    pub resource_cache_max_entries: usize,
+    /// Per-upstream call timeout, in seconds.
+    pub request_timeout_secs: u64,
+    /// Most router-to-router hops a request may take before it is rejected
+    /// with `-32004 loop detected`. Raise this in deep mesh deployments.
+    pub max_hops: u64,
+    /// How often the background liveness checker probes each upstream, in
+    /// seconds. Zero disables the checker.
+    pub health_check_interval_secs: u64,
+    /// Most upstreams the registry accepts, counting config and admin-API
+    /// registrations alike; each stdio upstream is a child process, so this
+    /// bounds how many a runaway script can spawn. Zero means unlimited.
+    pub max_upstreams: usize,
+    /// Most JSON-RPC requests processed at once; arrivals beyond it wait
+    /// briefly for a slot and are then rejected with `-32006 server busy`,
+    /// bounding upstream fan-out under a spike. Defaults to 64 per CPU;
+    /// zero disables the cap.
+    pub max_concurrent_requests: usize,
+    /// Entries of one `tools/call_batch` dispatched concurrently; zero means
+    /// the whole batch at once.
+    pub batch_parallelism: usize,
+    /// Largest request body accepted on `/mcp`, in bytes. Also caps the
+    /// serialized `arguments` of a single `tools/call`.
+    pub max_request_bytes: usize,
+    /// Largest serialized `tools/call` result handed back to clients, in
+    /// bytes. Zero disables the cap.
+    pub max_result_bytes: usize,
+    /// What to do with a result over `max_result_bytes`: shorten its string
+    /// fields and mark `_meta.truncated`, or reject the call with `-32005
+    /// result too large`.
+    pub oversize_policy: OversizePolicy,
+    /// How many recent `tools/call`s to keep in memory for
+    /// `GET /api/calls/recent`. Zero disables the buffer.
+    pub recent_calls: usize,
+    /// Also keep each recent call's arguments, with string values masked.
+    /// Off by default so user content never sits in router memory.
+    pub recent_calls_arguments: bool,
+    /// Warn-log any dispatch slower than this many milliseconds, with the
+    /// method, upstream, user and latency. Fast calls stay quiet, so latency
+    /// outliers surface without full request logging. Zero disables it.
+    pub slow_call_threshold_ms: u64,
+    /// Separator between the upstream name and the local name in namespaced
+    /// tools and prompts (`files/read`). Only used under the `prefix` style.
+    pub namespace_separator: String,
+    /// How namespaced names are rendered: `prefix` joins with
+    /// `namespace_separator`, `double-colon` forces `::` for clients that
+    /// cannot handle `/` in tool names.
+    pub namespace_style: NamespaceStyle,
+    /// JSON-RPC methods switched off for every client; calls answer
+    /// `-32601`, same as a method the router never had.
+    pub disabled_methods: Vec<String>,
+    /// Per-scope method allowlists for database-issued API tokens: a token
+    /// whose scope appears here may only call the listed methods. Scopes
+    /// absent from the map — and the static `bearer` — are unrestricted.
+    pub scope_methods: HashMap<String, Vec<String>>,
+    /// Check successful `tools/call` results against the tool's declared
+    /// `outputSchema` (when it declares one), attaching a
+    /// `_meta.schema_warning` on mismatch. Advisory only: results are never
+    /// rejected over it.
+    pub validate_output_schemas: bool,
+    /// What aggregated listings do about upstreams that fail to answer:
+    /// `skip` their entries, `include_stale` cached ones, or `fail` the
+    /// whole listing.
+    pub aggregate_on_error: AggregateOnError,
+    /// Seconds between keep-alive comments on idle SSE streams, so proxies
+    /// and load balancers with short idle timeouts do not cut them. Zero
+    /// disables keep-alives.
+    pub sse_keepalive_secs: u64,
+    /// Compress responses (gzip/br) when the client sends `Accept-Encoding`.
+    /// SSE streams are never compressed. Turn this off when a fronting proxy
+    /// already handles compression.
+    pub compression: bool,
+    /// MCP protocol version offered to upstreams during `initialize` and sent
+    /// on HTTP calls. Individual upstreams may override it.
+    pub protocol_version: String,
+    /// Free-form tag identifying this deployment to upstream operators
+    /// (`prod-eu`, `acme-staging`): appended to the outgoing `User-Agent`
+    /// and presented as the `clientInfo` title in router-initiated
+    /// handshakes. Empty means no tag.
+    pub deployment_tag: String,
+    /// Directory scanned at startup for `mcp-*` executables; each one that
+    /// answers `initialize` and `tools/list` is auto-registered as a stdio
+    /// upstream named after the binary (minus the prefix), so adding a tool
+    /// is dropping a binary in the folder. Empty disables discovery.
+    pub plugins_dir: String,
+    /// Filesystem roots the router advertises: answered on `roots/list` and
+    /// included in every stdio upstream's `initialize` params, so one fs
+    /// server can scope itself to them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<RootConfig>,
+    /// Terminate TLS in the router itself instead of an external proxy.
+    /// Unset means plain HTTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+}
+
+impl ServerConfig {
+    /// The active namespace separator: `namespace_separator` under the
+    /// `prefix` style, a literal `::` under `double-colon`.
+    pub fn separator(&self) -> &str {
+        match self.namespace_style {
+            NamespaceStyle::Prefix => &self.namespace_separator,
+            NamespaceStyle::DoubleColon => "::",
+        }
+    }
+
+    /// `User-Agent` for outgoing upstream calls: the crate version, plus the
+    /// deployment tag when one is configured. Individual HTTP upstreams may
+    /// override the whole string.
+    pub fn user_agent(&self) -> String {
+        match self.deployment_tag.as_str() {
+            "" => format!("mcp-router/{}", env!("CARGO_PKG_VERSION")),
+            tag => format!("mcp-router/{} ({tag})", env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    /// `clientInfo` the router presents when it initiates its own
+    /// `initialize` handshakes, with the deployment tag as the display title
+    /// so upstream operators can tell deployments apart.
+    pub fn client_info(&self) -> Value {
+        let mut info = serde_json::json!({
+            "name": "mcp-router",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        if !self.deployment_tag.is_empty() {
+            info["title"] = Value::String(self.deployment_tag.clone());
+        }
+        info
+    }
+
+    /// The configured roots in MCP shape (`uri` plus optional `name`), as
+    /// rendered on `roots/list` and in upstream `initialize` params.
+    pub fn roots_json(&self) -> Vec<Value> {
+        self.roots
+            .iter()
+            .map(|root| {
+                let mut value = serde_json::json!({"uri": root.uri});
+                if let Some(name) = &root.name {
+                    value["name"] = Value::String(name.clone());
+                }
+                value
+            })
+            .collect()
+    }
+}
+
+/// One filesystem root the router advertises to clients and upstreams.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootConfig {
+    /// Root URI, conventionally a `file://` URL.
+    pub uri: String,
+    /// Optional display name for the root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// How namespaced names are rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamespaceStyle {
+    /// `{server}{namespace_separator}{name}`, `/` by default.
+    #[default]
+    Prefix,
+    /// `{server}::{name}`, for clients that reject `/` in tool names.
+    DoubleColon,
+}
+
+/// What an aggregated listing does about upstreams that fail to answer.
+/// Whatever the policy, a listing that lost upstreams names them in
+/// `_meta.partial`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateOnError {
+    /// Leave the failing upstream's entries out.
+    #[default]
+    Skip,
+    /// Serve the failing upstream's last cached catalog, however old. Only
+    /// `tools/list` keeps a catalog cache; the other listings fall back to
+    /// skipping.
+    IncludeStale,
+    /// Refuse the whole listing with `-32001`, naming the failed upstreams.
+    Fail,
+}
+
+/// Policy for `tools/call` results over `max_result_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizePolicy {
+    /// Shorten string fields until the result fits, marking it with
+    /// `_meta.truncated: true` and the original size.
+    #[default]
+    Truncate,
+    /// Reject the call with `-32005 result too large`.
+    Reject,
+}
+
+/// Certificate paths for built-in TLS termination. The files are re-read on
+/// SIGHUP, so certs can be rotated without a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain.
+    pub cert_path: String,
+    /// PEM private key.
+    pub key_path: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "127.0.0.1:8484".into(),
+            bearer: None,
+            allow_origins: Vec::new(),
+            metrics_require_auth: false,
+            metrics_bearer: None,
+            stdio_server: false,
+            cache_ttl_secs: 30,
+            resource_cache_ttl_secs: 0,
+            resource_cache_max_entries: 128,
+            request_timeout_secs: 30,
+            max_hops: 4,
+            health_check_interval_secs: 30,
+            max_upstreams: 0,
+            max_concurrent_requests: 64
+                * std::thread::available_parallelism().map_or(1, usize::from),
+            batch_parallelism: 4,
+            max_request_bytes: 2 * 1024 * 1024,
+            max_result_bytes: 0,
+            recent_calls: 64,
+            recent_calls_arguments: false,
+            slow_call_threshold_ms: 0,
+            oversize_policy: OversizePolicy::Truncate,
+            namespace_separator: "/".into(),
+            namespace_style: NamespaceStyle::Prefix,
+            disabled_methods: Vec::new(),
+            scope_methods: HashMap::new(),
+            validate_output_schemas: false,
+            aggregate_on_error: AggregateOnError::Skip,
+            sse_keepalive_secs: 15,
+            compression: true,
+            protocol_version: mcp_core::PROTOCOL_VERSION.into(),
+            deployment_tag: String::new(),
+            plugins_dir: String::new(),
+            roots: Vec::new(),
+            tls: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// Set to `false` to run without the subscription/provider store: quota
+    /// enforcement is skipped and database-backed admin routes answer 503.
+    pub persistence: bool,
+    /// Connection URL for the subscription/provider store. The scheme picks
+    /// the backend: `sqlite://` for a single instance, `postgres://` when
+    /// several router instances share state.
+    pub url: String,
+    /// How long cached subscription records are trusted before being re-read
+    /// from the database, in seconds. Keep this low when several router
+    /// instances share one database file.
+    pub subscription_cache_ttl_secs: u64,
+    /// Size of the SQLite connection pool.
+    pub max_connections: u32,
+    /// How long a connection waits on a locked database before erroring, in
+    /// milliseconds.
+    pub busy_timeout_ms: u64,
+    /// Tier seeded as a subscription for newly created users, so their first
+    /// call does not fail with `no_subscription`. Empty string disables
+    /// seeding.
+    pub default_tier: String,
+    /// How often the background reconciler applies usage left pending in the
+    /// outbox, in seconds. Zero disables the sweep.
+    pub usage_reconcile_interval_secs: u64,
+    /// Fraction of the token or request quota at which a `quota_warning`
+    /// event is published to the SSE hub, so UIs can nudge the user before
+    /// calls start failing. The event fires once, on the call that crosses
+    /// the threshold. Zero disables the warning.
+    pub quota_warn_ratio: f64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            persistence: true,
+            url: "sqlite://mcp-router.db?mode=rwc".into(),
+            subscription_cache_ttl_secs: 5,
+            max_connections: 10,
+            busy_timeout_ms: 5_000,
+            default_tier: "basic".into(),
+            usage_reconcile_interval_secs: 60,
+            quota_warn_ratio: 0.8,
+        }
+    }
+}
+
+/// One mounted upstream MCP server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamConfig {
+    pub name: String,
+    /// Overrides `server.protocol_version` for this upstream only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<String>,
+    /// Only expose these tools (upstream-local names). Empty means all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_tools: Vec<String>,
+    /// Never expose these tools; wins over `allow_tools`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_tools: Vec<String>,
+    /// Only expose these prompts (upstream-local names). Empty means all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_prompts: Vec<String>,
+    /// Never expose these prompts; wins over `allow_prompts`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_prompts: Vec<String>,
+    /// Only expose these resources, matched on the upstream URI before the
+    /// router re-encodes it. Empty means all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_resources: Vec<String>,
+    /// Never expose these resources; wins over `allow_resources`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_resources: Vec<String>,
+    /// Quota cost multiplier per tool (upstream-local names). A `generate`
+    /// entry of `10.0` makes that tool consume ten times its estimated
+    /// tokens; tools absent from the map cost their estimate unweighted.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub cost_multipliers: HashMap<String, f64>,
+    /// Rewrites applied to `params` before each request is forwarded to this
+    /// upstream, e.g. a `set` of `/arguments/model` to inject a default.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub request_transforms: Vec<TransformRule>,
+    /// Rewrites applied to each successful `result` from this upstream.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub response_transforms: Vec<TransformRule>,
+    #[serde(flatten)]
+    pub transport: TransportConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TransportConfig {
+    /// A child process speaking newline-delimited JSON-RPC on stdio.
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        /// Largest output line accepted from the child, in bytes.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_line_bytes: Option<usize>,
+        /// Wire framing the child speaks; defaults to ndjson.
+        #[serde(default)]
+        framing: StdioFraming,
+        /// Kill the child after this long without a call; it respawns
+        /// lazily on the next one. Unset means the child lives forever.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idle_timeout_ms: Option<u64>,
+    },
+    /// A remote MCP server reached over HTTP POST.
+    Http {
+        /// Single-replica shorthand for `urls`.
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        url: String,
+        /// Replica pool: calls round-robin across these URLs, temporarily
+        /// skipping replicas that recently failed. Listing a URL more than
+        /// once weights it proportionally.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        urls: Vec<String>,
+        #[serde(default)]
+        bearer: Option<String>,
+        /// Shell command run to mint a short-lived bearer token, for
+        /// OAuth-fronted servers. Must print `{"token", "expires_in"}` JSON;
+        /// the token is cached and re-minted as its expiry nears. Takes
+        /// precedence over `bearer`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token_command: Option<String>,
+        /// Replace the router's default `User-Agent` (crate version plus the
+        /// deployment tag) for this upstream only.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user_agent: Option<String>,
+        /// Extra headers sent on every request. A `MCP-Protocol-Version`
+        /// entry here overrides the default protocol version header.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
+    },
+}
+
+/// Wire framing for a stdio upstream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StdioFraming {
+    /// Newline-delimited JSON-RPC: one frame per line.
+    #[default]
+    Ndjson,
+    /// LSP-style framing: `Content-Length: N\r\n\r\n{json}`.
+    Lsp,
+}
+
+/// A provider row seeded into the database at deploy time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderSeed {
+    pub slug: String,
+    pub kind: String,
+    /// Environment variable holding the provider API key.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+}
+
+impl Config {
+    /// Load a config file, interpolating `${VAR}` references from the
+    /// environment before parsing.
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config {}", path.display()))?;
+        let interpolated = interpolate_env(&raw);
+        let config: Config = toml::from_str(&interpolated)
+            .with_context(|| format!("parsing config {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Sanity checks the type system cannot express.
+    pub fn validate(&self) -> Result<()> {
+        let separator = self.server.separator();
+        anyhow::ensure!(
+            !separator.is_empty(),
+            "namespace_separator must not be empty"
+        );
+        anyhow::ensure!(
+            !separator
+                .chars()
+                .any(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')),
+            "namespace_separator {separator:?} conflicts with characters allowed in names"
+        );
+        Ok(())
+    }
+
+    /// A ready-to-edit example config mounting the bundled fs and webfetch
+    /// servers.
+    pub fn example() -> Config {
+        Config {
+            server: ServerConfig::default(),
+            database: DatabaseConfig::default(),
+            upstreams: vec![
+                UpstreamConfig {
+                    name: "fs".into(),
+                    protocol_version: None,
+                    allow_tools: Vec::new(),
+                    deny_tools: Vec::new(),
+                    allow_prompts: Vec::new(),
+                    deny_prompts: Vec::new(),
+                    allow_resources: Vec::new(),
+                    deny_resources: Vec::new(),
+                    cost_multipliers: HashMap::new(),
+                    request_transforms: Vec::new(),
+                    response_transforms: Vec::new(),
+                    transport: TransportConfig::Stdio {
+                        command: "mcp-fs".into(),
+                        args: vec!["--root".into(), ".".into()],
+                        env: HashMap::new(),
+                        max_line_bytes: None,
+                        framing: StdioFraming::Ndjson,
+                        idle_timeout_ms: None,
+                    },
+                },
+                UpstreamConfig {
+                    name: "web".into(),
+                    protocol_version: None,
+                    allow_tools: Vec::new(),
+                    deny_tools: Vec::new(),
+                    allow_prompts: Vec::new(),
+                    deny_prompts: Vec::new(),
+                    allow_resources: Vec::new(),
+                    deny_resources: Vec::new(),
+                    cost_multipliers: HashMap::new(),
+                    request_transforms: Vec::new(),
+                    response_transforms: Vec::new(),
+                    transport: TransportConfig::Stdio {
+                        command: "mcp-webfetch".into(),
+                        args: Vec::new(),
+                        env: HashMap::new(),
+                        max_line_bytes: None,
+                        framing: StdioFraming::Ndjson,
+                        idle_timeout_ms: None,
+                    },
+                },
+            ],
+            providers: vec![ProviderSeed {
+                slug: "openai".into(),
+                kind: "openai".into(),
+                api_key_env: Some("OPENAI_API_KEY".into()),
+                metadata: None,
+            }],
+        }
+    }
+
+    /// A copy safe to show an operator: every field that can carry a
+    /// credential — the server bearers, upstream bearers, and the values of
+    /// configured headers and child environments — becomes `"***"`. Field
+    /// names and structure survive, so the dump still answers "what is the
+    /// effective config" without answering "what are the secrets".
+    pub fn redacted(&self) -> Config {
+        const MASK: &str = "***";
+        let mut config = self.clone();
+        if config.server.bearer.is_some() {
+            config.server.bearer = Some(MASK.into());
+        }
+        if config.server.metrics_bearer.is_some() {
+            config.server.metrics_bearer = Some(MASK.into());
+        }
+        for upstream in &mut config.upstreams {
+            match &mut upstream.transport {
+                TransportConfig::Http {
+                    bearer, headers, ..
+                } => {
+                    if bearer.is_some() {
+                        *bearer = Some(MASK.into());
+                    }
+                    for value in headers.values_mut() {
+                        *value = MASK.into();
+                    }
+                }
+                TransportConfig::Stdio { env, .. } => {
+                    for value in env.values_mut() {
+                        *value = MASK.into();
+                    }
+                }
+            }
+        }
+        config
+    }
+}
+
+/// Replace `${VAR}` with the value of the environment variable `VAR`, leaving
+/// unknown references untouched so parse errors point at the real problem.
+fn interpolate_env(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        match rest[start + 2..].find('}') {
+            Some(end) => {
+                let name = &rest[start + 2..start + 2 + end];
+                match std::env::var(name) {
+                    Ok(value) => out.push_str(&value),
+                    Err(_) => out.push_str(&rest[start..start + 3 + end]),
+                }
+                rest = &rest[start + 3 + end..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_roundtrips_through_toml() {
+        let rendered = toml::to_string_pretty(&Config::example()).unwrap();
+        let parsed: Config = toml::from_str(&rendered).unwrap();
+        assert_eq!(parsed.upstreams.len(), 2);
+        assert_eq!(parsed.providers[0].slug, "openai");
+    }
+
+    #[test]
+    fn separators_conflicting_with_name_characters_are_rejected() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+        config.server.namespace_separator = "x".into();
+        assert!(config.validate().is_err());
+        config.server.namespace_separator = String::new();
+        assert!(config.validate().is_err());
+        config.server.namespace_separator = "::".into();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn env_interpolation() {
+        std::env::set_var("MCP_TEST_BEARER", "sekrit");
+        let raw = "[server]\nbearer = \"${MCP_TEST_BEARER}\"\n";
+        let cfg: Config = toml::from_str(&interpolate_env(raw)).unwrap();
+        assert_eq!(cfg.server.bearer.as_deref(), Some("sekrit"));
+    }
+
+    #[test]
+    fn unknown_vars_left_verbatim() {
+        let raw = "prefix ${THIS_VAR_DOES_NOT_EXIST_123} suffix";
+        assert_eq!(
+            interpolate_env(raw),
+            "prefix ${THIS_VAR_DOES_NOT_EXIST_123} suffix"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Pre-dispatch token estimation, so quotas bite before a call reaches an
+//! upstream rather than after the spend has happened.
+
+use serde_json::Value;
+
+/// Estimates how many tokens a tool call will consume, given the namespaced
+/// tool name and its arguments. Implementations only need to be in the right
+/// ballpark: the estimate reserves quota up front and is reconciled against
+/// the actual usage the upstream reports.
+pub trait TokenEstimator: Send + Sync {
+    fn estimate(&self, tool: &str, arguments: &Value) -> i64;
+}
+
+/// The default estimator: every string in the arguments counts at roughly
+/// four characters per token, the usual BPE rule of thumb. This covers the
+/// provider chat tools (`openai/chat`, `claude/chat`), whose cost is
+/// dominated by the prompt text, and degrades to a small constant for tools
+/// with no textual payload.
+pub struct HeuristicEstimator;
+
+impl TokenEstimator for HeuristicEstimator {
+    fn estimate(&self, _tool: &str, arguments: &Value) -> i64 {
+        // Round up so a one-character prompt still costs a token.
+        (text_len(arguments) as i64 + 3) / 4
+    }
+}
+
+/// Total length of every string value reachable in `value`, keys excluded.
+fn text_len(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.chars().count(),
+        Value::Array(items) => items.iter().map(text_len).sum(),
+        Value::Object(map) => map.values().map(text_len).sum(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn longer_prompts_estimate_higher() {
+        let estimator = HeuristicEstimator;
+        let small = estimator.estimate("openai/chat", &json!({"prompt": "hi"}));
+        let large = estimator.estimate(
+            "openai/chat",
+            &json!({"messages": [{"role": "user", "content": "x".repeat(4000)}]}),
+        );
+        assert!(large > small, "large={large} small={small}");
+        assert_eq!(large, (4000 + 4_i64) / 4);
+    }
+
+    #[test]
+    fn non_textual_arguments_are_near_free() {
+        let estimator = HeuristicEstimator;
+        assert_eq!(estimator.estimate("fs/list", &json!({"depth": 3})), 0);
+    }
+}
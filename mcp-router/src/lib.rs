@@ -0,0 +1,26 @@
+//! An aggregating MCP router.
+//!
+//! The router mounts any number of MCP servers ("upstreams", stdio children or
+//! remote HTTP endpoints), namespaces their tools/prompts/resources under the
+//! upstream name, and exposes the union over a single JSON-RPC endpoint at
+//! `/mcp`, with SSE event streams, Prometheus metrics, and a subscription /
+//! quota layer backed by SQLite.
+
+pub mod admin;
+pub mod auth;
+pub mod config;
+pub mod estimate;
+pub mod logging;
+pub mod metrics;
+pub mod router;
+pub mod server;
+pub mod sse;
+pub mod store;
+pub mod transform;
+pub mod upstream;
+
+/// JSON-RPC wire types, shared with the bundled `mcp-*` servers.
+pub use mcp_core::rpc as jsonrpc;
+
+pub use config::Config;
+pub use router::RouterState;
@@ -0,0 +1,163 @@
+//! Runtime log verbosity control, backing the MCP `logging/setLevel` method.
+
+use std::sync::{Mutex, OnceLock};
+
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+static HANDLE: OnceLock<FilterHandle> = OnceLock::new();
+static CURRENT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Output format for the installed subscriber.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines for local development.
+    #[default]
+    Pretty,
+    /// One flattened JSON object per event, with the current span's fields
+    /// (`request_id` etc.) included, for log aggregation.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format: {other} (pretty, json)")),
+        }
+    }
+}
+
+/// Install the global subscriber with a reloadable env filter. The initial
+/// filter comes from `RUST_LOG`, defaulting to `info`. Events go to stderr:
+/// stdout must stay clean for JSON-RPC frames when `server.stdio_server` is
+/// on.
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+    match format {
+        LogFormat::Pretty => registry
+            .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+            .init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(true)
+                    .with_span_list(false)
+                    .with_writer(std::io::stderr),
+            )
+            .init(),
+    }
+    let _ = HANDLE.set(handle);
+}
+
+/// Map an MCP logging level to a `tracing` filter directive. MCP uses syslog
+/// names; everything at `warning` and above folds onto the nearest `tracing`
+/// level.
+fn directive(level: &str) -> Option<&'static str> {
+    match level {
+        "debug" => Some("debug"),
+        "info" | "notice" => Some("info"),
+        "warning" => Some("warn"),
+        "error" | "critical" | "alert" | "emergency" => Some("error"),
+        _ => None,
+    }
+}
+
+/// Swap the active filter to the given MCP level. Returns the applied
+/// `tracing` directive, or an error message for an unknown level or when
+/// [`init`] never ran (stdio-server tests, embedding).
+pub fn set_level(level: &str) -> Result<&'static str, String> {
+    let directive = directive(level).ok_or_else(|| format!("unknown log level: {level}"))?;
+    let handle = HANDLE
+        .get()
+        .ok_or_else(|| "log level control unavailable: subscriber not installed".to_string())?;
+    handle
+        .reload(EnvFilter::new(directive))
+        .map_err(|err| format!("reloading filter: {err}"))?;
+    *CURRENT.lock().expect("level lock") = Some(level.to_string());
+    Ok(directive)
+}
+
+/// The level most recently applied through [`set_level`], if any.
+pub fn current_level() -> Option<String> {
+    CURRENT.lock().expect("level lock").clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_level_reloads_the_filter() {
+        init(LogFormat::Pretty);
+        // The default filter is `info`: debug events are filtered out.
+        assert!(!tracing::enabled!(tracing::Level::DEBUG));
+
+        set_level("debug").unwrap();
+        assert!(tracing::enabled!(tracing::Level::DEBUG));
+        assert_eq!(current_level().as_deref(), Some("debug"));
+
+        set_level("warning").unwrap();
+        assert!(!tracing::enabled!(tracing::Level::INFO));
+        assert!(tracing::enabled!(tracing::Level::WARN));
+
+        assert!(set_level("loud").is_err());
+    }
+
+    #[test]
+    fn json_format_emits_parseable_events() {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        // Capture output from a scoped subscriber configured like
+        // `init(LogFormat::Json)`, without touching the global one.
+        #[derive(Clone)]
+        struct Sink(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().expect("sink lock").extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sink = Sink(Arc::new(Mutex::new(Vec::new())));
+        let writer = sink.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(move || writer.clone()),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("rpc", request_id = "abc-123");
+            let _guard = span.enter();
+            tracing::info!(method = "tools/list", latency_ms = 7, "dispatched");
+        });
+
+        let raw = sink.0.lock().expect("sink lock").clone();
+        let line = String::from_utf8(raw).expect("utf8 log output");
+        let event: serde_json::Value =
+            serde_json::from_str(line.lines().next().expect("one event")).expect("json event");
+        assert_eq!(event["message"], "dispatched");
+        assert_eq!(event["method"], "tools/list");
+        assert_eq!(event["latency_ms"], 7);
+        assert_eq!(event["span"]["request_id"], "abc-123");
+    }
+}
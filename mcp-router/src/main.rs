@@ -0,0 +1,263 @@
+//! `mcp-router` binary: load config, open the store, mount upstreams, serve.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use mcp_router::config::Config;
+use mcp_router::router::RouterState;
+use mcp_router::server::build_app;
+use mcp_router::store::{ProviderStore, SubscriptionStore};
+use mcp_router::upstream::UpstreamRegistry;
+
+#[derive(Parser)]
+#[command(name = "mcp-router", version, about = "Aggregating MCP router")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the TOML config file.
+    #[arg(long, short, default_value = "router.toml")]
+    config: PathBuf,
+
+    /// Run without the subscription/provider store (overrides
+    /// `database.persistence`). Quota enforcement is disabled and
+    /// database-backed admin routes answer 503.
+    #[arg(long)]
+    no_persistence: bool,
+
+    /// Log output format: `pretty` for local dev, `json` for aggregation.
+    #[arg(long, default_value = "pretty")]
+    log_format: mcp_router::logging::LogFormat,
+
+    /// Preflight instead of serving: check the database, initialize every
+    /// upstream and run an aggregated `tools/list`, then exit — nonzero if
+    /// any check failed.
+    #[arg(long)]
+    self_test: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write an example config (fs and webfetch upstreams) to start from.
+    Init {
+        /// Where to write the config.
+        #[arg(long, default_value = "router.toml")]
+        output: PathBuf,
+
+        /// Overwrite the file if it already exists.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+fn generate_config(output: &PathBuf, force: bool) -> Result<()> {
+    if output.exists() && !force {
+        bail!(
+            "{} already exists, pass --force to overwrite",
+            output.display()
+        );
+    }
+    let toml = toml::to_string_pretty(&Config::example()).context("serializing example config")?;
+    std::fs::write(output, toml).with_context(|| format!("writing {}", output.display()))?;
+    println!("wrote {}", output.display());
+    println!();
+    println!("Provider API keys at rest are encrypted with a key derived from");
+    println!("MCP_ROUTER_MASTER_KEY; set one before enabling persistence, e.g.:");
+    println!();
+    println!(
+        "    export MCP_ROUTER_MASTER_KEY={}",
+        uuid::Uuid::new_v4().simple()
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if let Some(Command::Init { output, force }) = &cli.command {
+        return generate_config(output, *force);
+    }
+    mcp_router::logging::init(cli.log_format);
+    let mut config = if cli.config.exists() {
+        Config::load_from(&cli.config)?
+    } else {
+        tracing::warn!(path = %cli.config.display(), "config not found, using defaults");
+        Config::default()
+    };
+    if cli.no_persistence {
+        config.database.persistence = false;
+    }
+
+    let (store, providers) = if config.database.persistence {
+        let store = SubscriptionStore::open(
+            &config.database.url,
+            config.database.max_connections,
+            std::time::Duration::from_millis(config.database.busy_timeout_ms),
+        )
+        .await
+        .with_context(|| format!("opening database {}", config.database.url))?
+            .with_cache_ttl(std::time::Duration::from_secs(
+                config.database.subscription_cache_ttl_secs,
+            ))
+            .with_default_tier(match config.database.default_tier.as_str() {
+                "" => None,
+                tier => Some(tier.to_string()),
+            });
+        store.run_migrations().await.context("running migrations")?;
+        if config.database.usage_reconcile_interval_secs > 0 {
+            store.spawn_usage_reconciler(std::time::Duration::from_secs(
+                config.database.usage_reconcile_interval_secs,
+            ));
+        }
+        let providers = ProviderStore::new(store.pool().clone());
+        (Some(store), Some(providers))
+    } else {
+        tracing::warn!("persistence disabled: quota enforcement and admin DB routes are off");
+        (None, None)
+    };
+
+    let timeout = std::time::Duration::from_secs(config.server.request_timeout_secs);
+    let registry = Arc::new(
+        UpstreamRegistry::from_config(&config.upstreams, timeout, &config.server)
+        .context("mounting configured upstreams")?,
+    );
+    if !config.server.plugins_dir.is_empty() {
+        let found = registry
+            .discover_plugins(std::path::Path::new(&config.server.plugins_dir))
+            .await;
+        tracing::info!(dir = %config.server.plugins_dir, plugins = found, "plugin discovery done");
+    }
+    tracing::info!(upstreams = registry.names().len(), "registry ready");
+
+    if config.server.health_check_interval_secs > 0 {
+        registry.spawn_health_checker(std::time::Duration::from_secs(
+            config.server.health_check_interval_secs,
+        ));
+    }
+
+    let bind = config.server.bind.clone();
+    let tls = config.server.tls.clone();
+    let state = Arc::new(RouterState::new(config, registry, store, providers));
+    let seeded = state.seed_providers().await.context("seeding providers")?;
+    if seeded > 0 {
+        tracing::info!(providers = seeded, "seeded providers from config");
+    }
+    if cli.self_test {
+        return run_self_test(&state).await;
+    }
+
+    // Stdio mode: speak MCP on stdin/stdout for an embedding client and skip
+    // the HTTP listener entirely (an embedded child should not grab a port).
+    if state.config.server.stdio_server {
+        tracing::info!("mcp-router serving stdio (HTTP listener disabled)");
+        let stdio_state = state.clone();
+        mcp_core::stdio::serve_lines(move |req| {
+            let state = stdio_state.clone();
+            async move { mcp_router::router::handle_jsonrpc(&state, req).await }
+        })
+        .await?;
+        let report = state.shutdown_report().await;
+        tracing::info!(report = %report, "shutdown report");
+        return Ok(());
+    }
+
+    let app = build_app(state.clone());
+
+    if let Some(path) = bind.strip_prefix("unix:") {
+        // Sidecar mode: no TCP exposure at all.
+        #[cfg(unix)]
+        {
+            if tls.is_some() {
+                bail!("tls is not supported on a unix socket bind");
+            }
+            tracing::info!(%bind, "mcp-router listening (unix)");
+            mcp_router::server::serve_unix(app, std::path::Path::new(path), shutdown_signal())
+                .await
+                .with_context(|| format!("serving on {bind}"))?;
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = path;
+            bail!("unix socket binds are only supported on unix platforms");
+        }
+    } else if let Some(tls) = tls {
+        let addr: std::net::SocketAddr = bind
+            .parse()
+            .with_context(|| format!("parsing bind address {bind}"))?;
+        let handle = axum_server::Handle::new();
+        let shutdown = handle.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            shutdown.graceful_shutdown(Some(std::time::Duration::from_secs(10)));
+        });
+        tracing::info!(%bind, "mcp-router listening (tls)");
+        mcp_router::server::serve_tls(app, addr, &tls, handle)
+            .await
+            .with_context(|| format!("serving tls on {bind}"))?;
+    } else {
+        let listener = tokio::net::TcpListener::bind(&bind)
+            .await
+            .with_context(|| format!("binding {bind}"))?;
+        tracing::info!(%bind, "mcp-router listening");
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal())
+            .await?;
+    }
+    let report = state.shutdown_report().await;
+    tracing::info!(report = %report, "shutdown report");
+    Ok(())
+}
+
+/// `--self-test`: probe everything the router needs before taking traffic —
+/// the database (when persistence is on), each upstream's `initialize`, and
+/// one aggregated `tools/list` — logging one line per check. Config loading,
+/// migrations and upstream mounting have already run by the time we get
+/// here, so their failures abort the same way they would on a normal boot.
+async fn run_self_test(state: &Arc<RouterState>) -> Result<()> {
+    let mut failures: Vec<String> = Vec::new();
+
+    match &state.store {
+        Some(store) => match store.list_users(true).await {
+            Ok(users) => tracing::info!(users = users.len(), "self-test: database ok"),
+            Err(err) => {
+                tracing::error!(%err, "self-test: database check failed");
+                failures.push(format!("database: {err}"));
+            }
+        },
+        None => tracing::info!("self-test: persistence disabled, skipping database check"),
+    }
+
+    state.registry.check_health().await;
+    for handle in state.registry.handles() {
+        match handle.health().healthy {
+            Some(true) => tracing::info!(upstream = %handle.name, "self-test: upstream ok"),
+            _ => {
+                tracing::error!(upstream = %handle.name, "self-test: initialize failed");
+                failures.push(format!("upstream {}: initialize failed", handle.name));
+            }
+        }
+    }
+
+    let (tools, failed) = mcp_router::router::aggregate_tools(state, 0).await;
+    tracing::info!(tools = tools.len(), "self-test: aggregated tools/list");
+    for name in failed {
+        failures.push(format!("upstream {name}: tools/list failed"));
+    }
+
+    if !failures.is_empty() {
+        bail!("self-test failed: {}", failures.join("; "));
+    }
+    println!(
+        "self-test passed: {} upstreams, {} tools",
+        state.registry.names().len(),
+        tools.len()
+    );
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+    tracing::info!("shutdown signal received");
+}
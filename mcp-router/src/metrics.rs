@@ -0,0 +1,145 @@
+//! Prometheus metrics and the `/metrics` exposition handler.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use prometheus::{Encoder, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder};
+
+use crate::auth::MetricsToken;
+use crate::router::RouterState;
+
+/// Latency buckets spanning in-memory dispatch (~1ms) through slow LLM
+/// upstreams (~60s); the Prometheus defaults top out at 10s and have nothing
+/// below 5ms.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.025, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 15.0, 30.0, 60.0,
+];
+
+pub struct Metrics {
+    registry: Registry,
+    /// JSON-RPC requests handled, labeled by method.
+    pub rpc_requests: IntCounterVec,
+    /// JSON-RPC handling latency in seconds, labeled by method.
+    pub rpc_latency: HistogramVec,
+    /// Upstream call latency in seconds, labeled by upstream name.
+    pub upstream_latency: HistogramVec,
+    /// Upstream call failures, labeled by upstream name.
+    pub upstream_errors: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let rpc_requests = IntCounterVec::new(
+            Opts::new("mcp_rpc_requests_total", "JSON-RPC requests handled"),
+            &["method"],
+        )
+        .expect("rpc_requests opts");
+        let rpc_latency = HistogramVec::new(
+            prometheus::HistogramOpts::new("mcp_rpc_latency_seconds", "JSON-RPC handling latency")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["method"],
+        )
+        .expect("rpc_latency opts");
+        let upstream_latency = HistogramVec::new(
+            prometheus::HistogramOpts::new("mcp_upstream_latency_seconds", "Upstream call latency")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["upstream"],
+        )
+        .expect("upstream_latency opts");
+        let upstream_errors = IntCounterVec::new(
+            Opts::new("mcp_upstream_errors_total", "Upstream call failures"),
+            &["upstream"],
+        )
+        .expect("upstream_errors opts");
+        registry
+            .register(Box::new(rpc_requests.clone()))
+            .expect("register rpc_requests");
+        registry
+            .register(Box::new(rpc_latency.clone()))
+            .expect("register rpc_latency");
+        registry
+            .register(Box::new(upstream_latency.clone()))
+            .expect("register upstream_latency");
+        registry
+            .register(Box::new(upstream_errors.clone()))
+            .expect("register upstream_errors");
+        Metrics {
+            registry,
+            rpc_requests,
+            rpc_latency,
+            upstream_latency,
+            upstream_errors,
+        }
+    }
+
+    /// Per-method request counts, read back out of the registry for the
+    /// shutdown report.
+    pub fn rpc_request_counts(&self) -> Vec<(String, u64)> {
+        let mut counts = Vec::new();
+        for family in self.registry.gather() {
+            if family.name() != "mcp_rpc_requests_total" {
+                continue;
+            }
+            for metric in family.get_metric() {
+                let method = metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.name() == "method")
+                    .map(|label| label.value().to_string())
+                    .unwrap_or_default();
+                counts.push((method, metric.get_counter().get_value() as u64));
+            }
+        }
+        counts.sort();
+        counts
+    }
+
+    /// Render the registry in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        let encoder = TextEncoder::new();
+        if let Err(err) = encoder.encode(&self.registry.gather(), &mut buf) {
+            tracing::warn!(%err, "failed to encode metrics");
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// `GET /metrics`.
+pub async fn handler(State(state): State<Arc<RouterState>>, _auth: MetricsToken) -> String {
+    state.metrics.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_histograms_use_explicit_buckets() {
+        let metrics = Metrics::new();
+        metrics
+            .rpc_latency
+            .with_label_values(&["tools/list"])
+            .observe(0.002);
+        let text = metrics.render();
+        assert!(text.contains("le=\"0.001\""), "{text}");
+        assert!(text.contains("le=\"0.005\""), "{text}");
+        assert!(text.contains("mcp_rpc_latency_seconds_count"), "{text}");
+    }
+
+    #[test]
+    fn render_includes_counters_after_inc() {
+        let metrics = Metrics::new();
+        metrics.rpc_requests.with_label_values(&["tools/list"]).inc();
+        let text = metrics.render();
+        assert!(text.contains("mcp_rpc_requests_total"));
+        assert!(text.contains("method=\"tools/list\""));
+    }
+}
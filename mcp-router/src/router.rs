@@ -0,0 +1,3114 @@
+//! The aggregation core: shared state, the JSON-RPC dispatcher, and the
+//! namespacing logic that merges many upstreams into one catalog.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use mcp_core::rpc::{code, Id, Request, Response};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+use crate::config::{AggregateOnError, Config, OversizePolicy};
+use crate::estimate::{HeuristicEstimator, TokenEstimator};
+use crate::metrics::Metrics;
+use crate::sse::{EventHub, RouterEvent};
+use crate::store::{EnforcementError, ProviderStore, SubscriptionStore, TIERS};
+use crate::upstream::{hop_count, NotificationHandler, UpstreamError, UpstreamRegistry, HOP_HEADER};
+
+/// URI scheme for router-namespaced resources:
+/// `mcp+router://{server}/{percent-encoded upstream uri}`.
+pub const RESOURCE_SCHEME: &str = "mcp+router://";
+
+/// Everything the HTTP handlers share.
+pub struct RouterState {
+    pub config: Config,
+    pub registry: Arc<UpstreamRegistry>,
+    /// `None` when persistence is disabled: quota enforcement is skipped and
+    /// the database-backed admin routes answer 503.
+    pub store: Option<SubscriptionStore>,
+    pub providers: Option<ProviderStore>,
+    pub hub: EventHub,
+    pub metrics: Metrics,
+    /// Estimates token spend before dispatch when the caller did not provide
+    /// `usage.tokens`.
+    pub estimator: Arc<dyn TokenEstimator>,
+    /// Per-upstream cached `tools/list` results, shared with the upstream
+    /// notification handler so `tools/list_changed` can invalidate it.
+    tools_cache: ToolsCache,
+    /// Single-flight locks for cold `tools/list` fetches, keyed by upstream
+    /// and hop count: the first caller to miss the cache does the upstream
+    /// call while the rest wait and then read the freshly cached entry, so N
+    /// concurrent listings on a cold cache cost one fetch instead of N. The
+    /// hop count is part of the key so a mesh request re-entering this router
+    /// is never queued behind its own outer call.
+    list_flights: StdMutex<HashMap<(String, u64), FlightLock>>,
+    /// Maintenance mode: upstream-forwarding methods are rejected with
+    /// `-32000` while introspection, health and admin routes keep working.
+    maintenance: AtomicBool,
+    /// Read-through `resources/read` cache; empty unless
+    /// `resource_cache_ttl_secs` is set.
+    resource_cache: RwLock<HashMap<String, CachedResource>>,
+    /// When this state was built, for the shutdown report's uptime.
+    started: Instant,
+    /// Ring buffer of the last few `tools/call`s, newest first, for the
+    /// `/api/calls/recent` debug listing.
+    recent_calls: StdMutex<VecDeque<RecentCall>>,
+    /// Global concurrency gate sized by `max_concurrent_requests`; `None`
+    /// when the cap is disabled. A request that cannot take a slot within
+    /// [`BUSY_WAIT`] is rejected with `-32006 server busy`.
+    request_slots: Option<Arc<tokio::sync::Semaphore>>,
+}
+
+type ToolsCache = Arc<RwLock<HashMap<String, CachedCatalog>>>;
+
+/// One in-flight `tools/list` fetch; waiters queue on the lock.
+type FlightLock = Arc<tokio::sync::Mutex<()>>;
+
+struct CachedCatalog {
+    fetched: Instant,
+    tools: Vec<Value>,
+}
+
+/// One remembered `tools/call` for the recent-calls listing.
+struct RecentCall {
+    at: Instant,
+    tool: String,
+    user_id: Option<String>,
+    latency_ms: u64,
+    /// `"ok"`, or the JSON-RPC error code of the failure as a string.
+    status: String,
+    /// Redacted arguments, kept only when `recent_calls_arguments` is on.
+    arguments: Option<Value>,
+}
+
+/// A cached `resources/read` result, keyed by the full router URI.
+struct CachedResource {
+    fetched: Instant,
+    /// For LRU eviction once the cache is full.
+    last_used: Instant,
+    result: Value,
+    /// Validators the upstream attached to the result, echoed back on the
+    /// next fetch so it can answer `notModified` instead of re-sending.
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl RouterState {
+    pub fn new(
+        config: Config,
+        registry: Arc<UpstreamRegistry>,
+        store: Option<SubscriptionStore>,
+        providers: Option<ProviderStore>,
+    ) -> Self {
+        let hub = EventHub::new();
+        let tools_cache: ToolsCache = Arc::default();
+        let metrics = Metrics::new();
+        registry.set_notification_handler(notification_handler(hub.clone(), tools_cache.clone()));
+        registry.set_latency_histogram(metrics.upstream_latency.clone());
+        let request_slots = match config.server.max_concurrent_requests {
+            0 => None,
+            slots => Some(Arc::new(tokio::sync::Semaphore::new(slots))),
+        };
+        RouterState {
+            config,
+            registry,
+            store,
+            providers,
+            hub,
+            metrics,
+            estimator: Arc::new(HeuristicEstimator),
+            tools_cache,
+            list_flights: StdMutex::new(HashMap::new()),
+            maintenance: AtomicBool::new(false),
+            resource_cache: RwLock::new(HashMap::new()),
+            started: Instant::now(),
+            recent_calls: StdMutex::new(VecDeque::new()),
+            request_slots,
+        }
+    }
+
+    /// Remember one `tools/call` in the bounded recent-calls buffer.
+    fn record_recent_call(
+        &self,
+        tool: &str,
+        user_id: Option<&str>,
+        latency: Duration,
+        status: String,
+        arguments: Option<Value>,
+    ) {
+        let capacity = self.config.server.recent_calls;
+        if capacity == 0 {
+            return;
+        }
+        let mut calls = self.recent_calls.lock().expect("recent calls lock");
+        while calls.len() >= capacity {
+            calls.pop_back();
+        }
+        calls.push_front(RecentCall {
+            at: Instant::now(),
+            tool: tool.to_string(),
+            user_id: user_id.map(String::from),
+            latency_ms: latency.as_millis() as u64,
+            status,
+            arguments,
+        });
+    }
+
+    /// The most recent `tools/call`s, newest first, at most `limit` of them.
+    pub fn recent_calls(&self, limit: usize) -> Vec<Value> {
+        self.recent_calls
+            .lock()
+            .expect("recent calls lock")
+            .iter()
+            .take(limit)
+            .map(|call| {
+                let mut entry = json!({
+                    "tool": call.tool,
+                    "user_id": call.user_id,
+                    "status": call.status,
+                    "latency_ms": call.latency_ms,
+                    "secs_ago": call.at.elapsed().as_secs(),
+                });
+                if let Some(arguments) = &call.arguments {
+                    entry["arguments"] = arguments.clone();
+                }
+                entry
+            })
+            .collect()
+    }
+
+    /// Build the post-mortem summary logged at shutdown, stopping every
+    /// upstream transport along the way.
+    pub async fn shutdown_report(&self) -> Value {
+        let per_method = self.metrics.rpc_request_counts();
+        let total: u64 = per_method.iter().map(|(_, count)| count).sum();
+        let (stopped, killed) = self.registry.shutdown_all().await;
+        let per_method: serde_json::Map<String, Value> = per_method
+            .into_iter()
+            .map(|(method, count)| (method, json!(count)))
+            .collect();
+        json!({
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "total_requests": total,
+            "per_method": per_method,
+            "upstreams_stopped": stopped,
+            "upstreams_killed": killed,
+        })
+    }
+
+    /// Whether maintenance mode is currently on.
+    pub fn maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    pub fn set_maintenance(&self, enabled: bool) {
+        self.maintenance.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Swap the default [`HeuristicEstimator`] for a custom one.
+    pub fn with_estimator(mut self, estimator: Arc<dyn TokenEstimator>) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.config.server.cache_ttl_secs)
+    }
+
+    /// Drop the cached tool catalog for one upstream.
+    pub async fn invalidate_tools_cache(&self, upstream: &str) {
+        self.tools_cache.write().await.remove(upstream);
+    }
+
+    /// Insert a `resources/read` result into the content cache, evicting the
+    /// least recently used entry once the cache is full.
+    async fn cache_resource(&self, uri: &str, result: &Value) {
+        let max = self.config.server.resource_cache_max_entries.max(1);
+        let mut cache = self.resource_cache.write().await;
+        if !cache.contains_key(uri) && cache.len() >= max {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        let now = Instant::now();
+        cache.insert(
+            uri.to_string(),
+            CachedResource {
+                fetched: now,
+                last_used: now,
+                result: result.clone(),
+                etag: result.get("etag").and_then(Value::as_str).map(String::from),
+                last_modified: result
+                    .get("lastModified")
+                    .and_then(Value::as_str)
+                    .map(String::from),
+            },
+        );
+    }
+
+    /// Upsert every `[[providers]]` seed from the config into the provider
+    /// store, resolving API keys from the named environment variables.
+    /// Returns how many seeds were applied; a no-op without persistence.
+    pub async fn seed_providers(&self) -> Result<usize, sqlx::Error> {
+        let Some(providers) = &self.providers else {
+            return Ok(0);
+        };
+        for seed in &self.config.providers {
+            let api_key = seed.api_key_env.as_ref().and_then(|env| {
+                std::env::var(env)
+                    .map_err(|_| {
+                        tracing::warn!(
+                            provider = %seed.slug,
+                            %env,
+                            "api key env var unset, seeding provider without a key"
+                        );
+                    })
+                    .ok()
+            });
+            providers
+                .put_provider(
+                    &seed.slug,
+                    &seed.kind,
+                    api_key.as_deref(),
+                    seed.metadata.as_ref(),
+                )
+                .await?;
+        }
+        Ok(self.config.providers.len())
+    }
+}
+
+/// Route notifications emitted by upstream servers: `tools/list_changed`
+/// invalidates that upstream's cached catalog and is re-emitted as a
+/// router-level `tools_changed` event; `notifications/progress` becomes a
+/// `progress` event carrying the upstream's progressToken so subscribers can
+/// correlate it with their in-flight call; everything else is forwarded
+/// verbatim as a `notification` event.
+fn notification_handler(hub: EventHub, tools_cache: ToolsCache) -> NotificationHandler {
+    Arc::new(move |upstream: &str, notification| {
+        let hub = hub.clone();
+        let tools_cache = tools_cache.clone();
+        let upstream = upstream.to_string();
+        let notification = notification.clone();
+        tokio::spawn(async move {
+            if notification.method == "notifications/tools/list_changed" {
+                tools_cache.write().await.remove(&upstream);
+                hub.publish(RouterEvent::new(
+                    "tools_changed",
+                    Some(upstream),
+                    json!({"method": notification.method}),
+                ));
+            } else if notification.method == "notifications/progress" {
+                hub.publish(RouterEvent::new(
+                    "progress",
+                    Some(upstream),
+                    notification.params.clone(),
+                ));
+            } else {
+                hub.publish(RouterEvent::new(
+                    "notification",
+                    Some(upstream),
+                    json!({"method": notification.method, "params": notification.params}),
+                ));
+            }
+        });
+    })
+}
+
+/// Split a namespaced name (`server/tool`, or whatever separator the server
+/// config renders with) into `(server, local)`.
+pub fn split_namespace<'a>(name: &'a str, separator: &str) -> Option<(&'a str, &'a str)> {
+    name.split_once(separator)
+}
+
+/// How long an arriving request waits for a concurrency slot before being
+/// rejected as busy. Long enough to ride out a momentary burst, short enough
+/// that a saturated router sheds load instead of queueing it indefinitely.
+const BUSY_WAIT: Duration = Duration::from_millis(100);
+
+/// Dispatch one JSON-RPC request against the router.
+pub async fn handle_jsonrpc(state: &RouterState, request: Request) -> Response {
+    let method = request.method.clone();
+    state.metrics.rpc_requests.with_label_values(&[&method]).inc();
+    let timer = state
+        .metrics
+        .rpc_latency
+        .with_label_values(&[&method])
+        .start_timer();
+    // Whole methods can be switched off, globally (`disabled_methods`) or for
+    // particular token scopes (`scope_methods`). A blocked method answers
+    // `-32601`, exactly as if the router never had it.
+    if let Some(denied) = method_denied(state, &request) {
+        timer.observe_duration();
+        return denied;
+    }
+    if state.maintenance() && blocked_in_maintenance(&method) {
+        timer.observe_duration();
+        return Response::error_with_data(
+            request.id,
+            code::MAINTENANCE,
+            "router is in maintenance mode",
+            json!({"reason": "maintenance"}),
+        );
+    }
+    // Loop guard for mesh deployments: every router-to-router forward
+    // increments `_meta.mcp-hops`, so a request that has bounced through
+    // `max_hops` routers is a cycle, not a deep topology.
+    let hops = hop_count(&request.params);
+    if hops >= state.config.server.max_hops {
+        timer.observe_duration();
+        return Response::error_with_data(
+            request.id,
+            code::LOOP_DETECTED,
+            "loop detected: too many router hops",
+            json!({"hops": hops, "max_hops": state.config.server.max_hops}),
+        );
+    }
+    // The global concurrency gate, held for the whole dispatch (and so for
+    // any upstream fan-out it causes).
+    let _slot = match &state.request_slots {
+        Some(slots) => {
+            match tokio::time::timeout(BUSY_WAIT, Arc::clone(slots).acquire_owned()).await {
+                Ok(Ok(permit)) => Some(permit),
+                // Closed semaphores do not happen (nothing closes it); a
+                // timeout means sustained saturation.
+                _ => {
+                    timer.observe_duration();
+                    return Response::error_with_data(
+                        request.id,
+                        code::SERVER_BUSY,
+                        "server busy: too many concurrent requests",
+                        json!({
+                            "reason": "server_busy",
+                            "max_concurrent_requests": state.config.server.max_concurrent_requests,
+                        }),
+                    );
+                }
+            }
+        }
+        None => None,
+    };
+    // Captured before `request` moves into dispatch; only `tools/call`-shaped
+    // params name an upstream.
+    let threshold_ms = state.config.server.slow_call_threshold_ms;
+    let slow_context = (threshold_ms > 0).then(|| {
+        let upstream = request
+            .params
+            .get("name")
+            .and_then(Value::as_str)
+            .and_then(|name| split_namespace(name, state.config.server.separator()))
+            .map(|(server, _)| server.to_string());
+        let user = request
+            .params
+            .pointer("/_meta/user_id")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        (Instant::now(), upstream, user)
+    });
+    let response = dispatch(state, request).await;
+    timer.observe_duration();
+    if let Some((started, upstream, user)) = slow_context {
+        let latency_ms = started.elapsed().as_millis() as u64;
+        if latency_ms > threshold_ms {
+            tracing::warn!(
+                method = %method,
+                upstream = upstream.as_deref().unwrap_or(""),
+                user = user.as_deref().unwrap_or(""),
+                latency_ms,
+                threshold_ms,
+                "slow call"
+            );
+        }
+    }
+    response
+}
+
+/// The method filter: globally disabled methods are rejected for everyone;
+/// a request authenticated with a scoped API token (scopes stamped into
+/// `_meta.scopes` by the HTTP layer, never taken from the client) must have
+/// at least one scope permitting the method. A scope with no entry in
+/// `scope_methods` is unrestricted, as is the static bearer.
+fn method_denied(state: &RouterState, request: &Request) -> Option<Response> {
+    let server = &state.config.server;
+    let method = request.method.as_str();
+    if server.disabled_methods.iter().any(|m| m == method) {
+        return Some(Response::error(
+            request.id.clone(),
+            code::METHOD_NOT_FOUND,
+            format!("method disabled: {method}"),
+        ));
+    }
+    let scopes = request.params.pointer("/_meta/scopes").and_then(Value::as_array)?;
+    let mut restricted = false;
+    for scope in scopes.iter().filter_map(Value::as_str) {
+        match server.scope_methods.get(scope) {
+            // An unlisted scope has no method restriction.
+            None => return None,
+            Some(allowed) => {
+                restricted = true;
+                if allowed.iter().any(|m| m == method) {
+                    return None;
+                }
+            }
+        }
+    }
+    restricted.then(|| {
+        Response::error(
+            request.id.clone(),
+            code::METHOD_NOT_FOUND,
+            format!("method not permitted for this token: {method}"),
+        )
+    })
+}
+
+/// Methods that forward work to upstreams. Everything else (initialize, the
+/// listings, logging control) stays available during maintenance so clients
+/// can keep their catalogs warm.
+fn blocked_in_maintenance(method: &str) -> bool {
+    matches!(
+        method,
+        "tools/call" | "tools/call_batch" | "prompts/get" | "resources/read"
+            | "completion/complete"
+    )
+}
+
+async fn dispatch(state: &RouterState, request: Request) -> Response {
+    let id = request.id.clone();
+    // MCP params are always a JSON object (or absent). Rejecting anything
+    // else up front gives one clear error instead of whatever misleading
+    // missing-field complaint a handler would produce for, say, an array.
+    if !request.params.is_null() && !request.params.is_object() {
+        return Response::error_with_data(
+            id,
+            code::INVALID_PARAMS,
+            "invalid params: expected object",
+            json!({"type": json_type(&request.params)}),
+        );
+    }
+    let hops = hop_count(&request.params);
+    match request.method.as_str() {
+        "initialize" => handle_initialize(state, request).await,
+        "tools/list" => {
+            let (tools, failed) = aggregate_tools(state, hops).await;
+            aggregate_response(state, id, "tools", tools, failed)
+        }
+        "tools/call" => handle_tool_call(state, request).await,
+        "tools/call_batch" => handle_tool_call_batch(state, request).await,
+        "prompts/list" => {
+            let (prompts, failed) = aggregate_prompts(state, hops).await;
+            aggregate_response(state, id, "prompts", prompts, failed)
+        }
+        "prompts/get" => handle_prompt_get(state, request).await,
+        "resources/list" => {
+            let (resources, failed) = aggregate_resources(state, hops).await;
+            aggregate_response(state, id, "resources", resources, failed)
+        }
+        "resources/templates/list" => {
+            let (templates, failed) = aggregate_resource_templates(state, hops).await;
+            aggregate_response(state, id, "resourceTemplates", templates, failed)
+        }
+        "resources/read" => read_resource(state, request).await,
+        "roots/list" => {
+            Response::success(id, json!({"roots": state.config.server.roots_json()}))
+        }
+        "completion/complete" => handle_completion(state, request).await,
+        "logging/setLevel" => handle_set_level(request),
+        "rpc.discover" => handle_discover(state, id),
+        // MCP liveness probe: an empty result, no side effects.
+        "ping" => Response::success(id, json!({})),
+        other => match split_namespace(other, state.config.server.separator()) {
+            // A namespaced `ping` (`files/ping`) probes one upstream through
+            // the router instead of the router itself.
+            Some((server, "ping")) => {
+                match state
+                    .registry
+                    .call(server, Request::new("ping", json!({})))
+                    .await
+                {
+                    Ok(mut response) => {
+                        response.id = id;
+                        response
+                    }
+                    Err(err) => upstream_error_response(id, err),
+                }
+            }
+            // Name the supported set so a client can self-correct instead of
+            // probing; disabled methods are omitted, same as rpc.discover
+            // would have shown them.
+            _ => Response::error_with_data(
+                id,
+                code::METHOD_NOT_FOUND,
+                format!("unknown method: {other}"),
+                json!({"supported": supported_methods(state)}),
+            ),
+        },
+    }
+}
+
+/// The method names a client may actually call: everything `dispatch` knows
+/// minus `disabled_methods`, for `-32601` hints.
+fn supported_methods(state: &RouterState) -> Vec<&'static str> {
+    METHODS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !state.config.server.disabled_methods.iter().any(|m| m == name))
+        .collect()
+}
+
+/// Every method `dispatch` knows, in dispatch order. The admin API lives on
+/// `/api` routes, not JSON-RPC, so nothing admin-only belongs here.
+const METHODS: &[(&str, &str)] = &[
+    ("initialize", "Handshake: protocol version, capabilities and tiers"),
+    ("tools/list", "Aggregated tool catalog, namespaced as server/tool"),
+    ("tools/call", "Invoke a namespaced tool on its upstream"),
+    ("tools/call_batch", "Invoke several tools concurrently; quota covers the whole batch"),
+    ("prompts/list", "Aggregated prompt catalog"),
+    ("prompts/get", "Fetch a namespaced prompt from its upstream"),
+    ("resources/list", "Aggregated resource catalog"),
+    ("resources/templates/list", "Aggregated resource templates"),
+    ("resources/read", "Read a resource by its mcp+router:// uri"),
+    ("roots/list", "Filesystem roots configured on the router"),
+    ("completion/complete", "Argument completion, routed by ref"),
+    ("logging/setLevel", "Swap the router's log filter at runtime"),
+    ("rpc.discover", "This listing"),
+    ("ping", "Liveness probe; empty result (server/ping probes one upstream)"),
+];
+
+/// `rpc.discover`: the supported method set, so tooling doesn't have to
+/// probe for `-32601`s. Disabled methods are left out — they answer as if
+/// the router never had them, so listing them would be a lie.
+fn handle_discover(state: &RouterState, id: Id) -> Response {
+    let methods: Vec<Value> = METHODS
+        .iter()
+        .filter(|(name, _)| !state.config.server.disabled_methods.iter().any(|m| m == name))
+        .map(|(name, description)| json!({"name": name, "description": description}))
+        .collect();
+    Response::success(id, json!({"methods": methods}))
+}
+
+async fn handle_initialize(state: &RouterState, request: Request) -> Response {
+    let id = request.id;
+    let tiers: Vec<Value> = TIERS
+        .iter()
+        .map(|(tier, tokens, requests)| {
+            json!({"tier": tier, "max_tokens": tokens, "max_requests": requests})
+        })
+        .collect();
+    // Run the handshake against any upstream we have not spoken to yet, so
+    // even the very first client initialize sees their serverInfo; after
+    // that the cached result answers for free. Failures just leave nulls.
+    let mut params = state.registry.initialize_params();
+    // A client that declares roots of its own gets them forwarded alongside
+    // the configured ones, so an fs upstream can scope itself to both.
+    if let Some(client_roots) = request.params.get("roots").and_then(Value::as_array) {
+        if !client_roots.is_empty() {
+            let mut roots = params
+                .get("roots")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            roots.extend(client_roots.iter().cloned());
+            params["roots"] = Value::Array(roots);
+        }
+    }
+    let probes = state
+        .registry
+        .handles()
+        .into_iter()
+        .filter(|handle| handle.initialize_info().is_none())
+        .map(|handle| {
+            let params = params.clone();
+            async move {
+                let _ = handle.call(Request::new("initialize", params)).await;
+            }
+        });
+    futures::future::join_all(probes).await;
+    // Name, kind and what the upstream reported — not `describe()`, which
+    // carries urls and header names that do not belong in a client handshake.
+    let upstreams: Vec<Value> = state
+        .registry
+        .handles()
+        .into_iter()
+        .map(|handle| {
+            let info = handle.initialize_info().unwrap_or(Value::Null);
+            json!({
+                "name": handle.name,
+                "kind": handle.kind,
+                "serverInfo": info.get("serverInfo").cloned().unwrap_or(Value::Null),
+                "capabilities": info.get("capabilities").cloned().unwrap_or(Value::Null),
+            })
+        })
+        .collect();
+    Response::success(
+        id,
+        json!({
+            "protocolVersion": mcp_core::PROTOCOL_VERSION,
+            "serverInfo": {"name": "mcp-router", "version": env!("CARGO_PKG_VERSION")},
+            "capabilities": {
+                "tools": {"listChanged": true},
+                "prompts": {},
+                "resources": {},
+            },
+            "subscription_tiers": tiers,
+            "upstreams": upstreams,
+        }),
+    )
+}
+
+/// `logging/setLevel`: swap the router's own log filter at runtime. The
+/// level sticks for the rest of the session (until the next call or restart).
+fn handle_set_level(request: Request) -> Response {
+    let id = request.id;
+    let Some(level) = request.params.get("level").and_then(Value::as_str) else {
+        return Response::error(id, code::INVALID_PARAMS, "missing level");
+    };
+    match crate::logging::set_level(level) {
+        Ok(directive) => Response::success(id, json!({"level": level, "filter": directive})),
+        Err(err) if err.starts_with("unknown log level") => {
+            Response::error(id, code::INVALID_PARAMS, err)
+        }
+        Err(err) => Response::error(id, code::INTERNAL_ERROR, err),
+    }
+}
+
+/// Merge every upstream's `tools/list` into one namespaced catalog. Returns
+/// the catalog plus the upstreams that failed to answer; what their failure
+/// does to the listing is decided by `aggregate_on_error` (under
+/// `include_stale`, a failed upstream's last cached catalog is merged in, but
+/// it is still reported as failed since its entries may be outdated).
+pub async fn aggregate_tools(state: &RouterState, hops: u64) -> (Vec<Value>, Vec<String>) {
+    let mut merged = Vec::new();
+    let mut failed = Vec::new();
+    for name in state.registry.names() {
+        match upstream_tools(state, &name, hops).await {
+            Ok(tools) => merged.extend(tools),
+            Err(err) => {
+                tracing::warn!(upstream = %name, %err, "tools/list failed");
+                state.metrics.upstream_errors.with_label_values(&[&name]).inc();
+                if state.config.server.aggregate_on_error == AggregateOnError::IncludeStale {
+                    if let Some(stale) = stale_upstream_tools(state, &name).await {
+                        merged.extend(stale);
+                    }
+                }
+                failed.push(name);
+            }
+        }
+    }
+    // Stable output: clients diff this list between calls.
+    merged.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    (merged, failed)
+}
+
+/// Wrap an aggregated listing per `aggregate_on_error`: clean listings pass
+/// through, lossy ones either name the failed upstreams in `_meta.partial`
+/// or (under `fail`) become a `-32001` error.
+fn aggregate_response(
+    state: &RouterState,
+    id: Id,
+    key: &str,
+    items: Vec<Value>,
+    failed: Vec<String>,
+) -> Response {
+    if failed.is_empty() {
+        return Response::success(id, json!({key: items}));
+    }
+    if state.config.server.aggregate_on_error == AggregateOnError::Fail {
+        return Response::error_with_data(
+            id,
+            code::UPSTREAM_ERROR,
+            format!("{key} listing failed for upstreams: {}", failed.join(", ")),
+            json!({"failed": failed}),
+        );
+    }
+    Response::success(id, json!({key: items, "_meta": {"partial": failed}}))
+}
+
+/// Fan-out params for a listing call: empty except for the inbound hop count,
+/// which rides along in `_meta` so a downstream router can stop a mesh loop.
+fn hop_params(hops: u64) -> Value {
+    if hops == 0 {
+        json!({})
+    } else {
+        json!({"_meta": {HOP_HEADER: hops}})
+    }
+}
+
+/// Namespaced tools for one upstream, served from the TTL cache when fresh.
+/// Cache misses are single-flighted per upstream: concurrent misses queue on
+/// a flight lock and all but the first are answered from the cache the first
+/// one filled.
+async fn upstream_tools(
+    state: &RouterState,
+    name: &str,
+    hops: u64,
+) -> Result<Vec<Value>, UpstreamError> {
+    if let Some(tools) = cached_upstream_tools(state, name).await {
+        return Ok(tools);
+    }
+
+    let flight = {
+        let mut flights = state.list_flights.lock().expect("flights lock");
+        flights.entry((name.to_string(), hops)).or_default().clone()
+    };
+    let _guard = flight.lock().await;
+    // Whoever held the flight lock before us has filled the cache by now.
+    if let Some(tools) = cached_upstream_tools(state, name).await {
+        return Ok(tools);
+    }
+
+    let handle = state
+        .registry
+        .get(name)
+        .ok_or_else(|| UpstreamError::Unknown(name.to_string()))?;
+    let response = handle.call(Request::new("tools/list", hop_params(hops))).await?;
+    let result = unwrap_result(response)?;
+    let mut tools = Vec::new();
+    if let Some(list) = result.get("tools").and_then(Value::as_array) {
+        for tool in list {
+            let mut tool = tool.clone();
+            if let Some(local) = tool.get("name").and_then(Value::as_str) {
+                if !handle.filters.tools.permits(local) {
+                    continue;
+                }
+                let namespaced = format!("{name}{sep}{local}", sep = state.config.server.separator());
+                tool["name"] = Value::String(namespaced);
+            }
+            tools.push(tool);
+        }
+    }
+    state.tools_cache.write().await.insert(
+        name.to_string(),
+        CachedCatalog {
+            fetched: Instant::now(),
+            tools: tools.clone(),
+        },
+    );
+    Ok(tools)
+}
+
+/// The cached catalog for one upstream, if it is still fresh.
+async fn cached_upstream_tools(state: &RouterState, name: &str) -> Option<Vec<Value>> {
+    let cache = state.tools_cache.read().await;
+    let entry = cache.get(name)?;
+    (entry.fetched.elapsed() < state.cache_ttl()).then(|| entry.tools.clone())
+}
+
+/// The cached catalog for one upstream however old, for `include_stale`.
+async fn stale_upstream_tools(state: &RouterState, name: &str) -> Option<Vec<Value>> {
+    let cache = state.tools_cache.read().await;
+    cache.get(name).map(|entry| entry.tools.clone())
+}
+
+pub async fn aggregate_prompts(state: &RouterState, hops: u64) -> (Vec<Value>, Vec<String>) {
+    let mut merged = Vec::new();
+    let mut failed = Vec::new();
+    for handle in state.registry.handles() {
+        let name = &handle.name;
+        let response = handle
+            .call(Request::new("prompts/list", hop_params(hops)))
+            .await;
+        match response.map(unwrap_result) {
+            Ok(Ok(result)) => {
+                if let Some(list) = result.get("prompts").and_then(Value::as_array) {
+                    for prompt in list {
+                        let mut prompt = prompt.clone();
+                        if let Some(local) = prompt.get("name").and_then(Value::as_str) {
+                            if !handle.filters.prompts.permits(local) {
+                                continue;
+                            }
+                            prompt["name"] = Value::String(format!(
+                                "{name}{sep}{local}",
+                                sep = state.config.server.separator()
+                            ));
+                        }
+                        // `arguments` must be the spec's array of descriptors;
+                        // a malformed declaration is dropped rather than
+                        // forwarded for clients to choke on.
+                        if prompt
+                            .get("arguments")
+                            .is_some_and(|args| !args.is_array())
+                        {
+                            tracing::warn!(
+                                upstream = %name,
+                                prompt = %prompt["name"],
+                                "dropping non-array prompt arguments declaration"
+                            );
+                            prompt.as_object_mut().expect("prompt object").remove("arguments");
+                        }
+                        merged.push(prompt);
+                    }
+                }
+            }
+            Ok(Err(err)) | Err(err) => {
+                tracing::warn!(upstream = %name, %err, "prompts/list failed");
+                failed.push(name.clone());
+            }
+        }
+    }
+    merged.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    (merged, failed)
+}
+
+pub async fn aggregate_resources(state: &RouterState, hops: u64) -> (Vec<Value>, Vec<String>) {
+    let mut merged = Vec::new();
+    let mut failed = Vec::new();
+    for handle in state.registry.handles() {
+        let name = &handle.name;
+        let response = handle
+            .call(Request::new("resources/list", hop_params(hops)))
+            .await;
+        match response.map(unwrap_result) {
+            Ok(Ok(result)) => {
+                if let Some(list) = result.get("resources").and_then(Value::as_array) {
+                    for resource in list {
+                        let mut resource = resource.clone();
+                        if let Some(uri) = resource.get("uri").and_then(Value::as_str) {
+                            // Filtered on the upstream URI, before re-encoding.
+                            if !handle.filters.resources.permits(uri) {
+                                continue;
+                            }
+                            resource["uri"] = Value::String(encode_resource_uri(name, uri));
+                        }
+                        merged.push(resource);
+                    }
+                }
+            }
+            Ok(Err(err)) | Err(err) => {
+                tracing::warn!(upstream = %name, %err, "resources/list failed");
+                failed.push(name.clone());
+            }
+        }
+    }
+    merged.sort_by(|a, b| a["uri"].as_str().cmp(&b["uri"].as_str()));
+    (merged, failed)
+}
+
+pub async fn aggregate_resource_templates(
+    state: &RouterState,
+    hops: u64,
+) -> (Vec<Value>, Vec<String>) {
+    let mut merged = Vec::new();
+    let mut failed = Vec::new();
+    for name in state.registry.names() {
+        let response = state
+            .registry
+            .call(&name, Request::new("resources/templates/list", hop_params(hops)))
+            .await;
+        match response.map(unwrap_result) {
+            Ok(Ok(result)) => {
+                if let Some(list) = result.get("resourceTemplates").and_then(Value::as_array) {
+                    for template in list {
+                        let mut template = template.clone();
+                        if let Some(uri) = template.get("uriTemplate").and_then(Value::as_str) {
+                            template["uriTemplate"] =
+                                Value::String(encode_resource_template(&name, uri));
+                        }
+                        merged.push(template);
+                    }
+                }
+            }
+            Ok(Err(err)) | Err(err) => {
+                tracing::warn!(upstream = %name, %err, "resources/templates/list failed");
+                failed.push(name.clone());
+            }
+        }
+    }
+    merged.sort_by(|a, b| a["uriTemplate"].as_str().cmp(&b["uriTemplate"].as_str()));
+    (merged, failed)
+}
+
+/// Wrap an upstream resource URI into the router's namespaced scheme.
+pub fn encode_resource_uri(server: &str, uri: &str) -> String {
+    format!(
+        "{RESOURCE_SCHEME}{server}/{}",
+        utf8_percent_encode(uri, NON_ALPHANUMERIC)
+    )
+}
+
+/// Like [`encode_resource_uri`] but leaves `{placeholders}` intact, so a
+/// client can substitute into the template and hand the result straight to
+/// `resources/read` (which percent-decodes back to the upstream URI).
+pub fn encode_resource_template(server: &str, template: &str) -> String {
+    const TEMPLATE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'{').remove(b'}');
+    format!(
+        "{RESOURCE_SCHEME}{server}/{}",
+        utf8_percent_encode(template, TEMPLATE_SET)
+    )
+}
+
+/// Undo [`encode_resource_uri`], returning `(server, upstream_uri)`.
+pub fn decode_resource_uri(uri: &str) -> Option<(String, String)> {
+    let rest = uri.strip_prefix(RESOURCE_SCHEME)?;
+    let (server, encoded) = rest.split_once('/')?;
+    let decoded = percent_decode_str(encoded).decode_utf8().ok()?;
+    Some((server.to_string(), decoded.into_owned()))
+}
+
+pub async fn handle_tool_call(state: &RouterState, request: Request) -> Response {
+    let id = request.id.clone();
+    let Some(name) = request.params.get("name").and_then(Value::as_str) else {
+        return Response::error(id, code::INVALID_PARAMS, "missing tool name");
+    };
+    let sep = state.config.server.separator();
+    let Some((server, tool)) = split_namespace(name, sep) else {
+        return Response::error(
+            id,
+            code::INVALID_PARAMS,
+            format!("tool name must be namespaced as server{sep}tool"),
+        );
+    };
+
+    // A namespace matching no upstream is the caller's routing mistake, not
+    // an upstream failure, so it gets `-32601` rather than `-32001`.
+    let Some(handle) = state.registry.get(server) else {
+        return Response::error(
+            id,
+            code::METHOD_NOT_FOUND,
+            format!("unknown upstream: {server}"),
+        );
+    };
+    // A filtered-out tool is indistinguishable from one that does not exist.
+    if !handle.filters.tools.permits(tool) {
+        return Response::error(id, code::METHOD_NOT_FOUND, format!("unknown tool: {name}"));
+    }
+
+    let user_id = request
+        .params
+        .pointer("/_meta/user_id")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let arguments = request
+        .params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    // Batch entries and direct callers bypass the HTTP body limit, so cap
+    // what we are willing to forward to an upstream here as well.
+    let size = serde_json::to_vec(&arguments).map(|v| v.len()).unwrap_or(0);
+    let max = state.config.server.max_request_bytes;
+    if size > max {
+        return Response::error_with_data(
+            id,
+            code::INVALID_REQUEST,
+            "tools/call arguments too large",
+            json!({"size": size, "max_request_bytes": max}),
+        );
+    }
+    // Forward the caller's progressToken so the upstream's
+    // notifications/progress frames (routed to the event hub) carry it.
+    let progress_token = request.params.pointer("/_meta/progressToken").cloned();
+    // An explicit caller-provided estimate wins; otherwise derive one from
+    // the arguments so prompt-heavy calls are never free against the quota.
+    let estimated_tokens = request
+        .params
+        .pointer("/usage/tokens")
+        .and_then(Value::as_i64)
+        .unwrap_or_else(|| state.estimator.estimate(name, &arguments));
+    // Weight by the tool's configured cost multiplier so expensive tools
+    // draw down the shared token budget faster than cheap ones.
+    let multiplier = handle.cost_multiplier(tool);
+    let estimated_tokens = weight_tokens(estimated_tokens, multiplier);
+
+    // Quota accounting applies when persistence is enabled and the caller
+    // identified themselves; a store-less router is a pure proxy.
+    let accounting = match (&state.store, &user_id) {
+        (Some(store), Some(user_id)) => Some((store, user_id.as_str())),
+        _ => None,
+    };
+
+    // Reserve quota up front in one atomic statement; concurrent calls for
+    // the same user cannot jointly over-spend the budget.
+    if let Some((store, user_id)) = accounting {
+        if let Err(err) = store.try_consume(user_id, estimated_tokens).await {
+            return enforcement_response(id, err);
+        }
+    }
+    // Snapshot for the recent-calls buffer before `arguments` moves into the
+    // forwarded params.
+    let recorded_arguments = state
+        .config
+        .server
+        .recent_calls_arguments
+        .then(|| redact(&arguments));
+    let mut forwarded_params = json!({"name": tool, "arguments": arguments});
+    let mut forwarded_meta = serde_json::Map::new();
+    if let Some(token) = progress_token {
+        forwarded_meta.insert("progressToken".into(), token);
+    }
+    // Keep the correlation id attached on the upstream leg.
+    if let Some(request_id) = request.params.pointer("/_meta/request_id") {
+        forwarded_meta.insert("request_id".into(), request_id.clone());
+    }
+    if !forwarded_meta.is_empty() {
+        forwarded_params["_meta"] = Value::Object(forwarded_meta);
+    }
+    let started = Instant::now();
+    let forwarded = Request::new("tools/call", forwarded_params);
+    let response = match state.registry.call(server, forwarded).await {
+        Ok(response) => response,
+        Err(err) => {
+            state
+                .metrics
+                .upstream_errors
+                .with_label_values(&[server])
+                .inc();
+            if let Some((store, user_id)) = accounting {
+                if let Err(err) = store.release_reservation(user_id, estimated_tokens).await {
+                    tracing::warn!(user = %user_id, %err, "failed to release reservation");
+                }
+            }
+            let response = upstream_error_response(id, err);
+            state.record_recent_call(
+                name,
+                user_id.as_deref(),
+                started.elapsed(),
+                call_status(&response),
+                recorded_arguments,
+            );
+            return response;
+        }
+    };
+
+    let mut quota = None;
+    if let Some((store, user_id)) = accounting {
+        if response.is_error() {
+            if let Err(err) = store.release_reservation(user_id, estimated_tokens).await {
+                tracing::warn!(user = %user_id, %err, "failed to release reservation");
+            }
+        } else {
+            // Reconcile to the actual spend if the upstream reported one.
+            let actual_tokens = response
+                .result
+                .as_ref()
+                .and_then(|r| r.pointer("/usage/tokens"))
+                .and_then(Value::as_i64)
+                .map(|tokens| weight_tokens(tokens, multiplier))
+                .unwrap_or(estimated_tokens);
+            if let Err(err) = store
+                .record_usage(user_id, name, actual_tokens, estimated_tokens)
+                .await
+            {
+                tracing::warn!(user = %user_id, %err, "failed to record usage");
+            }
+            // Tell the caller where they stand after this call, so UIs can
+            // show a usage bar instead of discovering the limit the hard way.
+            if let Ok(Some(record)) = store.refresh(user_id).await {
+                maybe_warn_quota(state, user_id, &record, actual_tokens);
+                quota = Some(json!({
+                    "tokens_remaining": (record.max_tokens - record.tokens_used).max(0),
+                    "requests_remaining": (record.max_requests - record.requests_used).max(0),
+                    "reset_at": record.reset_at,
+                }));
+            }
+        }
+    }
+
+    // Hand the upstream's result back under the client's original id,
+    // capped to `max_result_bytes` on the way out.
+    let mut response = enforce_result_cap(state, Response { id, ..response });
+    if let (Some(quota), Some(result)) = (quota, response.result.as_mut()) {
+        if result.is_object() {
+            result["_meta"]["quota"] = quota;
+        }
+    }
+    if state.config.server.validate_output_schemas && response.error.is_none() {
+        let warning = match response.result.as_ref().filter(|r| r.is_object()) {
+            Some(result) => output_schema_warning(state, server, name, result).await,
+            None => None,
+        };
+        if let Some(warning) = warning {
+            tracing::warn!(tool = %name, %warning, "result does not match declared outputSchema");
+            if let Some(result) = response.result.as_mut() {
+                result["_meta"]["schema_warning"] = json!(warning);
+            }
+        }
+    }
+    state.record_recent_call(
+        name,
+        user_id.as_deref(),
+        started.elapsed(),
+        call_status(&response),
+        recorded_arguments,
+    );
+    response
+}
+
+/// `tools/call_batch`: invoke several tools in one request. Entries dispatch
+/// concurrently — at most `batch_parallelism` in flight — and results come
+/// back per entry, in request order. Quota is reserved for the whole batch
+/// in one atomic statement, so a combined estimate the budget cannot cover
+/// rejects the batch wholesale before any entry runs; afterwards each
+/// entry's share is reconciled (or released, for entries that failed)
+/// exactly as a single call's would be.
+pub async fn handle_tool_call_batch(state: &RouterState, request: Request) -> Response {
+    let id = request.id.clone();
+    let Some(calls) = request.params.get("calls").and_then(Value::as_array) else {
+        return Response::error(id, code::INVALID_PARAMS, "missing calls array");
+    };
+    let calls = calls.clone();
+    let user_id = request
+        .params
+        .pointer("/_meta/user_id")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    // Per-entry estimates, weighted like single calls. An entry that does
+    // not resolve to an upstream stays at zero: it will fail without costing
+    // anything, and its share releases cleanly below.
+    let sep = state.config.server.separator();
+    let mut estimates = Vec::with_capacity(calls.len());
+    for entry in &calls {
+        let name = entry.get("name").and_then(Value::as_str).unwrap_or_default();
+        let arguments = entry.get("arguments").cloned().unwrap_or_else(|| json!({}));
+        let resolved = split_namespace(name, sep)
+            .and_then(|(server, tool)| state.registry.get(server).map(|handle| (handle, tool)));
+        let (estimate, multiplier) = match resolved {
+            Some((handle, tool)) => {
+                let tokens = entry
+                    .pointer("/usage/tokens")
+                    .and_then(Value::as_i64)
+                    .unwrap_or_else(|| state.estimator.estimate(name, &arguments));
+                let multiplier = handle.cost_multiplier(tool);
+                (weight_tokens(tokens, multiplier), multiplier)
+            }
+            None => (0, 1.0),
+        };
+        estimates.push((estimate, multiplier));
+    }
+    let total: i64 = estimates.iter().map(|(estimate, _)| estimate).sum();
+
+    let accounting = match (&state.store, &user_id) {
+        (Some(store), Some(user_id)) => Some((store, user_id.as_str())),
+        _ => None,
+    };
+    if let Some((store, user_id)) = accounting {
+        if let Err(err) = store
+            .try_consume_batch(user_id, total, calls.len() as i64)
+            .await
+        {
+            return enforcement_response(id, err);
+        }
+    }
+
+    let parallelism = state.config.server.batch_parallelism;
+    let gate = (parallelism > 0).then(|| Arc::new(tokio::sync::Semaphore::new(parallelism)));
+    let dispatches = calls.iter().map(|entry| {
+        let gate = gate.clone();
+        let mut params = entry.clone();
+        // The batch reservation already covers this user; a user_id on the
+        // entry would charge them a second time.
+        if let Some(meta) = params.get_mut("_meta").and_then(Value::as_object_mut) {
+            meta.remove("user_id");
+        }
+        async move {
+            let _slot = match &gate {
+                Some(gate) => Some(gate.acquire().await.expect("batch gate never closed")),
+                None => None,
+            };
+            handle_tool_call(state, Request::new("tools/call", params)).await
+        }
+    });
+    let responses = futures::future::join_all(dispatches).await;
+
+    let mut results = Vec::with_capacity(responses.len());
+    for ((entry, response), (estimate, multiplier)) in calls.iter().zip(responses).zip(&estimates) {
+        let name = entry.get("name").and_then(Value::as_str).unwrap_or_default();
+        if let Some((store, user_id)) = accounting {
+            if response.is_error() {
+                if let Err(err) = store.release_reservation(user_id, *estimate).await {
+                    tracing::warn!(user = %user_id, %err, "failed to release reservation");
+                }
+            } else {
+                let actual = response
+                    .result
+                    .as_ref()
+                    .and_then(|r| r.pointer("/usage/tokens"))
+                    .and_then(Value::as_i64)
+                    .map(|tokens| weight_tokens(tokens, *multiplier))
+                    .unwrap_or(*estimate);
+                if let Err(err) = store.record_usage(user_id, name, actual, *estimate).await {
+                    tracing::warn!(user = %user_id, %err, "failed to record usage");
+                }
+            }
+        }
+        results.push(match response.error {
+            Some(error) => json!({"name": name, "error": error}),
+            None => json!({"name": name, "result": response.result.unwrap_or(Value::Null)}),
+        });
+    }
+    Response::success(id, json!({"results": results}))
+}
+
+/// Publish a `quota_warning` event if this call pushed the user's token or
+/// request usage past the configured soft limit (`database.quota_warn_ratio`).
+///
+/// Only the crossing call fires: usage already past the threshold before this
+/// call stays quiet, so an idle-above-the-line user is not spammed on every
+/// request.
+fn maybe_warn_quota(
+    state: &RouterState,
+    user_id: &str,
+    record: &crate::store::SubscriptionRecord,
+    spent_tokens: i64,
+) {
+    let ratio = state.config.database.quota_warn_ratio;
+    if ratio <= 0.0 {
+        return;
+    }
+    let crossed = |used: i64, spent: i64, max: i64| {
+        let threshold = (max as f64 * ratio).ceil() as i64;
+        used >= threshold && used - spent < threshold
+    };
+    let tokens_crossed = crossed(record.tokens_used, spent_tokens, record.max_tokens);
+    let requests_crossed = crossed(record.requests_used, 1, record.max_requests);
+    if tokens_crossed || requests_crossed {
+        state.hub.publish(RouterEvent::new(
+            "quota_warning",
+            None,
+            json!({
+                "user": user_id,
+                "threshold": ratio,
+                "tokens_remaining": (record.max_tokens - record.tokens_used).max(0),
+                "requests_remaining": (record.max_requests - record.requests_used).max(0),
+            }),
+        ));
+    }
+}
+
+/// The JSON type name of `value`, for `-32602` error data.
+/// `validate_output_schemas`: check a successful call's `structuredContent`
+/// against the `outputSchema` the tool declared in its catalog entry (looked
+/// up through the tools cache). Returns the warning to attach, or `None` when
+/// the tool declares no schema or the result conforms. Never an error: the
+/// result may still be useful, and the schema itself may be what is wrong.
+async fn output_schema_warning(
+    state: &RouterState,
+    server: &str,
+    tool_name: &str,
+    result: &Value,
+) -> Option<String> {
+    let tools = upstream_tools(state, server, 0).await.ok()?;
+    let schema = tools
+        .iter()
+        .find(|tool| tool["name"] == tool_name)?
+        .get("outputSchema")?
+        .clone();
+    match result.get("structuredContent") {
+        Some(content) => schema_mismatch(&schema, content, "structuredContent"),
+        None => Some("tool declares an outputSchema but the result has no structuredContent".into()),
+    }
+}
+
+/// Minimal structural check of `value` against a declared schema: `type`,
+/// `required`, and recursion into `properties`. Deliberately not full JSON
+/// Schema — an over-eager validator would turn keywords it half-understands
+/// into false warnings, so anything beyond this shape is accepted.
+fn schema_mismatch(schema: &Value, value: &Value, path: &str) -> Option<String> {
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let actual = json_type(value);
+        let matches = match expected {
+            "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+            other => other == actual,
+        };
+        if !matches {
+            return Some(format!("{path}: expected {expected}, got {actual}"));
+        }
+    }
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        for key in required.iter().filter_map(Value::as_str) {
+            if value.get(key).is_none() {
+                return Some(format!("{path}: missing required property {key:?}"));
+            }
+        }
+    }
+    if let (Some(properties), Some(object)) = (
+        schema.get("properties").and_then(Value::as_object),
+        value.as_object(),
+    ) {
+        for (key, subschema) in properties {
+            if let Some(child) = object.get(key) {
+                if let Some(mismatch) = schema_mismatch(subschema, child, &format!("{path}.{key}")) {
+                    return Some(mismatch);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// `"ok"` for a success, otherwise the error code as a string.
+fn call_status(response: &Response) -> String {
+    match &response.error {
+        None => "ok".into(),
+        Some(err) => err.code.to_string(),
+    }
+}
+
+/// Mask string values while keeping the argument structure readable, so the
+/// recent-calls buffer never retains user content.
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::String(_) => Value::String("***".into()),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::Object(map) => {
+            Value::Object(map.iter().map(|(k, v)| (k.clone(), redact(v))).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+/// Apply a tool's cost multiplier to a token count, rounding up so a
+/// fractional weight never rounds a real call down to free.
+fn weight_tokens(tokens: i64, multiplier: f64) -> i64 {
+    (tokens as f64 * multiplier).ceil() as i64
+}
+
+/// Enforce `max_result_bytes` on a successful `tools/call` response. Usage
+/// has already been recorded by this point — the upstream did the work even
+/// when the router refuses to relay the oversized result.
+fn enforce_result_cap(state: &RouterState, mut response: Response) -> Response {
+    let max = state.config.server.max_result_bytes;
+    if max == 0 {
+        return response;
+    }
+    let Some(result) = response.result.as_mut() else {
+        return response;
+    };
+    let size = serde_json::to_vec(&*result).map(|v| v.len()).unwrap_or(0);
+    if size <= max {
+        return response;
+    }
+    match state.config.server.oversize_policy {
+        OversizePolicy::Reject => Response::error_with_data(
+            response.id,
+            code::RESULT_TOO_LARGE,
+            "result too large",
+            json!({"size": size, "max_result_bytes": max}),
+        ),
+        OversizePolicy::Truncate => {
+            // Cut string fields at ever smaller limits until the result
+            // fits. A result still oversized past the smallest limit is not
+            // string-heavy and goes out as-is rather than mangled further.
+            let mut trimmed = size;
+            for limit in [65536, 16384, 4096, 1024, 256, 64] {
+                if trimmed <= max {
+                    break;
+                }
+                truncate_strings(result, limit);
+                trimmed = serde_json::to_vec(&*result).map(|v| v.len()).unwrap_or(0);
+            }
+            if result.is_object() {
+                result["_meta"]["truncated"] = json!(true);
+                result["_meta"]["original_bytes"] = json!(size);
+            }
+            tracing::warn!(size, max, "truncated oversized tools/call result");
+            response
+        }
+    }
+}
+
+/// Shorten every string in `value` to at most `limit` bytes, cutting on a
+/// char boundary.
+fn truncate_strings(value: &mut Value, limit: usize) {
+    match value {
+        Value::String(s) if s.len() > limit => {
+            let mut end = limit;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            s.truncate(end);
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| truncate_strings(item, limit)),
+        Value::Object(map) => map.values_mut().for_each(|item| truncate_strings(item, limit)),
+        _ => {}
+    }
+}
+
+async fn handle_prompt_get(state: &RouterState, request: Request) -> Response {
+    let id = request.id.clone();
+    let Some(name) = request.params.get("name").and_then(Value::as_str) else {
+        return Response::error(id, code::INVALID_PARAMS, "missing prompt name");
+    };
+    let sep = state.config.server.separator();
+    let Some((server, prompt)) = split_namespace(name, sep) else {
+        return Response::error(
+            id,
+            code::INVALID_PARAMS,
+            format!("prompt name must be namespaced as server{sep}prompt"),
+        );
+    };
+    let Some(handle) = state.registry.get(server) else {
+        return Response::error(
+            id,
+            code::METHOD_NOT_FOUND,
+            format!("unknown upstream: {server}"),
+        );
+    };
+    if !handle.filters.prompts.permits(prompt) {
+        return Response::error(id, code::METHOD_NOT_FOUND, format!("unknown prompt: {name}"));
+    }
+    // Forward the params wholesale with the namespace stripped from `name`:
+    // the client's `arguments` (and any `_meta`) must reach the upstream.
+    let mut forwarded_params = request.params.clone();
+    forwarded_params["name"] = json!(prompt);
+    let forwarded = Request::new("prompts/get", forwarded_params);
+    match state.registry.call(server, forwarded).await {
+        Ok(response) => Response { id, ..response },
+        Err(err) => upstream_error_response(id, err),
+    }
+}
+
+/// Hard cap on `resolve_depth`, so one read cannot fan out without bound.
+const MAX_RESOLVE_DEPTH: u64 = 4;
+
+/// `resources/read`, plus the opt-in `resolve_depth` param: when it is
+/// greater than zero, router URIs embedded in the returned text are read too
+/// and their contents appended to the result, saving the client a round trip
+/// per reference. Zero (the default) forwards the read untouched.
+pub async fn read_resource(state: &RouterState, request: Request) -> Response {
+    let depth = request
+        .params
+        .get("resolve_depth")
+        .and_then(Value::as_u64)
+        .unwrap_or(0)
+        .min(MAX_RESOLVE_DEPTH);
+    let origin = request
+        .params
+        .get("uri")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let mut response = read_resource_once(state, request).await;
+    if depth > 0 {
+        if let Some(result) = response.result.as_mut() {
+            resolve_embedded(state, &origin, result, depth).await;
+        }
+    }
+    response
+}
+
+/// One forwarded `resources/read`, through the content cache, with no
+/// embedded-URI resolution.
+async fn read_resource_once(state: &RouterState, request: Request) -> Response {
+    let id = request.id.clone();
+    let Some(uri) = request.params.get("uri").and_then(Value::as_str) else {
+        return Response::error(id, code::INVALID_PARAMS, "missing uri");
+    };
+    let Some((server, upstream_uri)) = decode_resource_uri(uri) else {
+        return Response::error(
+            id,
+            code::INVALID_PARAMS,
+            format!("uri must use the {RESOURCE_SCHEME} scheme"),
+        );
+    };
+    let Some(handle) = state.registry.get(&server) else {
+        return Response::error(
+            id,
+            code::METHOD_NOT_FOUND,
+            format!("unknown upstream: {server}"),
+        );
+    };
+    if !handle.filters.resources.permits(&upstream_uri) {
+        return Response::error(id, code::INVALID_PARAMS, format!("unknown resource: {uri}"));
+    }
+    let ttl = Duration::from_secs(state.config.server.resource_cache_ttl_secs);
+    let no_cache = request
+        .params
+        .get("no-cache")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let mut validators = None;
+    if !ttl.is_zero() && !no_cache {
+        let mut cache = state.resource_cache.write().await;
+        if let Some(entry) = cache.get_mut(uri) {
+            if entry.fetched.elapsed() < ttl {
+                entry.last_used = Instant::now();
+                return Response::success(id, entry.result.clone());
+            }
+            // Stale: offer the validators so the upstream can answer
+            // `notModified` instead of re-sending the content.
+            validators = Some((entry.etag.clone(), entry.last_modified.clone()));
+        }
+    }
+    let mut params = json!({"uri": upstream_uri});
+    if let Some((etag, last_modified)) = validators {
+        if let Some(etag) = etag {
+            params["ifNoneMatch"] = json!(etag);
+        }
+        if let Some(last_modified) = last_modified {
+            params["ifModifiedSince"] = json!(last_modified);
+        }
+    }
+    let forwarded = Request::new("resources/read", params);
+    match state.registry.call(&server, forwarded).await {
+        Ok(response) => {
+            if !ttl.is_zero() {
+                if let Some(result) = &response.result {
+                    if result.get("notModified").and_then(Value::as_bool) == Some(true) {
+                        let mut cache = state.resource_cache.write().await;
+                        if let Some(entry) = cache.get_mut(uri) {
+                            entry.fetched = Instant::now();
+                            entry.last_used = entry.fetched;
+                            return Response::success(id, entry.result.clone());
+                        }
+                    } else {
+                        state.cache_resource(uri, result).await;
+                    }
+                }
+            }
+            Response { id, ..response }
+        }
+        Err(err) => upstream_error_response(id, err),
+    }
+}
+
+/// The follow-up for a `resolve_depth` > 0 read: walks the router URIs
+/// embedded in text contents, reading each and appending its contents to
+/// `result` under the URI it was referenced by (so the caller can correlate an
+/// inlined entry with the reference that pulled it in). A visited set seeded
+/// with the original URI reads every resource at most once, so cyclic
+/// references terminate; a reference that fails to read is logged and skipped
+/// rather than failing the read that embedded it.
+async fn resolve_embedded(state: &RouterState, origin: &str, result: &mut Value, depth: u64) {
+    let mut visited: HashSet<String> = HashSet::from([origin.to_string()]);
+    let mut frontier: Vec<(String, u64)> = embedded_uris(result, &mut visited)
+        .into_iter()
+        .map(|uri| (uri, depth - 1))
+        .collect();
+    while let Some((uri, remaining)) = frontier.pop() {
+        let read = Request::new("resources/read", json!({"uri": uri}));
+        let response = read_resource_once(state, read).await;
+        let Some(mut inlined) = response.result else {
+            let error = response.error.map(|err| err.message).unwrap_or_default();
+            tracing::warn!(%uri, %error, "embedded resource read failed");
+            continue;
+        };
+        if remaining > 0 {
+            for next in embedded_uris(&inlined, &mut visited) {
+                frontier.push((next, remaining - 1));
+            }
+        }
+        if let Some(contents) = inlined.get_mut("contents").and_then(Value::as_array_mut) {
+            for mut entry in contents.drain(..) {
+                entry["uri"] = json!(uri);
+                if let Some(merged) = result.get_mut("contents").and_then(Value::as_array_mut) {
+                    merged.push(entry);
+                }
+            }
+        }
+    }
+}
+
+/// Router URIs embedded in the text contents of a `resources/read` result,
+/// deduplicated against (and recorded in) `visited`.
+fn embedded_uris(result: &Value, visited: &mut HashSet<String>) -> Vec<String> {
+    let mut found = Vec::new();
+    let Some(contents) = result.get("contents").and_then(Value::as_array) else {
+        return found;
+    };
+    for entry in contents {
+        let Some(text) = entry.get("text").and_then(Value::as_str) else {
+            continue;
+        };
+        for uri in find_router_uris(text) {
+            if visited.insert(uri.clone()) {
+                found.push(uri);
+            }
+        }
+    }
+    found
+}
+
+/// Scan free text for `mcp+router://` URIs. Encoded URIs are alphanumerics
+/// plus `%` and the scheme's own punctuation, so each one ends at the first
+/// character outside that set.
+fn find_router_uris(text: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(RESOURCE_SCHEME) {
+        let tail = &rest[start..];
+        let end = tail
+            .find(|c: char| !c.is_ascii_alphanumeric() && !matches!(c, '%' | '+' | ':' | '/'))
+            .unwrap_or(tail.len());
+        found.push(tail[..end].to_string());
+        rest = &tail[end..];
+    }
+    found
+}
+
+/// `completion/complete`: route to the upstream named in the `ref`,
+/// de-namespacing a prompt name or decoding a router resource URI so the
+/// upstream sees its own identifiers. The `argument` passes through untouched.
+async fn handle_completion(state: &RouterState, request: Request) -> Response {
+    let id = request.id.clone();
+    let Some(reference) = request.params.get("ref") else {
+        return Response::error(id, code::INVALID_PARAMS, "missing ref");
+    };
+    let mut forwarded_params = request.params.clone();
+    let server = match reference.get("type").and_then(Value::as_str) {
+        Some("ref/prompt") => {
+            let Some(name) = reference.get("name").and_then(Value::as_str) else {
+                return Response::error(id, code::INVALID_PARAMS, "missing ref name");
+            };
+            let sep = state.config.server.separator();
+            let Some((server, prompt)) = split_namespace(name, sep) else {
+                return Response::error(
+                    id,
+                    code::INVALID_PARAMS,
+                    format!("prompt ref must be namespaced as server{sep}prompt"),
+                );
+            };
+            forwarded_params["ref"]["name"] = json!(prompt);
+            server.to_string()
+        }
+        Some("ref/resource") => {
+            let Some(uri) = reference.get("uri").and_then(Value::as_str) else {
+                return Response::error(id, code::INVALID_PARAMS, "missing ref uri");
+            };
+            let Some((server, upstream_uri)) = decode_resource_uri(uri) else {
+                return Response::error(
+                    id,
+                    code::INVALID_PARAMS,
+                    format!("uri must use the {RESOURCE_SCHEME} scheme"),
+                );
+            };
+            forwarded_params["ref"]["uri"] = json!(upstream_uri);
+            server
+        }
+        other => {
+            return Response::error(
+                id,
+                code::INVALID_PARAMS,
+                format!("unsupported ref type: {}", other.unwrap_or("missing")),
+            )
+        }
+    };
+    let forwarded = Request::new("completion/complete", forwarded_params);
+    match state.registry.call(&server, forwarded).await {
+        Ok(response) => Response { id, ..response },
+        Err(err) => upstream_error_response(id, err),
+    }
+}
+
+/// Extract `result` or turn an upstream-side JSON-RPC error into
+/// [`UpstreamError::Protocol`].
+fn unwrap_result(response: Response) -> Result<Value, UpstreamError> {
+    if let Some(err) = response.error {
+        return Err(UpstreamError::Protocol(err.message));
+    }
+    Ok(response.result.unwrap_or(Value::Null))
+}
+
+fn upstream_error_response(id: Id, err: UpstreamError) -> Response {
+    match err {
+        // The handlers check the namespace before dispatch, but an upstream
+        // can be deregistered mid-flight; classify it as routing either way.
+        UpstreamError::Unknown(name) => Response::error(
+            id,
+            code::METHOD_NOT_FOUND,
+            format!("unknown upstream: {name}"),
+        ),
+        UpstreamError::RateLimited(retry_in) => Response::error_with_data(
+            id,
+            code::UPSTREAM_ERROR,
+            "upstream rate limited",
+            json!({
+                "reason": "rate_limited",
+                "retry_after_ms": retry_in.as_millis() as u64,
+            }),
+        ),
+        UpstreamError::CircuitOpen(retry_in) => Response::error_with_data(
+            id,
+            code::CIRCUIT_OPEN,
+            "upstream circuit open",
+            json!({
+                "reason": "circuit_open",
+                "retry_after_ms": retry_in.as_millis() as u64,
+            }),
+        ),
+        other => Response::error(id, code::UPSTREAM_ERROR, format!("upstream error: {other}")),
+    }
+}
+
+/// Rejections carry a machine-readable `error.data` envelope (`reason` plus
+/// `limit`/`remaining` where a quota applies) so clients can back off
+/// intelligently instead of parsing messages.
+fn enforcement_response(id: Id, err: EnforcementError) -> Response {
+    let data = match &err {
+        EnforcementError::NoSubscription(_) => json!({"reason": "no_subscription"}),
+        EnforcementError::Inactive(_) => json!({"reason": "user_inactive"}),
+        EnforcementError::TokenQuota { limit, used } => json!({
+            "reason": "token_quota",
+            "limit": limit,
+            "remaining": (limit - used).max(0),
+        }),
+        EnforcementError::RequestQuota { limit, used } => json!({
+            "reason": "request_quota",
+            "limit": limit,
+            "remaining": (limit - used).max(0),
+        }),
+        EnforcementError::DailyRequestQuota { limit, used } => json!({
+            "reason": "daily_request_quota",
+            "limit": limit,
+            "remaining": (limit - used).max(0),
+        }),
+        EnforcementError::Db(db) => {
+            return Response::error(id, code::INTERNAL_ERROR, format!("store error: {db}"))
+        }
+    };
+    Response::error_with_data(id, code::QUOTA_EXCEEDED, err.to_string(), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{ProviderStore, SubscriptionStore};
+
+    pub(crate) async fn test_state() -> RouterState {
+        test_state_with(Config::default()).await
+    }
+
+    async fn test_state_with(config: Config) -> RouterState {
+        let store = SubscriptionStore::new("sqlite::memory:").await.unwrap();
+        store.run_migrations().await.unwrap();
+        let providers = ProviderStore::new(store.pool().clone());
+        let registry = Arc::new(UpstreamRegistry::new(Duration::from_secs(2)));
+        RouterState::new(config, registry, Some(store), Some(providers))
+    }
+
+    fn fake_tools_upstream(state: &RouterState, name: &'static str, tools: Vec<&'static str>) {
+        state.registry.register_test(name, move |req| {
+            let id = req.id.clone();
+            match req.method.as_str() {
+                "tools/list" => {
+                    let tools: Vec<Value> = tools
+                        .iter()
+                        .map(|t| json!({"name": t, "inputSchema": {"type": "object"}}))
+                        .collect();
+                    Response::success(id, json!({"tools": tools}))
+                }
+                "tools/call" => {
+                    let tool = req.params["name"].as_str().unwrap_or("").to_string();
+                    Response::success(id, json!({"content": [{"type": "text", "text": tool}]}))
+                }
+                _ => Response::success(id, json!({})),
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn shutdown_report_summarizes_the_session() {
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["read"]);
+        for _ in 0..2 {
+            handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+        }
+        handle_jsonrpc(&state, Request::new("rpc.discover", json!({}))).await;
+
+        let report = state.shutdown_report().await;
+        assert_eq!(report["total_requests"], 3, "{report}");
+        assert_eq!(report["per_method"]["tools/list"], 2);
+        assert_eq!(report["per_method"]["rpc.discover"], 1);
+        assert_eq!(report["upstreams_stopped"], 1);
+        assert_eq!(report["upstreams_killed"], 0);
+        assert!(report["uptime_secs"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn ping_answers_an_empty_result() {
+        let state = test_state().await;
+        let response = handle_jsonrpc(&state, Request::new("ping", json!({}))).await;
+        assert!(response.error.is_none(), "{:?}", response.error);
+        assert_eq!(response.result.unwrap(), json!({}));
+    }
+
+    #[tokio::test]
+    async fn namespaced_ping_probes_one_upstream() {
+        let state = test_state().await;
+        fake_tools_upstream(&state, "alpha", vec!["x"]);
+        let response = handle_jsonrpc(&state, Request::new("alpha/ping", json!({}))).await;
+        assert!(response.error.is_none(), "{:?}", response.error);
+
+        let response = handle_jsonrpc(&state, Request::new("ghost/ping", json!({}))).await;
+        let error = response.error.unwrap();
+        assert!(error.message.contains("unknown upstream"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn discover_lists_the_dispatch_table() {
+        let state = test_state().await;
+        let response = handle_jsonrpc(&state, Request::new("rpc.discover", json!({}))).await;
+        let methods = response.result.unwrap()["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        for core in ["initialize", "tools/list", "tools/call", "prompts/get", "resources/read"] {
+            assert!(methods.contains(&core.to_string()), "missing {core}");
+        }
+        // The admin surface is HTTP-only, never a JSON-RPC method.
+        assert!(!methods.iter().any(|m| m.contains("subscriptions")));
+        assert!(!methods.iter().any(|m| m.contains("upstreams")));
+
+        // Everything advertised actually dispatches (no -32601).
+        for method in methods {
+            let response = handle_jsonrpc(&state, Request::new(method, json!({}))).await;
+            if let Some(err) = response.error {
+                assert_ne!(err.code, code::METHOD_NOT_FOUND, "{}", err.message);
+            }
+        }
+    }
+
+    fn register_counter_upstream(state: &RouterState) {
+        state.registry.register_test("lab", |req| match req.method.as_str() {
+            "tools/list" => Response::success(
+                req.id,
+                json!({"tools": [{
+                    "name": "count",
+                    "inputSchema": {"type": "object"},
+                    "outputSchema": {
+                        "type": "object",
+                        "required": ["count"],
+                        "properties": {"count": {"type": "integer"}},
+                    },
+                }]}),
+            ),
+            "tools/call" => {
+                let bad = req.params.pointer("/arguments/bad") == Some(&json!(true));
+                let content = if bad { json!({"count": "three"}) } else { json!({"count": 3}) };
+                Response::success(
+                    req.id,
+                    json!({"content": [], "structuredContent": content}),
+                )
+            }
+            _ => Response::success(req.id, json!({})),
+        });
+    }
+
+    #[tokio::test]
+    async fn declared_output_schemas_survive_aggregation() {
+        let state = test_state().await;
+        register_counter_upstream(&state);
+        let (tools, _) = aggregate_tools(&state, 0).await;
+        assert_eq!(tools[0]["outputSchema"]["required"], json!(["count"]), "{tools:?}");
+    }
+
+    #[tokio::test]
+    async fn mismatching_results_warn_in_meta() {
+        let mut state = test_state().await;
+        state.config.server.validate_output_schemas = true;
+        register_counter_upstream(&state);
+
+        let request = Request::new(
+            "tools/call",
+            json!({"name": "lab/count", "arguments": {"bad": true}}),
+        );
+        let result = handle_jsonrpc(&state, request).await.result.unwrap();
+        let warning = result["_meta"]["schema_warning"].as_str().unwrap();
+        assert!(warning.contains("count"), "{warning}");
+        assert!(warning.contains("integer"), "{warning}");
+
+        // A conforming result stays unannotated.
+        let request = Request::new("tools/call", json!({"name": "lab/count", "arguments": {}}));
+        let result = handle_jsonrpc(&state, request).await.result.unwrap();
+        assert!(result.get("_meta").is_none(), "{result}");
+    }
+
+    #[tokio::test]
+    async fn unknown_methods_name_the_supported_set() {
+        let mut state = test_state().await;
+        state.config.server.disabled_methods = vec!["completion/complete".into()];
+        let response = handle_jsonrpc(&state, Request::new("tools/lst", json!({}))).await;
+        let error = response.error.unwrap();
+        assert_eq!(error.code, code::METHOD_NOT_FOUND);
+        assert!(error.message.contains("unknown method: tools/lst"), "{}", error.message);
+        let supported = error.data.unwrap()["supported"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m.as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert!(supported.contains(&"tools/list".to_string()), "{supported:?}");
+        // A disabled method answers -32601, so hinting at it would mislead.
+        assert!(!supported.contains(&"completion/complete".to_string()), "{supported:?}");
+    }
+
+    #[tokio::test]
+    async fn tools_are_namespaced_by_upstream() {
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+        let (tools, _) = aggregate_tools(&state, 0).await;
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0]["name"], "fs/fs/read");
+    }
+
+    #[tokio::test]
+    async fn resource_reads_are_cached_until_no_cache() {
+        let store = SubscriptionStore::new("sqlite::memory:").await.unwrap();
+        store.run_migrations().await.unwrap();
+        let providers = ProviderStore::new(store.pool().clone());
+        let registry = Arc::new(UpstreamRegistry::new(Duration::from_secs(2)));
+        let mut config = Config::default();
+        config.server.resource_cache_ttl_secs = 60;
+        let state = RouterState::new(config, registry, Some(store), Some(providers));
+
+        let reads = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let counter = reads.clone();
+        state.registry.register_test("files", move |req| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Response::success(
+                req.id,
+                json!({"contents": [{"uri": "file:///conf", "text": "v1"}]}),
+            )
+        });
+        let uri = encode_resource_uri("files", "file:///conf");
+
+        let first =
+            handle_jsonrpc(&state, Request::new("resources/read", json!({"uri": uri}))).await;
+        assert_eq!(first.result.unwrap()["contents"][0]["text"], "v1");
+        assert_eq!(reads.load(Ordering::Relaxed), 1);
+
+        // Within the TTL the upstream is not consulted again.
+        let second =
+            handle_jsonrpc(&state, Request::new("resources/read", json!({"uri": uri}))).await;
+        assert_eq!(second.result.unwrap()["contents"][0]["text"], "v1");
+        assert_eq!(reads.load(Ordering::Relaxed), 1);
+
+        // `no-cache` forces a fresh read.
+        let forced = handle_jsonrpc(
+            &state,
+            Request::new("resources/read", json!({"uri": uri, "no-cache": true})),
+        )
+        .await;
+        assert!(forced.result.is_some());
+        assert_eq!(reads.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn tools_list_is_sorted_and_stable() {
+        let state = test_state().await;
+        fake_tools_upstream(&state, "zeta", vec!["write", "append"]);
+        fake_tools_upstream(&state, "alpha", vec!["read"]);
+
+        let first: Vec<String> = aggregate_tools(&state, 0)
+            .await
+            .0
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(first, ["alpha/read", "zeta/append", "zeta/write"]);
+        for _ in 0..3 {
+            let again: Vec<String> = aggregate_tools(&state, 0)
+                .await
+                .0
+                .iter()
+                .map(|tool| tool["name"].as_str().unwrap().to_string())
+                .collect();
+            assert_eq!(again, first);
+        }
+    }
+
+    #[tokio::test]
+    async fn skipped_upstreams_are_named_in_meta_partial() {
+        let state = test_state().await;
+        fake_tools_upstream(&state, "good", vec!["alpha"]);
+        state
+            .registry
+            .register_test("bad", |req| Response::error(req.id, code::INTERNAL_ERROR, "boom"));
+
+        let response = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+        let result = response.result.unwrap();
+        assert_eq!(result["tools"][0]["name"], "good/alpha", "{result}");
+        assert_eq!(result["_meta"]["partial"], json!(["bad"]), "{result}");
+    }
+
+    #[tokio::test]
+    async fn fail_policy_refuses_a_lossy_listing() {
+        let mut config = Config::default();
+        config.server.aggregate_on_error = AggregateOnError::Fail;
+        let state = test_state_with(config).await;
+        fake_tools_upstream(&state, "good", vec!["alpha"]);
+        state
+            .registry
+            .register_test("bad", |req| Response::error(req.id, code::INTERNAL_ERROR, "boom"));
+
+        let response = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::UPSTREAM_ERROR);
+        assert!(err.message.contains("bad"), "{}", err.message);
+        assert_eq!(err.data.unwrap()["failed"], json!(["bad"]));
+    }
+
+    #[tokio::test]
+    async fn include_stale_serves_the_last_good_catalog() {
+        let mut config = Config::default();
+        // TTL zero: every listing re-fetches but the cached entries stay
+        // around for the stale fallback.
+        config.server.cache_ttl_secs = 0;
+        config.server.aggregate_on_error = AggregateOnError::IncludeStale;
+        let state = test_state_with(config).await;
+        let healthy = Arc::new(AtomicBool::new(true));
+        let flag = healthy.clone();
+        state.registry.register_test("flaky", move |req| {
+            let id = req.id.clone();
+            match req.method.as_str() {
+                "tools/list" if flag.load(Ordering::SeqCst) => {
+                    Response::success(id, json!({"tools": [{"name": "alpha"}]}))
+                }
+                "tools/list" => Response::error(id, code::INTERNAL_ERROR, "down"),
+                _ => Response::success(id, json!({})),
+            }
+        });
+
+        let response = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+        assert_eq!(response.result.unwrap()["tools"][0]["name"], "flaky/alpha");
+
+        // The upstream goes down; its last catalog is still served, but the
+        // listing admits it may be outdated.
+        healthy.store(false, Ordering::SeqCst);
+        let response = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+        let result = response.result.unwrap();
+        assert_eq!(result["tools"][0]["name"], "flaky/alpha", "{result}");
+        assert_eq!(result["_meta"]["partial"], json!(["flaky"]), "{result}");
+    }
+
+    #[tokio::test]
+    async fn tool_call_routes_to_upstream_and_strips_namespace() {
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+        let request = Request::new(
+            "tools/call",
+            json!({"name": "fs/fs/read", "arguments": {"path": "x"}}),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "fs/read");
+    }
+
+    #[tokio::test]
+    async fn oversized_tool_arguments_are_rejected_before_forwarding() {
+        let store = SubscriptionStore::new("sqlite::memory:").await.unwrap();
+        store.run_migrations().await.unwrap();
+        let providers = ProviderStore::new(store.pool().clone());
+        let registry = Arc::new(UpstreamRegistry::new(Duration::from_secs(2)));
+        let mut config = Config::default();
+        config.server.max_request_bytes = 64;
+        let state = RouterState::new(config, registry, Some(store), Some(providers));
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+
+        let request = Request::new(
+            "tools/call",
+            json!({"name": "fs/fs/read", "arguments": {"pad": "x".repeat(200)}}),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::INVALID_REQUEST);
+        let data = err.data.unwrap();
+        assert_eq!(data["max_request_bytes"], 64);
+        assert!(data["size"].as_u64().unwrap() > 64);
+    }
+
+    #[tokio::test]
+    async fn unnamespaced_tool_call_is_invalid_params() {
+        let state = test_state().await;
+        let request = Request::new("tools/call", json!({"name": "bare"}));
+        let response = handle_jsonrpc(&state, request).await;
+        assert_eq!(response.error.unwrap().code, code::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn unknown_method_is_reported() {
+        let state = test_state().await;
+        let response = handle_jsonrpc(&state, Request::new("bogus/method", json!({}))).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::METHOD_NOT_FOUND);
+        assert!(err.message.contains("bogus/method"));
+    }
+
+    #[tokio::test]
+    async fn upstream_latency_is_observed_per_upstream() {
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+        for _ in 0..2 {
+            state
+                .registry
+                .call("fs", Request::new("tools/list", json!({})))
+                .await
+                .unwrap();
+        }
+        let text = state.metrics.render();
+        assert!(
+            text.contains("mcp_upstream_latency_seconds_count{upstream=\"fs\"} 2"),
+            "{text}"
+        );
+        assert!(text.contains("upstream=\"fs\",le=\"0.001\""), "{text}");
+    }
+
+    #[test]
+    fn resource_uri_roundtrip() {
+        let encoded = encode_resource_uri("fs", "file:///tmp/a b.txt");
+        assert!(encoded.starts_with("mcp+router://fs/"));
+        let (server, uri) = decode_resource_uri(&encoded).unwrap();
+        assert_eq!(server, "fs");
+        assert_eq!(uri, "file:///tmp/a b.txt");
+    }
+
+    #[tokio::test]
+    async fn resource_templates_are_namespaced_with_placeholders_intact() {
+        let state = test_state().await;
+        state.registry.register_test("fs", |req| {
+            let id = req.id.clone();
+            match req.method.as_str() {
+                "resources/templates/list" => Response::success(
+                    id,
+                    json!({"resourceTemplates": [
+                        {"uriTemplate": "file:///{path}", "name": "file"},
+                    ]}),
+                ),
+                _ => Response::success(id, json!({})),
+            }
+        });
+        let (templates, _) = aggregate_resource_templates(&state, 0).await;
+        assert_eq!(templates.len(), 1);
+        let uri = templates[0]["uriTemplate"].as_str().unwrap();
+        assert!(uri.starts_with("mcp+router://fs/"), "{uri}");
+        assert!(uri.contains("{path}"), "{uri}");
+    }
+
+    #[tokio::test]
+    async fn substituted_template_uri_reads_through() {
+        let state = test_state().await;
+        state.registry.register_test("fs", |req| {
+            let id = req.id.clone();
+            match req.method.as_str() {
+                "resources/read" => {
+                    let uri = req.params["uri"].as_str().unwrap_or("").to_string();
+                    Response::success(id, json!({"contents": [{"uri": uri, "text": "hi"}]}))
+                }
+                _ => Response::success(id, json!({})),
+            }
+        });
+
+        // Substitute into the namespaced template exactly as a client would.
+        let template = encode_resource_template("fs", "file:///{path}");
+        let uri = template.replace("{path}", "notes/today.txt");
+        let request = Request::new("resources/read", json!({"uri": uri}));
+        let response = handle_jsonrpc(&state, request).await;
+        let contents = response.result.unwrap();
+        assert_eq!(contents["contents"][0]["uri"], "file:///notes/today.txt");
+    }
+
+    /// Three in-memory resources: `mem:/a` references `mem:/b`, `mem:/b` is a
+    /// leaf, and `mem:/c` references itself.
+    fn register_linked_resources(state: &RouterState) {
+        state.registry.register_test("fs", |req| {
+            let id = req.id.clone();
+            match req.method.as_str() {
+                "resources/read" => {
+                    let uri = req.params["uri"].as_str().unwrap_or("").to_string();
+                    let text = match uri.as_str() {
+                        "mem:/a" => format!("see {}", encode_resource_uri("fs", "mem:/b")),
+                        "mem:/b" => "leaf".into(),
+                        "mem:/c" => format!("loops to {}", encode_resource_uri("fs", "mem:/c")),
+                        other => format!("unknown {other}"),
+                    };
+                    Response::success(id, json!({"contents": [{"uri": uri, "text": text}]}))
+                }
+                _ => Response::success(id, json!({})),
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn embedded_uris_stay_references_by_default() {
+        let state = test_state().await;
+        register_linked_resources(&state);
+        let uri = encode_resource_uri("fs", "mem:/a");
+        let response =
+            handle_jsonrpc(&state, Request::new("resources/read", json!({"uri": uri}))).await;
+        let result = response.result.unwrap();
+        assert_eq!(result["contents"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resolve_depth_one_inlines_referenced_resources() {
+        let state = test_state().await;
+        register_linked_resources(&state);
+        let uri = encode_resource_uri("fs", "mem:/a");
+        let response = handle_jsonrpc(
+            &state,
+            Request::new("resources/read", json!({"uri": uri, "resolve_depth": 1})),
+        )
+        .await;
+        let result = response.result.unwrap();
+        let contents = result["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 2);
+        // The inlined entry carries the router URI it was referenced by.
+        assert_eq!(
+            contents[1]["uri"].as_str().unwrap(),
+            encode_resource_uri("fs", "mem:/b")
+        );
+        assert_eq!(contents[1]["text"], "leaf");
+    }
+
+    #[tokio::test]
+    async fn cyclic_references_are_read_once() {
+        let state = test_state().await;
+        register_linked_resources(&state);
+        let uri = encode_resource_uri("fs", "mem:/c");
+        let response = handle_jsonrpc(
+            &state,
+            Request::new("resources/read", json!({"uri": uri, "resolve_depth": 2})),
+        )
+        .await;
+        let result = response.result.unwrap();
+        // The self-reference is already in the visited set, so the read
+        // terminates with just the original content.
+        assert_eq!(result["contents"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_concurrency_cap_sheds_excess_load() {
+        use std::sync::atomic::AtomicUsize;
+
+        let mut config = Config::default();
+        config.server.max_concurrent_requests = 2;
+        let state = Arc::new(test_state_with(config).await);
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (gauge, high_water) = (in_flight.clone(), peak.clone());
+        state
+            .registry
+            .register_dyn("slow", move |req: Request| {
+                let gauge = gauge.clone();
+                let high_water = high_water.clone();
+                async move {
+                    let now = gauge.fetch_add(1, Ordering::SeqCst) + 1;
+                    high_water.fetch_max(now, Ordering::SeqCst);
+                    // Longer than BUSY_WAIT, so the overflow is rejected
+                    // rather than squeezing in after the first wave.
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    gauge.fetch_sub(1, Ordering::SeqCst);
+                    Response::success(req.id, json!({"content": []}))
+                }
+            })
+            .unwrap();
+
+        let tasks: Vec<_> = (0..6)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    handle_jsonrpc(
+                        &state,
+                        Request::new("tools/call", json!({"name": "slow/x", "arguments": {}})),
+                    )
+                    .await
+                })
+            })
+            .collect();
+        let (mut ok, mut busy) = (0, 0);
+        for task in tasks {
+            match tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap().error {
+                None => ok += 1,
+                Some(err) => {
+                    assert_eq!(err.code, code::SERVER_BUSY);
+                    assert_eq!(err.data.unwrap()["max_concurrent_requests"], 2);
+                    busy += 1;
+                }
+            }
+        }
+        assert_eq!((ok, busy), (2, 4));
+        assert!(peak.load(Ordering::SeqCst) <= 2, "cap was exceeded");
+    }
+
+    #[tokio::test]
+    async fn cold_cache_listings_are_single_flighted() {
+        use std::sync::atomic::AtomicUsize;
+
+        let state = Arc::new(test_state().await);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        state
+            .registry
+            .register_dyn("slow", move |req: Request| {
+                let counter = counter.clone();
+                async move {
+                    let id = req.id.clone();
+                    match req.method.as_str() {
+                        "tools/list" => {
+                            counter.fetch_add(1, Ordering::SeqCst);
+                            // Slow enough that every client below misses the
+                            // cache while the first fetch is still in flight.
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            Response::success(id, json!({"tools": [{"name": "probe"}]}))
+                        }
+                        _ => Response::success(id, json!({})),
+                    }
+                }
+            })
+            .unwrap();
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await
+                })
+            })
+            .collect();
+        for task in tasks {
+            let response = task.await.unwrap();
+            let tools = response.result.unwrap()["tools"].clone();
+            assert_eq!(tools[0]["name"], "slow/probe", "{tools}");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1, "listing was not coalesced");
+    }
+
+    #[tokio::test]
+    async fn quota_is_enforced_for_known_users() {
+        use crate::store::SubscriptionRecord;
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+        state.store.as_ref().unwrap().create_user("carol", "Carol").await.unwrap();
+        state
+            .store
+            .as_ref()
+            .unwrap()
+            .upsert_subscription(&SubscriptionRecord {
+                user_id: "carol".into(),
+                tier: "free".into(),
+                max_tokens: 10,
+                tokens_used: 0,
+                max_requests: 100,
+                requests_used: 0,
+                max_requests_per_day: 0,
+                reset_at: None,
+            })
+            .await
+            .unwrap();
+
+        let request = Request::new(
+            "tools/call",
+            json!({
+                "name": "fs/fs/read",
+                "arguments": {},
+                "_meta": {"user_id": "carol"},
+                "usage": {"tokens": 50},
+            }),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::QUOTA_EXCEEDED);
+        let data = err.data.unwrap();
+        assert_eq!(data["reason"], "token_quota");
+        assert_eq!(data["limit"], 10);
+        assert_eq!(data["remaining"], 10);
+    }
+
+    #[tokio::test]
+    async fn responses_carry_remaining_quota_in_meta() {
+        use crate::store::SubscriptionRecord;
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+        state.store.as_ref().unwrap().create_user("hana", "Hana").await.unwrap();
+        state
+            .store
+            .as_ref()
+            .unwrap()
+            .upsert_subscription(&SubscriptionRecord {
+                user_id: "hana".into(),
+                tier: "basic".into(),
+                max_tokens: 100,
+                tokens_used: 0,
+                max_requests: 50,
+                requests_used: 0,
+                max_requests_per_day: 0,
+                reset_at: None,
+            })
+            .await
+            .unwrap();
+
+        let call = || {
+            Request::new(
+                "tools/call",
+                json!({
+                    "name": "fs/fs/read",
+                    "arguments": {},
+                    "_meta": {"user_id": "hana"},
+                    "usage": {"tokens": 10},
+                }),
+            )
+        };
+        let response = handle_jsonrpc(&state, call()).await;
+        let quota = response.result.unwrap()["_meta"]["quota"].clone();
+        assert_eq!(quota["tokens_remaining"], 90);
+        assert_eq!(quota["requests_remaining"], 49);
+
+        let response = handle_jsonrpc(&state, call()).await;
+        let quota = response.result.unwrap()["_meta"]["quota"].clone();
+        assert_eq!(quota["tokens_remaining"], 80);
+        assert_eq!(quota["requests_remaining"], 48);
+
+        // Anonymous calls get no quota block.
+        let response = handle_jsonrpc(
+            &state,
+            Request::new("tools/call", json!({"name": "fs/fs/read", "arguments": {}})),
+        )
+        .await;
+        assert!(response.result.unwrap().get("_meta").is_none());
+    }
+
+    #[tokio::test]
+    async fn a_batch_returns_per_entry_results_in_order() {
+        let state = test_state().await;
+        state.registry.register_test("fs", |req| {
+            let id = req.id.clone();
+            match req.method.as_str() {
+                "tools/call" => match req.params["name"].as_str() {
+                    Some("boom") => Response::error(id, code::INTERNAL_ERROR, "kaboom"),
+                    tool => Response::success(
+                        id,
+                        json!({"content": [{"type": "text", "text": tool}]}),
+                    ),
+                },
+                _ => Response::success(id, json!({})),
+            }
+        });
+
+        let request = Request::new(
+            "tools/call_batch",
+            json!({"calls": [
+                {"name": "fs/read", "arguments": {}},
+                {"name": "fs/boom", "arguments": {}},
+                {"name": "ghost/x", "arguments": {}},
+            ]}),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        let result = response.result.unwrap();
+        let results = result["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["name"], "fs/read");
+        assert!(results[0].get("error").is_none());
+        assert_eq!(results[1]["error"]["code"], json!(code::INTERNAL_ERROR));
+        assert_eq!(results[2]["error"]["code"], json!(code::METHOD_NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn an_over_budget_batch_is_rejected_wholesale() {
+        use crate::store::SubscriptionRecord;
+        use std::sync::atomic::AtomicUsize;
+
+        let state = test_state().await;
+        let dispatched = Arc::new(AtomicUsize::new(0));
+        let counter = dispatched.clone();
+        state.registry.register_test("fs", move |req| {
+            let id = req.id.clone();
+            if req.method == "tools/call" {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+            Response::success(id, json!({"content": []}))
+        });
+        let store = state.store.as_ref().unwrap();
+        store.create_user("dave", "Dave").await.unwrap();
+        store
+            .upsert_subscription(&SubscriptionRecord {
+                user_id: "dave".into(),
+                tier: "free".into(),
+                max_tokens: 30,
+                tokens_used: 0,
+                max_requests: 100,
+                requests_used: 0,
+                max_requests_per_day: 0,
+                reset_at: None,
+            })
+            .await
+            .unwrap();
+
+        let batch = |tokens: i64| {
+            Request::new(
+                "tools/call_batch",
+                json!({
+                    "calls": [
+                        {"name": "fs/read", "arguments": {}, "usage": {"tokens": tokens}},
+                        {"name": "fs/read", "arguments": {}, "usage": {"tokens": tokens}},
+                    ],
+                    "_meta": {"user_id": "dave"},
+                }),
+            )
+        };
+        // 2 x 20 = 40 > 30: refused as a whole, before any entry runs.
+        let response = handle_jsonrpc(&state, batch(20)).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::QUOTA_EXCEEDED);
+        assert_eq!(err.data.unwrap()["reason"], "token_quota");
+        assert_eq!(dispatched.load(Ordering::SeqCst), 0);
+
+        // 2 x 10 fits, and both entries land in the usage counters.
+        let response = handle_jsonrpc(&state, batch(10)).await;
+        assert!(response.error.is_none());
+        assert_eq!(dispatched.load(Ordering::SeqCst), 2);
+        let record = store.refresh("dave").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 20);
+        assert_eq!(record.requests_used, 2);
+    }
+
+    #[tokio::test]
+    async fn crossing_the_soft_limit_warns_exactly_once() {
+        use crate::store::SubscriptionRecord;
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+        state.store.as_ref().unwrap().create_user("ivan", "Ivan").await.unwrap();
+        state
+            .store
+            .as_ref()
+            .unwrap()
+            .upsert_subscription(&SubscriptionRecord {
+                user_id: "ivan".into(),
+                tier: "basic".into(),
+                max_tokens: 100,
+                tokens_used: 0,
+                max_requests: 50,
+                requests_used: 0,
+                max_requests_per_day: 0,
+                reset_at: None,
+            })
+            .await
+            .unwrap();
+
+        let mut events = state.hub.subscribe();
+        // 20 tokens per call against a 100-token quota and the default 0.8
+        // ratio: the fourth call lands exactly on the threshold, the fifth
+        // stays above it.
+        for _ in 0..5 {
+            let response = handle_jsonrpc(
+                &state,
+                Request::new(
+                    "tools/call",
+                    json!({
+                        "name": "fs/fs/read",
+                        "arguments": {},
+                        "_meta": {"user_id": "ivan"},
+                        "usage": {"tokens": 20},
+                    }),
+                ),
+            )
+            .await;
+            assert!(response.error.is_none(), "{response:?}");
+        }
+
+        let event = events.try_recv().expect("no quota_warning was published");
+        assert_eq!(event.kind, "quota_warning");
+        assert_eq!(event.payload["user"], "ivan");
+        assert_eq!(event.payload["tokens_remaining"], 20);
+        // The fifth call was also above the threshold but did not cross it.
+        assert!(events.try_recv().is_err(), "soft-limit warning was repeated");
+    }
+
+    #[tokio::test]
+    async fn deactivated_user_calls_are_rejected() {
+        use crate::store::SubscriptionRecord;
+        let state = test_state().await;
+        fake_tools_upstream(&state, "fs", vec!["fs/read"]);
+        state.store.as_ref().unwrap().create_user("gone", "Gone").await.unwrap();
+        state
+            .store
+            .as_ref()
+            .unwrap()
+            .upsert_subscription(&SubscriptionRecord {
+                user_id: "gone".into(),
+                tier: "basic".into(),
+                max_tokens: 1000,
+                tokens_used: 0,
+                max_requests: 100,
+                requests_used: 0,
+                max_requests_per_day: 0,
+                reset_at: None,
+            })
+            .await
+            .unwrap();
+        state.store.as_ref().unwrap().deactivate_user("gone").await.unwrap();
+
+        let request = Request::new(
+            "tools/call",
+            json!({
+                "name": "fs/fs/read",
+                "arguments": {},
+                "_meta": {"user_id": "gone"},
+            }),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::QUOTA_EXCEEDED);
+        assert_eq!(err.data.unwrap()["reason"], "user_inactive");
+    }
+
+    #[tokio::test]
+    async fn circuit_open_rejections_carry_retry_after() {
+        use crate::upstream::{Upstream, UpstreamError};
+
+        struct Broken;
+
+        #[async_trait::async_trait]
+        impl Upstream for Broken {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, _request: Request) -> Result<Response, UpstreamError> {
+                Err(UpstreamError::Protocol("boom".into()))
+            }
+        }
+
+        let state = test_state().await;
+        state.registry.register("dead", Arc::new(Broken));
+        let call = || {
+            Request::new(
+                "tools/call",
+                json!({"name": "dead/x", "arguments": {}}),
+            )
+        };
+        // Trip the breaker (threshold 5).
+        for _ in 0..5 {
+            let response = handle_jsonrpc(&state, call()).await;
+            assert_eq!(response.error.unwrap().code, code::UPSTREAM_ERROR);
+        }
+        let response = handle_jsonrpc(&state, call()).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::CIRCUIT_OPEN);
+        let data = err.data.unwrap();
+        assert_eq!(data["reason"], "circuit_open");
+        assert!(data["retry_after_ms"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn routing_mistakes_and_call_failures_get_distinct_codes() {
+        use crate::upstream::{Upstream, UpstreamError};
+
+        struct Flaky;
+
+        #[async_trait::async_trait]
+        impl Upstream for Flaky {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, _request: Request) -> Result<Response, UpstreamError> {
+                Err(UpstreamError::Protocol("boom".into()))
+            }
+        }
+
+        let state = test_state().await;
+        state.registry.register("real", Arc::new(Flaky));
+
+        // A namespace nobody registered is the caller's mistake: -32601.
+        let requests = [
+            Request::new("tools/call", json!({"name": "ghost/x", "arguments": {}})),
+            Request::new("prompts/get", json!({"name": "ghost/p"})),
+            Request::new(
+                "resources/read",
+                json!({"uri": encode_resource_uri("ghost", "file:///x")}),
+            ),
+        ];
+        for request in requests {
+            let err = handle_jsonrpc(&state, request).await.error.unwrap();
+            assert_eq!(err.code, code::METHOD_NOT_FOUND);
+            assert!(
+                err.message.contains("unknown upstream: ghost"),
+                "{}",
+                err.message
+            );
+        }
+
+        // A registered upstream that fails the call is a real upstream error.
+        let request = Request::new("tools/call", json!({"name": "real/x", "arguments": {}}));
+        let err = handle_jsonrpc(&state, request).await.error.unwrap();
+        assert_eq!(err.code, code::UPSTREAM_ERROR);
+    }
+
+    #[tokio::test]
+    async fn prompt_arguments_flow_both_ways() {
+        let state = test_state().await;
+        state.registry.register_test("up", |req| match req.method.as_str() {
+            "prompts/list" => Response::success(
+                req.id,
+                json!({"prompts": [
+                    {
+                        "name": "greet",
+                        "description": "say hi",
+                        "arguments": [{"name": "who", "required": true}],
+                    },
+                    {"name": "broken", "arguments": {"who": "not a list"}},
+                ]}),
+            ),
+            "prompts/get" => Response::success(req.id, json!({"received": req.params})),
+            _ => Response::success(req.id, json!({})),
+        });
+
+        // The declared argument schema survives aggregation; a malformed
+        // (non-array) declaration is dropped, not forwarded.
+        let listed = handle_jsonrpc(&state, Request::new("prompts/list", json!({})))
+            .await
+            .result
+            .unwrap();
+        let prompts = listed["prompts"].as_array().unwrap();
+        let greet = prompts.iter().find(|p| p["name"] == "up/greet").unwrap();
+        assert_eq!(greet["arguments"][0]["name"], "who");
+        let broken = prompts.iter().find(|p| p["name"] == "up/broken").unwrap();
+        assert!(broken.get("arguments").is_none(), "{broken}");
+
+        // The client's argument values reach the upstream alongside the
+        // denamespaced name.
+        let fetched = handle_jsonrpc(
+            &state,
+            Request::new(
+                "prompts/get",
+                json!({"name": "up/greet", "arguments": {"who": "world"}}),
+            ),
+        )
+        .await
+        .result
+        .unwrap();
+        assert_eq!(fetched["received"]["name"], "greet");
+        assert_eq!(fetched["received"]["arguments"]["who"], "world");
+    }
+
+    fn register_big_upstream(state: &RouterState) {
+        state.registry.register_test("big", |req| {
+            Response::success(
+                req.id,
+                json!({"content": [{"type": "text", "text": "x".repeat(5_000)}]}),
+            )
+        });
+    }
+
+    #[tokio::test]
+    async fn oversized_results_are_truncated_with_a_marker() {
+        let mut state = test_state().await;
+        state.config.server.max_result_bytes = 512;
+        register_big_upstream(&state);
+
+        let request = Request::new("tools/call", json!({"name": "big/dump", "arguments": {}}));
+        let result = handle_jsonrpc(&state, request).await.result.unwrap();
+        assert_eq!(result["_meta"]["truncated"], true, "{result}");
+        assert!(result["_meta"]["original_bytes"].as_u64().unwrap() > 512);
+        assert!(result["content"][0]["text"].as_str().unwrap().len() <= 512);
+    }
+
+    #[tokio::test]
+    async fn oversized_results_can_be_rejected_instead() {
+        let mut state = test_state().await;
+        state.config.server.max_result_bytes = 512;
+        state.config.server.oversize_policy = OversizePolicy::Reject;
+        register_big_upstream(&state);
+
+        let request = Request::new("tools/call", json!({"name": "big/dump", "arguments": {}}));
+        let err = handle_jsonrpc(&state, request).await.error.unwrap();
+        assert_eq!(err.code, code::RESULT_TOO_LARGE);
+        let data = err.data.unwrap();
+        assert!(data["size"].as_u64().unwrap() > 512);
+        assert_eq!(data["max_result_bytes"], 512);
+    }
+
+    #[tokio::test]
+    async fn cost_multipliers_deplete_quota_faster() {
+        use crate::store::SubscriptionRecord;
+        use crate::upstream::{Upstream, UpstreamError, UpstreamFilters};
+        use std::collections::HashMap;
+
+        struct Echo;
+
+        #[async_trait::async_trait]
+        impl Upstream for Echo {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+                Ok(Response::success(request.id, json!({"content": []})))
+            }
+        }
+
+        let state = test_state().await;
+        let mut costs = HashMap::new();
+        costs.insert("generate".to_string(), 10.0);
+        state
+            .registry
+            .register_filtered(
+                "img",
+                Arc::new(Echo),
+                UpstreamFilters::default(),
+                costs,
+                Default::default(),
+            );
+
+        let store = state.store.as_ref().unwrap();
+        for user in ["cheap", "pricey"] {
+            store.create_user(user, user).await.unwrap();
+            store
+                .upsert_subscription(&SubscriptionRecord {
+                    user_id: user.into(),
+                    tier: "free".into(),
+                    max_tokens: 1000,
+                    tokens_used: 0,
+                    max_requests: 100,
+                    requests_used: 0,
+                    max_requests_per_day: 0,
+                    reset_at: None,
+                })
+                .await
+                .unwrap();
+        }
+        let call = |user: &str, tool: &str| {
+            Request::new(
+                "tools/call",
+                json!({
+                    "name": format!("img/{tool}"),
+                    "arguments": {},
+                    "_meta": {"user_id": user},
+                    "usage": {"tokens": 50},
+                }),
+            )
+        };
+
+        // An unweighted tool spends its 50 tokens as-is...
+        let response = handle_jsonrpc(&state, call("cheap", "resize")).await;
+        let quota = response.result.unwrap()["_meta"]["quota"].clone();
+        assert_eq!(quota["tokens_remaining"], 950);
+
+        // ...while the 10x tool burns 500 for the same declared usage.
+        let response = handle_jsonrpc(&state, call("pricey", "generate")).await;
+        let quota = response.result.unwrap()["_meta"]["quota"].clone();
+        assert_eq!(quota["tokens_remaining"], 500);
+
+        // Two weighted calls exhaust the budget; the third is rejected.
+        let response = handle_jsonrpc(&state, call("pricey", "generate")).await;
+        assert!(response.error.is_none());
+        let response = handle_jsonrpc(&state, call("pricey", "generate")).await;
+        assert_eq!(response.error.unwrap().code, code::QUOTA_EXCEEDED);
+    }
+
+    #[tokio::test]
+    async fn estimated_tokens_gate_the_quota_without_explicit_usage() {
+        use crate::store::SubscriptionRecord;
+        let state = test_state().await;
+        fake_tools_upstream(&state, "openai", vec!["chat"]);
+        state.store.as_ref().unwrap().create_user("dave", "Dave").await.unwrap();
+        state
+            .store
+            .as_ref()
+            .unwrap()
+            .upsert_subscription(&SubscriptionRecord {
+                user_id: "dave".into(),
+                tier: "free".into(),
+                max_tokens: 10,
+                tokens_used: 0,
+                max_requests: 100,
+                requests_used: 0,
+                max_requests_per_day: 0,
+                reset_at: None,
+            })
+            .await
+            .unwrap();
+
+        // No usage.tokens: the heuristic (chars/4) puts this prompt well
+        // over the 10-token budget.
+        let request = Request::new(
+            "tools/call",
+            json!({
+                "name": "openai/chat",
+                "arguments": {"prompt": "p".repeat(200)},
+                "_meta": {"user_id": "dave"},
+            }),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        assert_eq!(response.error.unwrap().code, code::QUOTA_EXCEEDED);
+
+        // A short prompt fits and goes through.
+        let request = Request::new(
+            "tools/call",
+            json!({
+                "name": "openai/chat",
+                "arguments": {"prompt": "hi"},
+                "_meta": {"user_id": "dave"},
+            }),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        assert!(response.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn double_colon_separator_round_trips_list_and_call() {
+        let store = SubscriptionStore::new("sqlite::memory:").await.unwrap();
+        store.run_migrations().await.unwrap();
+        let providers = ProviderStore::new(store.pool().clone());
+        let registry =
+            Arc::new(UpstreamRegistry::new(Duration::from_secs(2)).with_namespace_separator("::"));
+        let mut config = Config::default();
+        config.server.namespace_style = crate::config::NamespaceStyle::DoubleColon;
+        let state = RouterState::new(config, registry, Some(store), Some(providers));
+        fake_tools_upstream(&state, "files", vec!["read"]);
+
+        let response = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+        let result = response.result.unwrap();
+        assert_eq!(result["tools"][0]["name"], "files::read", "{result}");
+
+        // The listed name routes straight back to its upstream.
+        let request = Request::new(
+            "tools/call",
+            json!({"name": "files::read", "arguments": {}}),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "read");
+    }
+
+    #[tokio::test]
+    async fn non_object_params_get_a_clear_invalid_params_error() {
+        let state = test_state().await;
+
+        let mut request = Request::new("tools/call", json!(["oops"]));
+        request.id = Id::Int(1);
+        let response = handle_jsonrpc(&state, request).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::INVALID_PARAMS);
+        assert_eq!(err.message, "invalid params: expected object");
+        assert_eq!(err.data.unwrap()["type"], "array");
+
+        let request = Request::new("prompts/get", json!("just a string"));
+        let response = handle_jsonrpc(&state, request).await;
+        let err = response.error.unwrap();
+        assert_eq!(err.code, code::INVALID_PARAMS);
+        assert_eq!(err.data.unwrap()["type"], "string");
+    }
+
+    #[tokio::test]
+    async fn initialize_lists_upstream_server_info() {
+        let state = test_state().await;
+        state.registry.register_test("alpha", |req| {
+            Response::success(
+                req.id,
+                json!({
+                    "serverInfo": {"name": "alpha-server", "version": "1.2"},
+                    "capabilities": {"tools": {}},
+                }),
+            )
+        });
+        state.registry.register_test("beta", |req| {
+            Response::success(
+                req.id,
+                json!({
+                    "serverInfo": {"name": "beta-server"},
+                    "capabilities": {"resources": {"subscribe": true}},
+                }),
+            )
+        });
+
+        let response = handle_jsonrpc(&state, Request::new("initialize", json!({}))).await;
+        let result = response.result.unwrap();
+        let upstreams = result["upstreams"].as_array().unwrap();
+        assert_eq!(upstreams.len(), 2, "{result}");
+        // Registry order is alphabetical.
+        assert_eq!(upstreams[0]["name"], "alpha");
+        assert_eq!(upstreams[0]["kind"], "test");
+        assert_eq!(upstreams[0]["serverInfo"]["name"], "alpha-server");
+        assert_eq!(upstreams[0]["capabilities"], json!({"tools": {}}));
+        assert_eq!(upstreams[1]["name"], "beta");
+        assert_eq!(
+            upstreams[1]["capabilities"]["resources"]["subscribe"],
+            true
+        );
+        // No transport details leak into the handshake.
+        assert!(upstreams[0].get("url").is_none());
+    }
+
+    #[tokio::test]
+    async fn only_calls_over_the_slow_threshold_are_warned_about() {
+        use std::io::Write;
+        use std::sync::Mutex;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        // Capture log output from a scoped subscriber, as the logging tests do.
+        #[derive(Clone)]
+        struct Sink(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().expect("sink lock").extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut config = Config::default();
+        config.server.slow_call_threshold_ms = 25;
+        let state = test_state_with(config).await;
+        let store = state.store.as_ref().unwrap();
+        store.create_user("alice", "Alice").await.unwrap();
+        store
+            .upsert_subscription(&crate::store::SubscriptionRecord {
+                user_id: "alice".into(),
+                tier: "basic".into(),
+                max_tokens: 100_000,
+                tokens_used: 0,
+                max_requests: 100,
+                requests_used: 0,
+                max_requests_per_day: 0,
+                reset_at: None,
+            })
+            .await
+            .unwrap();
+        fake_tools_upstream(&state, "fs", vec!["read"]);
+        state.registry.register_test("snail", |req| {
+            std::thread::sleep(Duration::from_millis(60));
+            Response::success(req.id, json!({"content": [{"type": "text", "text": "done"}]}))
+        });
+
+        let sink = Sink(Arc::new(Mutex::new(Vec::new())));
+        let writer = sink.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(move || writer.clone()),
+        );
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = Request::new(
+            "tools/call",
+            json!({"name": "snail/nap", "arguments": {}, "_meta": {"user_id": "alice"}}),
+        );
+        let response = handle_jsonrpc(&state, request).await;
+        let logged =
+            String::from_utf8(sink.0.lock().expect("sink lock").clone()).expect("utf8 logs");
+        assert!(response.error.is_none(), "{response:?}");
+        assert!(logged.contains("slow call"), "{logged}");
+        assert!(logged.contains("upstream=\"snail\""), "{logged}");
+        assert!(logged.contains("user=\"alice\""), "{logged}");
+
+        // A fast call stays quiet: no second warning shows up.
+        let request = Request::new("tools/call", json!({"name": "fs/read", "arguments": {}}));
+        handle_jsonrpc(&state, request).await;
+        let logged =
+            String::from_utf8(sink.0.lock().expect("sink lock").clone()).expect("utf8 logs");
+        assert_eq!(logged.matches("slow call").count(), 1, "{logged}");
+    }
+}
@@ -0,0 +1,328 @@
+//! The axum application: `/mcp`, SSE streams, health, metrics and `/api`.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::stream::{FuturesUnordered, StreamExt};
+use mcp_core::rpc::{code, Id, Request, Response};
+use serde_json::{json, Value};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::Instrument;
+
+use crate::auth::BearerToken;
+use crate::router::{handle_jsonrpc, RouterState};
+use crate::upstream::HOP_HEADER;
+use crate::{admin, metrics, sse};
+
+/// Build the full router application.
+pub fn build_app(state: Arc<RouterState>) -> Router {
+    let cors = CorsLayer::new().allow_origin(allow_origin(&state.config.server.allow_origins));
+
+    let app = Router::new()
+        .route(
+            "/mcp",
+            post(handle_rpc)
+                .layer(DefaultBodyLimit::max(state.config.server.max_request_bytes)),
+        )
+        .route("/events", get(sse::stream))
+        .route("/upstreams/{name}/events", get(sse::sse_stream))
+        .route("/healthz", get(healthz))
+        .route("/metrics", get(metrics::handler))
+        .nest("/api", admin::routes())
+        .layer(cors);
+    let app = if state.config.server.compression {
+        // The default predicate skips `text/event-stream`, so the SSE routes
+        // keep streaming uncompressed.
+        app.layer(CompressionLayer::new())
+    } else {
+        app
+    };
+    app.with_state(state)
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Build the CORS origin policy from `allow_origins`: exact origins, a bare
+/// `*` allowing anything, or single-wildcard patterns like
+/// `https://*.example.com`. Invalid entries are skipped with a warning so one
+/// typo does not take the listener down.
+fn allow_origin(origins: &[String]) -> AllowOrigin {
+    if origins.iter().any(|origin| origin == "*") {
+        return AllowOrigin::any();
+    }
+    let mut exact = Vec::new();
+    let mut patterns = Vec::new();
+    for origin in origins {
+        if let Some((prefix, suffix)) = origin.split_once('*') {
+            // Only `https://*.example.com` shapes: the trailing dot keeps
+            // `evil-example.com` from matching a `*.example.com` pattern.
+            if suffix.contains('*') || !suffix.starts_with('.') {
+                tracing::warn!(%origin, "ignoring CORS pattern: want a single `*.` wildcard");
+                continue;
+            }
+            patterns.push((prefix.to_string(), suffix.to_string()));
+        } else if let Ok(value) = origin.parse::<HeaderValue>() {
+            exact.push(value);
+        } else {
+            tracing::warn!(%origin, "ignoring unparseable CORS origin");
+        }
+    }
+    AllowOrigin::predicate(move |origin, _| {
+        exact.iter().any(|allowed| allowed == origin)
+            || origin.to_str().is_ok_and(|origin| {
+                patterns.iter().any(|(prefix, suffix)| {
+                    origin.len() > prefix.len() + suffix.len()
+                        && origin.starts_with(prefix.as_str())
+                        && origin.ends_with(suffix.as_str())
+                })
+            })
+    })
+}
+
+/// Serve `app` on a Unix domain socket, for sidecar deployments where the
+/// router should not be reachable over TCP at all (a `bind` of
+/// `unix:/run/mcp-router.sock`). A stale socket file from a previous unclean
+/// shutdown is removed before binding, permissions are tightened to owner and
+/// group, and the file is cleaned up again once the listener stops.
+#[cfg(unix)]
+pub async fn serve_unix(
+    app: Router,
+    path: &std::path::Path,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    // rw for owner and group only; sidecars join via group membership.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await;
+    if let Err(err) = std::fs::remove_file(path) {
+        tracing::warn!(path = %path.display(), %err, "failed to remove socket file");
+    }
+    result
+}
+
+/// Serve `app` over TLS using the configured certificate pair. `handle` can
+/// be used to learn the bound address and to shut the listener down. On unix,
+/// SIGHUP re-reads the certificate files so certs rotate without a restart.
+pub async fn serve_tls(
+    app: Router,
+    addr: std::net::SocketAddr,
+    tls: &crate::config::TlsConfig,
+    handle: axum_server::Handle,
+) -> std::io::Result<()> {
+    let rustls = axum_server::tls_rustls::RustlsConfig::from_pem_file(
+        tls.cert_path.clone(),
+        tls.key_path.clone(),
+    )
+    .await?;
+
+    #[cfg(unix)]
+    {
+        let rustls = rustls.clone();
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            let mut hangup = match tokio::signal::unix::signal(
+                tokio::signal::unix::SignalKind::hangup(),
+            ) {
+                Ok(hangup) => hangup,
+                Err(err) => {
+                    tracing::warn!(%err, "cannot listen for SIGHUP, cert reload disabled");
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                match rustls
+                    .reload_from_pem_file(tls.cert_path.clone(), tls.key_path.clone())
+                    .await
+                {
+                    Ok(()) => tracing::info!("reloaded TLS certificates"),
+                    Err(err) => tracing::error!(%err, "failed to reload TLS certificates"),
+                }
+            }
+        });
+    }
+
+    axum_server::bind_rustls(addr, rustls)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await
+}
+
+/// `POST /mcp`: accepts a single JSON-RPC request or a batch array.
+///
+/// Transport-level failures get transport-level status codes: a body that is
+/// not JSON at all is a 400 (with a JSON-RPC parse error for good measure),
+/// and auth failures are 401 via the extractor. Anything that parses stays
+/// 200 — application-level failures belong in the JSON-RPC `error` member,
+/// per the spec, so proxies and monitors see exactly where a request died.
+///
+/// Batches default to a buffered JSON array. A client sending
+/// `Accept: application/x-ndjson` instead gets one response per line,
+/// streamed in completion order, so fast sub-requests are not held back by
+/// the slowest entry in the batch.
+async fn handle_rpc(
+    State(state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    auth: BearerToken,
+    body: Result<Json<Value>, JsonRejection>,
+) -> axum::response::Response {
+    let body = match body {
+        Ok(Json(body)) => body,
+        Err(rejection) => {
+            // The rejection's own status is kept (400 for bad JSON, 413 for
+            // oversized bodies, 415 for a wrong content type).
+            let status = rejection.status();
+            let response = Response::error(
+                Id::Null,
+                code::PARSE_ERROR,
+                format!("parse error: {}", rejection.body_text()),
+            );
+            return (
+                status,
+                Json(serde_json::to_value(response).expect("serialize response")),
+            )
+                .into_response();
+        }
+    };
+    let wants_ndjson = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/x-ndjson"));
+
+    // Correlation id for this HTTP request: honor the caller's X-Request-Id
+    // or mint one. It tags the dispatch span, rides along to upstreams in
+    // `_meta`, and is echoed back in the response headers.
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    // Hop count stamped by an upstream router forwarding to us; folded into
+    // `_meta` below so the loop guard in the dispatcher sees it.
+    let hops = headers
+        .get(HOP_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    // Scopes of the verified token (admin-issued API tokens only); the
+    // dispatcher uses them for per-scope method filtering.
+    let scopes = Arc::new(auth.scopes);
+
+    let mut response = match body {
+        Value::Array(entries) if wants_ndjson => {
+            let stream = entries
+                .into_iter()
+                .map(|entry| {
+                    let state = state.clone();
+                    let request_id = request_id.clone();
+                    let scopes = scopes.clone();
+                    async move { dispatch_value(&state, entry, &request_id, hops, &scopes).await }
+                })
+                .collect::<FuturesUnordered<_>>()
+                .map(|response| {
+                    let mut line =
+                        serde_json::to_vec(&response).expect("serialize response");
+                    line.push(b'\n');
+                    Ok::<_, Infallible>(Bytes::from(line))
+                });
+            (
+                [(header::CONTENT_TYPE, "application/x-ndjson")],
+                Body::from_stream(stream),
+            )
+                .into_response()
+        }
+        Value::Array(entries) => {
+            let futures = entries.into_iter().map(|entry| {
+                let state = state.clone();
+                let request_id = request_id.clone();
+                let scopes = scopes.clone();
+                async move { dispatch_value(&state, entry, &request_id, hops, &scopes).await }
+            });
+            let responses = futures::future::join_all(futures).await;
+            Json(Value::Array(
+                responses
+                    .into_iter()
+                    .map(|r| serde_json::to_value(r).expect("serialize response"))
+                    .collect(),
+            ))
+            .into_response()
+        }
+        single => {
+            let response = dispatch_value(&state, single, &request_id, hops, &scopes).await;
+            Json(serde_json::to_value(response).expect("serialize response")).into_response()
+        }
+    };
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert("x-request-id", value);
+    }
+    response
+}
+
+async fn dispatch_value(
+    state: &RouterState,
+    entry: Value,
+    request_id: &str,
+    hops: u64,
+    scopes: &Option<Vec<String>>,
+) -> Response {
+    let span = tracing::info_span!("rpc", %request_id);
+    match serde_json::from_value::<Request>(entry) {
+        Ok(mut request) => {
+            // Stamp the correlation id into `_meta` so handlers that forward
+            // params (tools/call) carry it through to the upstream, and fold
+            // in the transport-level hop count (the body's own value wins if
+            // it is larger).
+            // A paramless request still needs somewhere to carry the hops
+            // (and the token scopes, when the credential has any).
+            if request.params.is_null() && (hops > 0 || scopes.is_some()) {
+                request.params = json!({});
+            }
+            let meta_indexable = request
+                .params
+                .get("_meta")
+                .map(Value::is_object)
+                .unwrap_or(true);
+            if request.params.is_object() && meta_indexable {
+                request.params["_meta"]["request_id"] = Value::String(request_id.to_string());
+                let hops = hops.max(crate::upstream::hop_count(&request.params));
+                if hops > 0 {
+                    request.params["_meta"][HOP_HEADER] = Value::from(hops);
+                }
+                // Scopes come from the verified credential, never from the
+                // body: drop whatever the client wrote before stamping.
+                if let Some(meta) = request.params["_meta"].as_object_mut() {
+                    meta.remove("scopes");
+                }
+                if let Some(scopes) = scopes {
+                    request.params["_meta"]["scopes"] = json!(scopes);
+                }
+            }
+            handle_jsonrpc(state, request).instrument(span).await
+        }
+        Err(err) => Response::error(
+            Id::Null,
+            code::INVALID_REQUEST,
+            format!("invalid request: {err}"),
+        ),
+    }
+}
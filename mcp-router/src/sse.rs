@@ -0,0 +1,201 @@
+//! The router event hub and the SSE endpoints that expose it.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::stream::Stream;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::router::RouterState;
+
+/// An event published on the router's broadcast hub: upstream notifications,
+/// catalog changes, quota events.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouterEvent {
+    /// Event type, e.g. `tools_changed` or `notification`.
+    pub kind: String,
+    /// The upstream the event concerns, when there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    pub payload: Value,
+}
+
+impl RouterEvent {
+    pub fn new(kind: impl Into<String>, server: Option<String>, payload: Value) -> Self {
+        RouterEvent {
+            kind: kind.into(),
+            server,
+            payload,
+        }
+    }
+}
+
+/// Fan-out hub for [`RouterEvent`]s backed by a tokio broadcast channel.
+#[derive(Clone)]
+pub struct EventHub {
+    tx: tokio::sync::broadcast::Sender<RouterEvent>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (tx, _) = tokio::sync::broadcast::channel(256);
+        EventHub { tx }
+    }
+
+    /// Publish an event; having no subscribers is not an error.
+    pub fn publish(&self, event: RouterEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<RouterEvent> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        EventHub::new()
+    }
+}
+
+fn into_sse(event: RouterEvent) -> Event {
+    let data = serde_json::to_string(&event.payload).unwrap_or_else(|_| "null".into());
+    Event::default().event(event.kind).data(data)
+}
+
+/// Server-side filter for an SSE subscription, from query parameters:
+/// `?event=tools_changed&server=fs`. Absent fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StreamFilter {
+    /// Only deliver events of this kind.
+    pub event: Option<String>,
+    /// Only deliver events concerning this upstream.
+    pub server: Option<String>,
+}
+
+impl StreamFilter {
+    pub fn matches(&self, event: &RouterEvent) -> bool {
+        if let Some(kind) = &self.event {
+            if *kind != event.kind {
+                return false;
+            }
+        }
+        if let Some(server) = &self.server {
+            if event.server.as_deref() != Some(server.as_str()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn filtered(
+    hub: &EventHub,
+    filter: StreamFilter,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    BroadcastStream::new(hub.subscribe())
+        .filter_map(move |item| {
+            let filter = filter.clone();
+            async move {
+                match item {
+                    Ok(event) if filter.matches(&event) => Some(event),
+                    _ => None,
+                }
+            }
+        })
+        .map(|event| Ok(into_sse(event)))
+}
+
+/// Apply the configured keep-alive cadence (`sse_keepalive_secs`) to a
+/// stream; zero leaves keep-alives off entirely, for deployments where a
+/// fronting proxy injects its own.
+fn with_keepalive<S>(sse: Sse<S>, state: &RouterState) -> axum::response::Response
+where
+    S: Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    use axum::response::IntoResponse;
+    match state.config.server.sse_keepalive_secs {
+        0 => sse.into_response(),
+        secs => sse
+            .keep_alive(KeepAlive::new().interval(Duration::from_secs(secs)))
+            .into_response(),
+    }
+}
+
+/// `GET /events`: router events as SSE, optionally filtered server-side by
+/// `?event=` and/or `?server=`.
+pub async fn stream(
+    State(state): State<Arc<RouterState>>,
+    Query(filter): Query<StreamFilter>,
+) -> axum::response::Response {
+    with_keepalive(Sse::new(filtered(&state.hub, filter)), &state)
+}
+
+/// `GET /upstreams/{name}/events`: only events for one upstream, optionally
+/// narrowed further by `?event=`.
+pub async fn sse_stream(
+    State(state): State<Arc<RouterState>>,
+    Path(name): Path<String>,
+    Query(filter): Query<StreamFilter>,
+) -> axum::response::Response {
+    let filter = StreamFilter {
+        server: Some(name),
+        ..filter
+    };
+    with_keepalive(Sse::new(filtered(&state.hub, filter)), &state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(kind: &str, server: Option<&str>) -> RouterEvent {
+        RouterEvent::new(kind, server.map(str::to_string), json!({}))
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = StreamFilter::default();
+        assert!(filter.matches(&event("tools_changed", Some("fs"))));
+        assert!(filter.matches(&event("notification", None)));
+    }
+
+    #[test]
+    fn event_kind_filter() {
+        let filter = StreamFilter {
+            event: Some("tools_changed".into()),
+            server: None,
+        };
+        assert!(filter.matches(&event("tools_changed", Some("fs"))));
+        assert!(!filter.matches(&event("notification", Some("fs"))));
+    }
+
+    #[test]
+    fn server_filter_requires_a_server() {
+        let filter = StreamFilter {
+            event: None,
+            server: Some("fs".into()),
+        };
+        assert!(filter.matches(&event("tools_changed", Some("fs"))));
+        assert!(!filter.matches(&event("tools_changed", Some("web"))));
+        assert!(!filter.matches(&event("tools_changed", None)));
+    }
+
+    #[test]
+    fn combined_filter_needs_both() {
+        let filter = StreamFilter {
+            event: Some("tools_changed".into()),
+            server: Some("fs".into()),
+        };
+        assert!(filter.matches(&event("tools_changed", Some("fs"))));
+        assert!(!filter.matches(&event("quota_warning", Some("fs"))));
+        assert!(!filter.matches(&event("tools_changed", Some("web"))));
+    }
+}
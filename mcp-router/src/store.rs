@@ -0,0 +1,1486 @@
+//! Persistence: users, subscriptions, usage accounting, and the provider
+//! credential store. The backend is picked from the `database_url` scheme —
+//! SQLite for a single instance, Postgres when several routers share state —
+//! and every query is written in the dialect both speak.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::any::AnyPoolOptions;
+use sqlx::{AnyPool, FromRow};
+use thiserror::Error;
+
+/// Built-in subscription tiers and their default quotas.
+pub const TIERS: &[(&str, i64, i64)] = &[
+    // (tier, max_tokens, max_requests)
+    ("free", 10_000, 100),
+    ("basic", 100_000, 1_000),
+    ("pro", 1_000_000, 10_000),
+];
+
+/// The tenant rows land in when no tenant is named: pre-tenancy databases
+/// are migrated into it, and a store opened with [`SubscriptionStore::open`]
+/// is scoped to it until [`for_tenant`](SubscriptionStore::for_tenant) says
+/// otherwise.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Default quotas for a named tier, if it is one of the built-ins.
+pub fn tier_quotas(tier: &str) -> Option<(i64, i64)> {
+    TIERS
+        .iter()
+        .find(|(name, _, _)| *name == tier)
+        .map(|(_, tokens, requests)| (*tokens, *requests))
+}
+
+/// Days since the Unix epoch, UTC: the key for the rolling daily counter in
+/// `usage_counters`.
+fn current_day() -> i64 {
+    (std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 86_400) as i64
+}
+
+#[derive(Debug, Error)]
+pub enum EnforcementError {
+    #[error("no subscription for user {0}")]
+    NoSubscription(String),
+    #[error("token quota exceeded: {used}/{limit}")]
+    TokenQuota { limit: i64, used: i64 },
+    #[error("request quota exceeded: {used}/{limit}")]
+    RequestQuota { limit: i64, used: i64 },
+    #[error("daily request quota exceeded: {used}/{limit}")]
+    DailyRequestQuota { limit: i64, used: i64 },
+    #[error("user {0} is deactivated")]
+    Inactive(String),
+    #[error("store error: {0}")]
+    Db(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    pub user_id: String,
+    pub name: String,
+    pub created_at: String,
+    pub active: bool,
+}
+
+// Hand-rolled because `active` is BOOLEAN on Postgres but INTEGER on SQLite;
+// queries select it normalized to a BIGINT and the conversion happens here.
+impl sqlx::FromRow<'_, sqlx::any::AnyRow> for UserRecord {
+    fn from_row(row: &sqlx::any::AnyRow) -> Result<Self, sqlx::Error> {
+        use sqlx::Row;
+        Ok(UserRecord {
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            created_at: row.try_get("created_at")?,
+            active: row.try_get::<i64, _>("active")? != 0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SubscriptionRecord {
+    pub user_id: String,
+    pub tier: String,
+    pub max_tokens: i64,
+    pub tokens_used: i64,
+    pub max_requests: i64,
+    pub requests_used: i64,
+    /// Rolling per-day request cap, enforced on a counter that resets at UTC
+    /// day boundaries. Zero means no daily cap.
+    #[serde(default)]
+    pub max_requests_per_day: i64,
+    pub reset_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TokenRecord {
+    pub id: String,
+    pub user_id: String,
+    pub scopes: String,
+    pub created_at: String,
+}
+
+/// Default lifetime of a cached subscription record before it is re-read
+/// from the database.
+pub const DEFAULT_SUBSCRIPTION_CACHE_TTL: Duration = Duration::from_secs(5);
+
+struct CachedSubscription {
+    fetched: Instant,
+    record: SubscriptionRecord,
+}
+
+/// Which SQL dialect the store speaks, picked from the `database_url` scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreBackend {
+    Sqlite,
+    Postgres,
+}
+
+/// Users, subscriptions and usage accounting, with an in-memory cache of
+/// subscription records in front of the database.
+///
+/// Every store handle is scoped to one tenant (the default tenant unless
+/// [`for_tenant`](Self::for_tenant) was used), and every query it runs reads
+/// and writes that tenant's rows only.
+///
+/// Cache invalidation on writes only covers writes made *through this
+/// process*. When several router instances share one SQLite file (WAL mode),
+/// another instance's writes are picked up only once the cached entry's TTL
+/// expires — size the TTL accordingly, or call [`refresh`](Self::refresh)
+/// after known external writes.
+pub struct SubscriptionStore {
+    pool: AnyPool,
+    backend: StoreBackend,
+    /// Keyed by `(tenant_id, user_id)` and shared between the tenant-scoped
+    /// handles of one store, so a write through any handle invalidates the
+    /// entry every handle sees.
+    cache: Arc<RwLock<HashMap<(String, String), CachedSubscription>>>,
+    cache_ttl: Duration,
+    /// Tier seeded as a subscription for newly created users. `None` leaves
+    /// new users without a subscription until one is assigned explicitly.
+    default_tier: Option<String>,
+    /// The tenant whose rows this handle sees.
+    tenant: String,
+}
+
+/// Default size of the SQLite connection pool.
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+/// Default time a connection waits on a locked database before erroring.
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_millis(5_000);
+
+impl SubscriptionStore {
+    pub async fn new(database_url: &str) -> Result<Self, sqlx::Error> {
+        Self::open(database_url, DEFAULT_MAX_CONNECTIONS, DEFAULT_BUSY_TIMEOUT).await
+    }
+
+    /// Open the store with an explicit pool size and SQLite busy timeout.
+    /// Every SQLite connection gets WAL mode and the busy timeout applied, so
+    /// concurrent writers queue instead of failing with "database is locked";
+    /// Postgres needs neither and skips both.
+    pub async fn open(
+        database_url: &str,
+        max_connections: u32,
+        busy_timeout: Duration,
+    ) -> Result<Self, sqlx::Error> {
+        static DRIVERS: std::sync::Once = std::sync::Once::new();
+        DRIVERS.call_once(sqlx::any::install_default_drivers);
+        let backend = if database_url.starts_with("postgres") {
+            StoreBackend::Postgres
+        } else {
+            StoreBackend::Sqlite
+        };
+        let busy_timeout_ms = busy_timeout.as_millis() as u64;
+        let is_sqlite = backend == StoreBackend::Sqlite;
+        // An in-memory SQLite database lives and dies with one connection, so
+        // the pool must hold exactly one open forever; a wider pool would hand
+        // out fresh, empty databases.
+        let in_memory = is_sqlite && database_url.contains(":memory:");
+        let pool = AnyPoolOptions::new()
+            .max_connections(if in_memory { 1 } else { max_connections })
+            .min_connections(if in_memory { 1 } else { 0 })
+            .after_connect(move |conn, _meta| {
+                Box::pin(async move {
+                    if is_sqlite {
+                        sqlx::query(&format!("PRAGMA busy_timeout = {busy_timeout_ms}"))
+                            .execute(&mut *conn)
+                            .await?;
+                        sqlx::query("PRAGMA journal_mode = WAL")
+                            .execute(&mut *conn)
+                            .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect(database_url)
+            .await?;
+        Ok(SubscriptionStore {
+            pool,
+            backend,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            cache_ttl: DEFAULT_SUBSCRIPTION_CACHE_TTL,
+            default_tier: None,
+            tenant: DEFAULT_TENANT.into(),
+        })
+    }
+
+    /// A handle on the same store scoped to `tenant`: it shares the pool and
+    /// the subscription cache, but every query it runs is confined to that
+    /// tenant's rows.
+    pub fn for_tenant(&self, tenant: &str) -> SubscriptionStore {
+        SubscriptionStore {
+            pool: self.pool.clone(),
+            backend: self.backend,
+            cache: Arc::clone(&self.cache),
+            cache_ttl: self.cache_ttl,
+            default_tier: self.default_tier.clone(),
+            tenant: tenant.to_string(),
+        }
+    }
+
+    /// Override how long cached subscription records are trusted.
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    /// Seed a subscription at `tier` for users created without one. `None`
+    /// disables seeding.
+    pub fn with_default_tier(mut self, tier: Option<String>) -> Self {
+        self.default_tier = tier;
+        self
+    }
+
+    /// Apply the migration set for this store's backend. The two sets define
+    /// the same logical schema; Postgres gets it in one step since no
+    /// pre-tenancy deployments exist to migrate.
+    pub async fn run_migrations(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        match self.backend {
+            StoreBackend::Sqlite => sqlx::migrate!("./migrations/sqlite").run(&self.pool).await,
+            StoreBackend::Postgres => {
+                sqlx::migrate!("./migrations/postgres")
+                    .run(&self.pool)
+                    .await
+            }
+        }
+    }
+
+    pub fn pool(&self) -> &AnyPool {
+        &self.pool
+    }
+
+    pub async fn create_user(&self, user_id: &str, name: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO users (tenant_id, user_id, name) VALUES ($1, $2, $3)")
+            .bind(&self.tenant)
+            .bind(user_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        self.seed_default_subscription(user_id).await
+    }
+
+    /// Create the user row if it does not exist yet.
+    pub async fn ensure_user(&self, user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO users (tenant_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(&self.tenant)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        self.seed_default_subscription(user_id).await
+    }
+
+    /// Give a new user the configured default tier's quotas, unless they
+    /// already hold a subscription.
+    async fn seed_default_subscription(&self, user_id: &str) -> Result<(), sqlx::Error> {
+        let Some(tier) = &self.default_tier else {
+            return Ok(());
+        };
+        let Some((max_tokens, max_requests)) = tier_quotas(tier) else {
+            tracing::warn!(%tier, "unknown default tier, not seeding a subscription");
+            return Ok(());
+        };
+        sqlx::query(
+            "INSERT INTO subscriptions \
+                 (tenant_id, user_id, tier, max_tokens, tokens_used, max_requests, requests_used) \
+             VALUES ($1, $2, $3, $4, 0, $5, 0) ON CONFLICT DO NOTHING",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(tier)
+        .bind(max_tokens)
+        .bind(max_requests)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list_users(&self, include_inactive: bool) -> Result<Vec<UserRecord>, sqlx::Error> {
+        let query = if include_inactive {
+            "SELECT user_id, name, created_at, \
+                    CAST(CASE WHEN active THEN 1 ELSE 0 END AS BIGINT) AS active \
+             FROM users WHERE tenant_id = $1 ORDER BY user_id"
+        } else {
+            "SELECT user_id, name, created_at, \
+                    CAST(CASE WHEN active THEN 1 ELSE 0 END AS BIGINT) AS active \
+             FROM users WHERE tenant_id = $1 AND active = TRUE ORDER BY user_id"
+        };
+        sqlx::query_as(query)
+            .bind(&self.tenant)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Soft-delete a user: their rows stay, but enforcement rejects them.
+    /// Returns false when the user does not exist.
+    pub async fn deactivate_user(&self, user_id: &str) -> Result<bool, sqlx::Error> {
+        let done =
+            sqlx::query("UPDATE users SET active = FALSE WHERE tenant_id = $1 AND user_id = $2")
+            .bind(&self.tenant)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        self.invalidate(user_id);
+        Ok(done.rows_affected() > 0)
+    }
+
+    /// `None` when the user row does not exist.
+    async fn is_active(&self, user_id: &str) -> Result<Option<bool>, sqlx::Error> {
+        let active: Option<i64> = sqlx::query_scalar(
+            "SELECT CAST(CASE WHEN active THEN 1 ELSE 0 END AS BIGINT) \
+             FROM users WHERE tenant_id = $1 AND user_id = $2",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(active.map(|active| active != 0))
+    }
+
+    pub async fn upsert_subscription(
+        &self,
+        record: &SubscriptionRecord,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO subscriptions \
+                 (tenant_id, user_id, tier, max_tokens, tokens_used, max_requests, \
+                  requests_used, max_requests_per_day, reset_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+             ON CONFLICT (tenant_id, user_id) DO UPDATE SET \
+                 tier = excluded.tier, \
+                 max_tokens = excluded.max_tokens, \
+                 max_requests = excluded.max_requests, \
+                 max_requests_per_day = excluded.max_requests_per_day, \
+                 reset_at = excluded.reset_at",
+        )
+        .bind(&self.tenant)
+        .bind(&record.user_id)
+        .bind(&record.tier)
+        .bind(record.max_tokens)
+        .bind(record.tokens_used)
+        .bind(record.max_requests)
+        .bind(record.requests_used)
+        .bind(record.max_requests_per_day)
+        .bind(&record.reset_at)
+        .execute(&self.pool)
+        .await?;
+        self.invalidate(&record.user_id);
+        Ok(())
+    }
+
+    /// Upsert a batch of subscriptions in one transaction: either every known
+    /// user gets the new quotas or none do. Returns, per record, whether the
+    /// user row existed; unknown users are skipped rather than created, so
+    /// the caller can report them without aborting the cohort.
+    pub async fn bulk_upsert_subscriptions(
+        &self,
+        records: &[SubscriptionRecord],
+    ) -> Result<Vec<bool>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut applied = Vec::with_capacity(records.len());
+        for record in records {
+            let known: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM users WHERE tenant_id = $1 AND user_id = $2",
+            )
+            .bind(&self.tenant)
+            .bind(&record.user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if known == 0 {
+                applied.push(false);
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO subscriptions \
+                     (tenant_id, user_id, tier, max_tokens, tokens_used, max_requests, \
+                      requests_used, max_requests_per_day, reset_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+                 ON CONFLICT (tenant_id, user_id) DO UPDATE SET \
+                     tier = excluded.tier, \
+                     max_tokens = excluded.max_tokens, \
+                     max_requests = excluded.max_requests, \
+                     max_requests_per_day = excluded.max_requests_per_day, \
+                     reset_at = excluded.reset_at",
+            )
+            .bind(&self.tenant)
+            .bind(&record.user_id)
+            .bind(&record.tier)
+            .bind(record.max_tokens)
+            .bind(record.tokens_used)
+            .bind(record.max_requests)
+            .bind(record.requests_used)
+            .bind(record.max_requests_per_day)
+            .bind(&record.reset_at)
+            .execute(&mut *tx)
+            .await?;
+            applied.push(true);
+        }
+        tx.commit().await?;
+        for record in records {
+            self.invalidate(&record.user_id);
+        }
+        Ok(applied)
+    }
+
+    pub async fn get_subscription(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<SubscriptionRecord>, sqlx::Error> {
+        if let Some(cached) = self
+            .cache
+            .read()
+            .expect("cache lock")
+            .get(&self.cache_key(user_id))
+        {
+            if cached.fetched.elapsed() < self.cache_ttl {
+                return Ok(Some(cached.record.clone()));
+            }
+        }
+        self.refresh(user_id).await
+    }
+
+    /// Re-read a subscription from the database, replacing any cached copy.
+    pub async fn refresh(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<SubscriptionRecord>, sqlx::Error> {
+        let record: Option<SubscriptionRecord> = sqlx::query_as(
+            "SELECT user_id, tier, max_tokens, tokens_used, max_requests, requests_used, \
+                    max_requests_per_day, reset_at \
+             FROM subscriptions WHERE tenant_id = $1 AND user_id = $2",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let mut cache = self.cache.write().expect("cache lock");
+        match &record {
+            Some(record) => {
+                cache.insert(
+                    self.cache_key(user_id),
+                    CachedSubscription {
+                        fetched: Instant::now(),
+                        record: record.clone(),
+                    },
+                );
+            }
+            None => {
+                cache.remove(&self.cache_key(user_id));
+            }
+        }
+        Ok(record)
+    }
+
+    /// Check the caller's quota against an estimated token spend, without
+    /// consuming anything.
+    pub async fn enforce_subscription(
+        &self,
+        user_id: &str,
+        estimated_tokens: i64,
+    ) -> Result<SubscriptionRecord, EnforcementError> {
+        if self.is_active(user_id).await? == Some(false) {
+            return Err(EnforcementError::Inactive(user_id.to_string()));
+        }
+        let record = self
+            .get_subscription(user_id)
+            .await?
+            .ok_or_else(|| EnforcementError::NoSubscription(user_id.to_string()))?;
+        if record.tokens_used + estimated_tokens > record.max_tokens {
+            return Err(EnforcementError::TokenQuota {
+                limit: record.max_tokens,
+                used: record.tokens_used,
+            });
+        }
+        if record.requests_used >= record.max_requests {
+            return Err(EnforcementError::RequestQuota {
+                limit: record.max_requests,
+                used: record.requests_used,
+            });
+        }
+        if record.max_requests_per_day > 0 {
+            let used = self.requests_today(user_id).await?;
+            if used >= record.max_requests_per_day {
+                return Err(EnforcementError::DailyRequestQuota {
+                    limit: record.max_requests_per_day,
+                    used,
+                });
+            }
+        }
+        Ok(record)
+    }
+
+    /// Atomically reserve quota for a call: one statement checks the limits
+    /// and increments `tokens_used`/`requests_used`, so two concurrent calls
+    /// can never both pass a check they jointly exceed. Returns the record
+    /// after the reservation; classification of a refused reservation is done
+    /// with a follow-up read.
+    pub async fn try_consume(
+        &self,
+        user_id: &str,
+        estimated_tokens: i64,
+    ) -> Result<SubscriptionRecord, EnforcementError> {
+        self.try_consume_batch(user_id, estimated_tokens, 1).await
+    }
+
+    /// Like [`try_consume`], reserving `requests` slots at once: a
+    /// `tools/call_batch` either fits in the budget as a whole or is refused
+    /// before any entry runs.
+    pub async fn try_consume_batch(
+        &self,
+        user_id: &str,
+        estimated_tokens: i64,
+        requests: i64,
+    ) -> Result<SubscriptionRecord, EnforcementError> {
+        let done = sqlx::query(
+            "UPDATE subscriptions \
+             SET tokens_used = tokens_used + $1, requests_used = requests_used + $2 \
+             WHERE tenant_id = $3 AND user_id = $4 \
+               AND tokens_used + $1 <= max_tokens \
+               AND requests_used + $2 <= max_requests \
+               AND NOT EXISTS (SELECT 1 FROM users \
+                               WHERE tenant_id = $3 AND user_id = $4 AND active = FALSE)",
+        )
+        .bind(estimated_tokens)
+        .bind(requests)
+        .bind(&self.tenant)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if done.rows_affected() == 0 {
+            if self.is_active(user_id).await? == Some(false) {
+                return Err(EnforcementError::Inactive(user_id.to_string()));
+            }
+            let record = self
+                .refresh(user_id)
+                .await?
+                .ok_or_else(|| EnforcementError::NoSubscription(user_id.to_string()))?;
+            if record.tokens_used + estimated_tokens > record.max_tokens {
+                return Err(EnforcementError::TokenQuota {
+                    limit: record.max_tokens,
+                    used: record.tokens_used,
+                });
+            }
+            return Err(EnforcementError::RequestQuota {
+                limit: record.max_requests,
+                used: record.requests_used,
+            });
+        }
+        // The rolling daily cap comes second: a refusal here gives the
+        // lifetime reservation straight back, so nothing leaks.
+        if let Err(err) = self.consume_daily(user_id, requests).await {
+            sqlx::query(
+                "UPDATE subscriptions \
+                 SET tokens_used = CASE WHEN tokens_used > $1 THEN tokens_used - $1 ELSE 0 END, \
+                     requests_used = \
+                         CASE WHEN requests_used > $2 THEN requests_used - $2 ELSE 0 END \
+                 WHERE tenant_id = $3 AND user_id = $4",
+            )
+            .bind(estimated_tokens)
+            .bind(requests)
+            .bind(&self.tenant)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+            self.invalidate(user_id);
+            return Err(err);
+        }
+        Ok(self
+            .refresh(user_id)
+            .await?
+            .expect("subscription row just updated"))
+    }
+
+    /// Count `requests` against today's rolling counter, refusing when the
+    /// subscription's `max_requests_per_day` would be exceeded. Counters are
+    /// keyed by UTC day in `usage_counters`; rows for past days simply stop
+    /// matching, so the reset at each day boundary is automatic.
+    async fn consume_daily(&self, user_id: &str, requests: i64) -> Result<(), EnforcementError> {
+        let day = current_day();
+        // One live row per user: rows from earlier days are swept here rather
+        // than by a background job.
+        sqlx::query("DELETE FROM usage_counters WHERE tenant_id = $1 AND user_id = $2 AND day < $3")
+            .bind(&self.tenant)
+            .bind(user_id)
+            .bind(day)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "INSERT INTO usage_counters (tenant_id, user_id, day, requests) \
+             VALUES ($1, $2, $3, 0) ON CONFLICT DO NOTHING",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(day)
+        .execute(&self.pool)
+        .await?;
+        let done = sqlx::query(
+            "UPDATE usage_counters SET requests = requests + $1 \
+             WHERE tenant_id = $2 AND user_id = $3 AND day = $4 \
+               AND NOT EXISTS (SELECT 1 FROM subscriptions s \
+                               WHERE s.tenant_id = $2 AND s.user_id = $3 \
+                                 AND s.max_requests_per_day > 0 \
+                                 AND usage_counters.requests + $1 > s.max_requests_per_day)",
+        )
+        .bind(requests)
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(day)
+        .execute(&self.pool)
+        .await?;
+        if done.rows_affected() == 0 {
+            let (limit, used): (i64, i64) = sqlx::query_as(
+                "SELECT s.max_requests_per_day, c.requests \
+                 FROM subscriptions s \
+                 JOIN usage_counters c ON c.tenant_id = s.tenant_id AND c.user_id = s.user_id \
+                 WHERE s.tenant_id = $1 AND s.user_id = $2 AND c.day = $3",
+            )
+            .bind(&self.tenant)
+            .bind(user_id)
+            .bind(day)
+            .fetch_one(&self.pool)
+            .await?;
+            return Err(EnforcementError::DailyRequestQuota { limit, used });
+        }
+        Ok(())
+    }
+
+    /// Today's request count from the rolling daily counter.
+    async fn requests_today(&self, user_id: &str) -> Result<i64, sqlx::Error> {
+        let used: Option<i64> = sqlx::query_scalar(
+            "SELECT requests FROM usage_counters \
+             WHERE tenant_id = $1 AND user_id = $2 AND day = $3",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(current_day())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(used.unwrap_or(0))
+    }
+
+    /// Release a reservation made by [`try_consume`] for a call that never
+    /// completed: gives back the estimated tokens and the request slot.
+    pub async fn release_reservation(
+        &self,
+        user_id: &str,
+        estimated_tokens: i64,
+    ) -> Result<(), sqlx::Error> {
+        // CASE instead of a scalar MAX/GREATEST: SQLite spells it max(a, b)
+        // and Postgres GREATEST, so the portable form is the conditional.
+        sqlx::query(
+            "UPDATE subscriptions \
+             SET tokens_used = CASE WHEN tokens_used > $1 THEN tokens_used - $1 ELSE 0 END, \
+                 requests_used = CASE WHEN requests_used > 0 THEN requests_used - 1 ELSE 0 END \
+             WHERE tenant_id = $2 AND user_id = $3",
+        )
+        .bind(estimated_tokens)
+        .bind(&self.tenant)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        // The call never ran, so it should not count against today's cap
+        // either.
+        sqlx::query(
+            "UPDATE usage_counters \
+             SET requests = CASE WHEN requests > 0 THEN requests - 1 ELSE 0 END \
+             WHERE tenant_id = $1 AND user_id = $2 AND day = $3",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(current_day())
+        .execute(&self.pool)
+        .await?;
+        self.invalidate(user_id);
+        Ok(())
+    }
+
+    /// Record consumed usage after a successful call, reconciling the
+    /// reservation when the actual token count differs from the estimate.
+    ///
+    /// The outbox row is durable before any accounting happens: if the apply
+    /// step fails here (DB hiccup, crash), the background reconciler picks
+    /// the row up later, and the claiming update in [`apply_usage`] makes
+    /// sure each row lands exactly once either way.
+    pub async fn record_usage(
+        &self,
+        user_id: &str,
+        tool: &str,
+        actual_tokens: i64,
+        estimated_tokens: i64,
+    ) -> Result<(), sqlx::Error> {
+        let outbox_id = self
+            .enqueue_usage(user_id, tool, actual_tokens, estimated_tokens)
+            .await?;
+        apply_usage(&self.pool, outbox_id).await?;
+        self.invalidate(user_id);
+        Ok(())
+    }
+
+    /// Write one pending-usage row to the outbox; returns its id.
+    async fn enqueue_usage(
+        &self,
+        user_id: &str,
+        tool: &str,
+        actual_tokens: i64,
+        estimated_tokens: i64,
+    ) -> Result<i64, sqlx::Error> {
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO usage_outbox (tenant_id, user_id, tool, actual_tokens, estimated_tokens) \
+             VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(tool)
+        .bind(actual_tokens)
+        .bind(estimated_tokens)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Apply every usage row still pending in the outbox; returns how many
+    /// were applied.
+    pub async fn reconcile_usage(&self) -> Result<u64, sqlx::Error> {
+        let applied = reconcile_pending(&self.pool).await?;
+        if applied > 0 {
+            self.cache.write().expect("cache lock").clear();
+        }
+        Ok(applied)
+    }
+
+    /// Start a background task that periodically sweeps the outbox for usage
+    /// left pending by a failed or interrupted [`record_usage`]. The task
+    /// owns only a pool clone, so cached subscription records catch up via
+    /// their TTL rather than an explicit invalidation.
+    pub fn spawn_usage_reconciler(&self, interval: Duration) {
+        let pool = self.pool.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                match reconcile_pending(&pool).await {
+                    Ok(0) => {}
+                    Ok(rows) => tracing::info!(rows, "applied pending usage from the outbox"),
+                    Err(err) => tracing::warn!(%err, "usage reconciliation failed"),
+                }
+            }
+        });
+    }
+
+    fn invalidate(&self, user_id: &str) {
+        self.cache
+            .write()
+            .expect("cache lock")
+            .remove(&self.cache_key(user_id));
+    }
+
+    fn cache_key(&self, user_id: &str) -> (String, String) {
+        (self.tenant.clone(), user_id.to_string())
+    }
+
+    /// Issue a bearer API token for a user; only its SHA-256 hash is stored.
+    /// Returns `(token_id, secret)` — the secret is shown once.
+    pub async fn issue_token(
+        &self,
+        user_id: &str,
+        scopes: &str,
+    ) -> Result<(String, String), sqlx::Error> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let secret = format!("mcpr_{}", uuid::Uuid::new_v4().simple());
+        sqlx::query(
+            "INSERT INTO api_tokens (id, tenant_id, user_id, token_hash, scopes) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&id)
+        .bind(&self.tenant)
+        .bind(user_id)
+        .bind(crypto::sha256_hex(&secret))
+        .bind(scopes)
+        .execute(&self.pool)
+        .await?;
+        Ok((id, secret))
+    }
+
+    /// Resolve a presented token secret to its scopes string, or `None` when
+    /// no live token with that hash exists. This is the auth-path lookup, so
+    /// it only ever sees the hash of what the client sent; revoked tokens are
+    /// indistinguishable from tokens that never existed.
+    pub async fn token_scopes(&self, secret: &str) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT scopes FROM api_tokens WHERE token_hash = $1 AND revoked_at IS NULL",
+        )
+        .bind(crypto::sha256_hex(secret))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Revoke a token by id. The row is kept with a `revoked_at` timestamp
+    /// for audit rather than deleted; returns whether a live token was found.
+    pub async fn revoke_token(&self, id: &str) -> Result<bool, sqlx::Error> {
+        // The one per-dialect query: `revoked_at` is TEXT, and each backend
+        // spells now-as-text differently.
+        let query = match self.backend {
+            StoreBackend::Sqlite => {
+                "UPDATE api_tokens SET revoked_at = datetime('now') \
+                 WHERE id = $1 AND tenant_id = $2 AND revoked_at IS NULL"
+            }
+            StoreBackend::Postgres => {
+                "UPDATE api_tokens SET revoked_at = now()::text \
+                 WHERE id = $1 AND tenant_id = $2 AND revoked_at IS NULL"
+            }
+        };
+        let result = sqlx::query(query)
+            .bind(id)
+            .bind(&self.tenant)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_tokens(&self) -> Result<Vec<TokenRecord>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, user_id, scopes, created_at FROM api_tokens \
+             WHERE tenant_id = $1 AND revoked_at IS NULL ORDER BY created_at",
+        )
+        .bind(&self.tenant)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+/// Claim and apply one outbox row inside a transaction: the claiming update
+/// flips `applied` only if it is still zero, so a concurrent reconciler (or a
+/// retry of the inline apply) finds nothing left to do. Returns whether this
+/// call did the work.
+async fn apply_usage(pool: &AnyPool, outbox_id: i64) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row: Option<(String, String, String, i64, i64)> = sqlx::query_as(
+        "UPDATE usage_outbox SET applied = 1 WHERE id = $1 AND applied = 0 \
+         RETURNING tenant_id, user_id, tool, actual_tokens, estimated_tokens",
+    )
+    .bind(outbox_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((tenant_id, user_id, tool, actual_tokens, estimated_tokens)) = row else {
+        return Ok(false);
+    };
+    let delta = actual_tokens - estimated_tokens;
+    if delta != 0 {
+        sqlx::query(
+            "UPDATE subscriptions \
+             SET tokens_used = CASE WHEN tokens_used + $1 > 0 THEN tokens_used + $1 ELSE 0 END \
+             WHERE tenant_id = $2 AND user_id = $3",
+        )
+        .bind(delta)
+        .bind(&tenant_id)
+        .bind(&user_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    sqlx::query("INSERT INTO usage_log (tenant_id, user_id, tool, tokens) VALUES ($1, $2, $3, $4)")
+        .bind(&tenant_id)
+        .bind(&user_id)
+        .bind(&tool)
+        .bind(actual_tokens)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Apply all pending outbox rows, oldest first.
+async fn reconcile_pending(pool: &AnyPool) -> Result<u64, sqlx::Error> {
+    let pending: Vec<i64> =
+        sqlx::query_scalar("SELECT id FROM usage_outbox WHERE applied = 0 ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+    let mut applied = 0;
+    for id in pending {
+        if apply_usage(pool, id).await? {
+            applied += 1;
+        }
+    }
+    Ok(applied)
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ProviderRecord {
+    pub slug: String,
+    pub kind: String,
+    /// Encrypted API key; never serialized into API responses.
+    #[serde(skip_serializing)]
+    pub api_key_enc: Option<String>,
+    pub metadata: Option<String>,
+    pub created_at: String,
+}
+
+/// Check a provider slug: non-empty, lowercase ASCII alphanumeric plus
+/// hyphen. Slugs end up in `provider_slug` links and URIs, so spaces, slashes
+/// and case variants would all break lookups. Allowing only lowercase also
+/// makes uniqueness case-insensitive for free — `OpenAI` is rejected rather
+/// than stored alongside `openai`.
+pub fn validate_slug(slug: &str) -> Result<(), String> {
+    if slug.is_empty() {
+        return Err("provider slug must not be empty".into());
+    }
+    if !slug
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(format!(
+            "invalid provider slug {slug:?}: lowercase alphanumerics and '-' only"
+        ));
+    }
+    Ok(())
+}
+
+/// API-key-backed provider registrations. Keys are sealed with the
+/// `MCP_ROUTER_MASTER_KEY` before they touch disk. Like the subscription
+/// store, each handle is scoped to one tenant's rows.
+pub struct ProviderStore {
+    pool: AnyPool,
+    tenant: String,
+}
+
+impl ProviderStore {
+    pub fn new(pool: AnyPool) -> Self {
+        ProviderStore {
+            pool,
+            tenant: DEFAULT_TENANT.into(),
+        }
+    }
+
+    /// A handle on the same store scoped to `tenant`.
+    pub fn for_tenant(&self, tenant: &str) -> ProviderStore {
+        ProviderStore {
+            pool: self.pool.clone(),
+            tenant: tenant.to_string(),
+        }
+    }
+
+    pub async fn put_provider(
+        &self,
+        slug: &str,
+        kind: &str,
+        api_key: Option<&str>,
+        metadata: Option<&Value>,
+    ) -> Result<(), sqlx::Error> {
+        validate_slug(slug).map_err(sqlx::Error::Protocol)?;
+        let sealed = api_key.map(crypto::seal);
+        let metadata = metadata.map(|m| m.to_string());
+        sqlx::query(
+            "INSERT INTO providers (tenant_id, slug, kind, api_key_enc, metadata) \
+             VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (tenant_id, slug) DO UPDATE SET \
+                 kind = excluded.kind, \
+                 api_key_enc = COALESCE(excluded.api_key_enc, providers.api_key_enc), \
+                 metadata = excluded.metadata",
+        )
+        .bind(&self.tenant)
+        .bind(slug)
+        .bind(kind)
+        .bind(sealed)
+        .bind(metadata)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_provider(&self, slug: &str) -> Result<Option<ProviderRecord>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT slug, kind, api_key_enc, metadata, created_at FROM providers \
+             WHERE tenant_id = $1 AND slug = $2",
+        )
+        .bind(&self.tenant)
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn list_providers(&self) -> Result<Vec<ProviderRecord>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT slug, kind, api_key_enc, metadata, created_at FROM providers \
+             WHERE tenant_id = $1 ORDER BY slug",
+        )
+        .bind(&self.tenant)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn delete_provider(&self, slug: &str) -> Result<bool, sqlx::Error> {
+        let done = sqlx::query("DELETE FROM providers WHERE tenant_id = $1 AND slug = $2")
+            .bind(&self.tenant)
+            .bind(slug)
+            .execute(&self.pool)
+            .await?;
+        Ok(done.rows_affected() > 0)
+    }
+
+    /// Decrypt the stored API key for a provider.
+    pub fn decrypt_key(record: &ProviderRecord) -> Option<String> {
+        record.api_key_enc.as_deref().and_then(crypto::open)
+    }
+}
+
+/// Sealing of provider API keys with AES-256-GCM under a key derived from the
+/// `MCP_ROUTER_MASTER_KEY` environment variable.
+pub mod crypto {
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use base64::engine::general_purpose::STANDARD as B64;
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+
+    const NONCE_LEN: usize = 12;
+
+    fn cipher() -> Aes256Gcm {
+        let master =
+            std::env::var("MCP_ROUTER_MASTER_KEY").unwrap_or_else(|_| "insecure-dev-key".into());
+        let digest = Sha256::digest(master.as_bytes());
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&digest))
+    }
+
+    /// Encrypt a secret, returning base64(nonce || ciphertext).
+    pub fn seal(plaintext: &str) -> String {
+        let cipher = cipher();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut out = nonce.to_vec();
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .expect("aes-gcm encrypt");
+        out.extend(ciphertext);
+        B64.encode(out)
+    }
+
+    /// Decrypt a secret produced by [`seal`]; `None` if tampered or the
+    /// master key changed.
+    pub fn open(sealed: &str) -> Option<String> {
+        let raw = B64.decode(sealed).ok()?;
+        if raw.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = raw.split_at(NONCE_LEN);
+        let plaintext = cipher().decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+
+    pub fn sha256_hex(input: &str) -> String {
+        let digest = Sha256::digest(input.as_bytes());
+        let mut hex = String::with_capacity(64);
+        for byte in digest {
+            hex.push_str(&format!("{byte:02x}"));
+        }
+        hex
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn memory_store() -> SubscriptionStore {
+        let store = SubscriptionStore::new("sqlite::memory:").await.unwrap();
+        store.run_migrations().await.unwrap();
+        store
+    }
+
+    fn basic_sub(user_id: &str) -> SubscriptionRecord {
+        let (max_tokens, max_requests) = tier_quotas("basic").unwrap();
+        SubscriptionRecord {
+            user_id: user_id.into(),
+            tier: "basic".into(),
+            max_tokens,
+            tokens_used: 0,
+            max_requests,
+            requests_used: 0,
+            max_requests_per_day: 0,
+            reset_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn provider_slugs_are_validated() {
+        let store = memory_store().await;
+        let providers = ProviderStore::new(store.pool().clone());
+
+        for bad in ["", "My Provider", "open/ai", "OpenAI", "ops_team"] {
+            let err = providers
+                .put_provider(bad, "openai", None, None)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("slug"), "{bad:?}: {err}");
+        }
+
+        providers
+            .put_provider("openai-eu-2", "openai", None, None)
+            .await
+            .unwrap();
+        assert!(providers
+            .get_provider("openai-eu-2")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn subscription_roundtrip_and_usage() {
+        let store = memory_store().await;
+        store.create_user("alice", "Alice").await.unwrap();
+        store.upsert_subscription(&basic_sub("alice")).await.unwrap();
+
+        let record = store.try_consume("alice", 500).await.unwrap();
+        assert_eq!(record.tier, "basic");
+        assert_eq!(record.tokens_used, 500);
+        assert_eq!(record.requests_used, 1);
+
+        // Actual spend came in lower than the estimate: reconcile down.
+        store.record_usage("alice", "fs/read", 300, 500).await.unwrap();
+        let record = store.get_subscription("alice").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 300);
+        assert_eq!(record.requests_used, 1);
+    }
+
+    #[tokio::test]
+    async fn orphaned_usage_is_reconciled_exactly_once() {
+        let store = memory_store().await;
+        store.create_user("carol", "Carol").await.unwrap();
+        store.upsert_subscription(&basic_sub("carol")).await.unwrap();
+        store.try_consume("carol", 500).await.unwrap();
+
+        // Simulate a record_usage that died between the durable enqueue and
+        // the apply: the outbox row exists, the subscription is untouched.
+        store
+            .enqueue_usage("carol", "fs/read", 300, 500)
+            .await
+            .unwrap();
+        let record = store.get_subscription("carol").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 500);
+
+        // The reconciler applies it once...
+        assert_eq!(store.reconcile_usage().await.unwrap(), 1);
+        let record = store.get_subscription("carol").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 300);
+
+        // ...and a second sweep finds nothing, so nothing double-counts.
+        assert_eq!(store.reconcile_usage().await.unwrap(), 0);
+        let record = store.get_subscription("carol").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 300);
+        let logged: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM usage_log WHERE user_id = 'carol'")
+                .fetch_one(store.pool())
+                .await
+                .unwrap();
+        assert_eq!(logged, 1);
+    }
+
+    #[tokio::test]
+    async fn default_tier_seeds_new_users() {
+        let store = memory_store().await.with_default_tier(Some("basic".into()));
+        let (max_tokens, max_requests) = tier_quotas("basic").unwrap();
+
+        // A fresh user can consume immediately, no explicit subscription.
+        store.create_user("newbie", "Newbie").await.unwrap();
+        let record = store.try_consume("newbie", 100).await.unwrap();
+        assert_eq!(record.tier, "basic");
+        assert_eq!(record.max_tokens, max_tokens);
+        assert_eq!(record.max_requests, max_requests);
+
+        // Seeding never tramples an existing subscription.
+        store.create_user("vip", "Vip").await.unwrap();
+        let mut sub = basic_sub("vip");
+        sub.tier = "pro".into();
+        store.upsert_subscription(&sub).await.unwrap();
+        store.ensure_user("vip").await.unwrap();
+        let record = store.get_subscription("vip").await.unwrap().unwrap();
+        assert_eq!(record.tier, "pro");
+
+        // Opting out keeps the old behaviour.
+        let bare = memory_store().await;
+        bare.create_user("solo", "Solo").await.unwrap();
+        assert!(bare.get_subscription("solo").await.unwrap().is_none());
+        let err = bare.try_consume("solo", 1).await.unwrap_err();
+        assert!(matches!(err, EnforcementError::NoSubscription(_)));
+    }
+
+    #[tokio::test]
+    async fn deactivated_users_are_hidden_and_rejected() {
+        let store = memory_store().await;
+        store.create_user("eve", "Eve").await.unwrap();
+        store.create_user("mallory", "Mallory").await.unwrap();
+        store
+            .upsert_subscription(&basic_sub("mallory"))
+            .await
+            .unwrap();
+
+        assert!(store.deactivate_user("mallory").await.unwrap());
+        assert!(!store.deactivate_user("ghost").await.unwrap());
+
+        let visible = store.list_users(false).await.unwrap();
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].user_id, "eve");
+        let all = store.list_users(true).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(!all.iter().find(|u| u.user_id == "mallory").unwrap().active);
+
+        let err = store.try_consume("mallory", 1).await.unwrap_err();
+        assert!(matches!(err, EnforcementError::Inactive(_)));
+    }
+
+    #[tokio::test]
+    async fn release_reservation_gives_back_quota() {
+        let store = memory_store().await;
+        store.create_user("frank", "Frank").await.unwrap();
+        store.upsert_subscription(&basic_sub("frank")).await.unwrap();
+
+        store.try_consume("frank", 500).await.unwrap();
+        store.release_reservation("frank", 500).await.unwrap();
+        let record = store.get_subscription("frank").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 0);
+        assert_eq!(record.requests_used, 0);
+    }
+
+    #[tokio::test]
+    async fn small_pool_survives_concurrent_writes() {
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}/busy.db?mode=rwc", dir.path().display());
+        let store = Arc::new(
+            SubscriptionStore::open(&url, 2, Duration::from_millis(2_000))
+                .await
+                .unwrap(),
+        );
+        store.run_migrations().await.unwrap();
+
+        let mut tasks = Vec::new();
+        for i in 0..20 {
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                store.ensure_user(&format!("writer-{i}")).await
+            }));
+        }
+        for task in tasks {
+            task.await
+                .unwrap()
+                .expect("write failed despite busy timeout");
+        }
+        assert_eq!(store.list_users(false).await.unwrap().len(), 20);
+    }
+
+    #[tokio::test]
+    async fn concurrent_consumption_cannot_overspend() {
+        use std::sync::Arc;
+
+        // A file-backed database so every pooled connection sees one store.
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}/quota.db?mode=rwc", dir.path().display());
+        let store = Arc::new(SubscriptionStore::new(&url).await.unwrap());
+        store.run_migrations().await.unwrap();
+        store.create_user("greedy", "Greedy").await.unwrap();
+        let mut sub = basic_sub("greedy");
+        sub.max_tokens = 50;
+        store.upsert_subscription(&sub).await.unwrap();
+
+        let mut tasks = Vec::new();
+        for _ in 0..20 {
+            let store = store.clone();
+            tasks.push(tokio::spawn(
+                async move { store.try_consume("greedy", 10).await },
+            ));
+        }
+        let mut allowed = 0;
+        for task in tasks {
+            if task.await.unwrap().is_ok() {
+                allowed += 1;
+            }
+        }
+        assert_eq!(allowed, 5, "exactly max_tokens / estimate calls may pass");
+        let record = store.refresh("greedy").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 50);
+    }
+
+    #[tokio::test]
+    async fn quota_exhaustion_is_rejected() {
+        let store = memory_store().await;
+        store.create_user("bob", "Bob").await.unwrap();
+        let mut sub = basic_sub("bob");
+        sub.max_tokens = 100;
+        store.upsert_subscription(&sub).await.unwrap();
+
+        let err = store.enforce_subscription("bob", 101).await.unwrap_err();
+        assert!(matches!(err, EnforcementError::TokenQuota { limit: 100, .. }));
+    }
+
+    #[tokio::test]
+    async fn stale_cache_entry_is_refreshed_after_ttl() {
+        let store = memory_store().await.with_cache_ttl(Duration::from_millis(50));
+        store.create_user("dave", "Dave").await.unwrap();
+        store.upsert_subscription(&basic_sub("dave")).await.unwrap();
+
+        // Prime the cache, then write behind its back, as a second router
+        // instance sharing the database would.
+        store.get_subscription("dave").await.unwrap().unwrap();
+        sqlx::query("UPDATE subscriptions SET tokens_used = 777 WHERE user_id = ?")
+            .bind("dave")
+            .execute(store.pool())
+            .await
+            .unwrap();
+
+        let stale = store.get_subscription("dave").await.unwrap().unwrap();
+        assert_eq!(stale.tokens_used, 0, "entry should still be served stale");
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let fresh = store.get_subscription("dave").await.unwrap().unwrap();
+        assert_eq!(fresh.tokens_used, 777, "TTL expiry should re-read the DB");
+    }
+
+    #[tokio::test]
+    async fn refresh_bypasses_the_cache() {
+        let store = memory_store().await;
+        store.create_user("erin", "Erin").await.unwrap();
+        store.upsert_subscription(&basic_sub("erin")).await.unwrap();
+        store.get_subscription("erin").await.unwrap().unwrap();
+
+        sqlx::query("UPDATE subscriptions SET tokens_used = 42 WHERE user_id = ?")
+            .bind("erin")
+            .execute(store.pool())
+            .await
+            .unwrap();
+
+        let fresh = store.refresh("erin").await.unwrap().unwrap();
+        assert_eq!(fresh.tokens_used, 42);
+    }
+
+    #[tokio::test]
+    async fn missing_subscription_is_distinct() {
+        let store = memory_store().await;
+        let err = store.enforce_subscription("ghost", 1).await.unwrap_err();
+        assert!(matches!(err, EnforcementError::NoSubscription(_)));
+    }
+
+    #[tokio::test]
+    async fn tenants_do_not_see_each_others_rows() {
+        let store = memory_store().await;
+        let tenant_a = store.for_tenant("acme");
+        let tenant_b = store.for_tenant("globex");
+
+        // Both tenants can hold their own "alice"; neither sees the other's.
+        tenant_a.create_user("alice", "Acme Alice").await.unwrap();
+        tenant_b.create_user("alice", "Globex Alice").await.unwrap();
+        tenant_a.upsert_subscription(&basic_sub("alice")).await.unwrap();
+
+        let users = tenant_b.list_users(true).await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].name, "Globex Alice");
+        assert!(tenant_b.get_subscription("alice").await.unwrap().is_none());
+        assert!(store.get_subscription("alice").await.unwrap().is_none());
+
+        // Usage accounting lands on the owning tenant only.
+        tenant_a.try_consume("alice", 10).await.unwrap();
+        tenant_a.record_usage("alice", "fs/read", 10, 10).await.unwrap();
+        let record = tenant_a.get_subscription("alice").await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 10);
+        assert!(tenant_b.get_subscription("alice").await.unwrap().is_none());
+
+        // Providers are scoped the same way.
+        let providers = ProviderStore::new(store.pool().clone());
+        let providers_a = providers.for_tenant("acme");
+        providers_a
+            .put_provider("openai", "openai", None, None)
+            .await
+            .unwrap();
+        assert!(providers_a.get_provider("openai").await.unwrap().is_some());
+        assert!(providers
+            .for_tenant("globex")
+            .get_provider("openai")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn crypto_seal_open_roundtrip() {
+        let sealed = crypto::seal("sk-very-secret");
+        assert_ne!(sealed, "sk-very-secret");
+        assert_eq!(crypto::open(&sealed).as_deref(), Some("sk-very-secret"));
+        assert_eq!(crypto::open("not-base64!!"), None);
+    }
+
+    #[tokio::test]
+    async fn the_daily_request_cap_blocks_and_rolls_over() {
+        let store = memory_store().await;
+        store.create_user("dora", "Dora").await.unwrap();
+        let mut sub = basic_sub("dora");
+        sub.max_requests_per_day = 2;
+        store.upsert_subscription(&sub).await.unwrap();
+
+        store.try_consume("dora", 1).await.unwrap();
+        store.try_consume("dora", 1).await.unwrap();
+        let err = store.try_consume("dora", 1).await.unwrap_err();
+        assert!(
+            matches!(err, EnforcementError::DailyRequestQuota { limit: 2, used: 2 }),
+            "{err}"
+        );
+
+        // Only the daily cap tripped: the lifetime counters were given back.
+        let record = store.get_subscription("dora").await.unwrap().unwrap();
+        assert_eq!(record.requests_used, 2);
+        assert_eq!(record.tokens_used, 2);
+
+        // Age the counter by a day: the boundary reset is automatic, so the
+        // next call goes through again.
+        sqlx::query("UPDATE usage_counters SET day = day - 1")
+            .execute(store.pool())
+            .await
+            .unwrap();
+        store.try_consume("dora", 1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_released_reservation_frees_a_daily_slot_too() {
+        let store = memory_store().await;
+        store.create_user("dan", "Dan").await.unwrap();
+        let mut sub = basic_sub("dan");
+        sub.max_requests_per_day = 1;
+        store.upsert_subscription(&sub).await.unwrap();
+
+        // The one daily slot is reserved, then the call never completes.
+        store.try_consume("dan", 5).await.unwrap();
+        store.release_reservation("dan", 5).await.unwrap();
+
+        // The slot is free again within the same day.
+        store.try_consume("dan", 5).await.unwrap();
+    }
+
+    /// Runs only when `MCP_ROUTER_TEST_PG_URL` points at a disposable
+    /// Postgres database; the default suite stays self-contained on SQLite.
+    #[tokio::test]
+    async fn postgres_backend_round_trips_when_available() {
+        let Ok(url) = std::env::var("MCP_ROUTER_TEST_PG_URL") else {
+            return;
+        };
+        let store = SubscriptionStore::new(&url).await.unwrap();
+        assert_eq!(store.backend, StoreBackend::Postgres);
+        store.run_migrations().await.unwrap();
+
+        // Unique per run: the target database persists across invocations.
+        let user = format!("pg-{}", uuid::Uuid::new_v4().simple());
+        store.create_user(&user, "Postgres Pat").await.unwrap();
+        store.upsert_subscription(&basic_sub(&user)).await.unwrap();
+
+        let record = store.try_consume(&user, 500).await.unwrap();
+        assert_eq!(record.tokens_used, 500);
+        assert_eq!(record.requests_used, 1);
+
+        store.record_usage(&user, "fs/read", 300, 500).await.unwrap();
+        let record = store.get_subscription(&user).await.unwrap().unwrap();
+        assert_eq!(record.tokens_used, 300);
+        assert_eq!(record.requests_used, 1);
+    }
+}
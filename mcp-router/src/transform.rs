@@ -0,0 +1,196 @@
+//! Declarative per-upstream JSON rewrites.
+//!
+//! Upstreams sometimes need small adjustments the router can make in flight:
+//! inject a default `model`, strip a field the upstream rejects, or rename
+//! one the client and server disagree on. A transform is an ordered list of
+//! set/remove/rename rules addressed by JSON pointer (RFC 6901), applied to
+//! outgoing `params` before the forward and to successful `result`s after.
+//! Deliberately not a scripting engine: if a rewrite needs logic, it belongs
+//! in a real proxy upstream.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One rewrite rule. Paths are JSON pointers, e.g. `/arguments/model`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum TransformRule {
+    /// Write `value` at `path`, creating intermediate objects as needed.
+    Set { path: String, value: Value },
+    /// Drop the value at `path`; a missing path is a no-op.
+    Remove { path: String },
+    /// Move the value at `from` to `to`; a no-op when `from` is missing.
+    Rename { from: String, to: String },
+}
+
+/// The configured rule sets for one upstream.
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamTransforms {
+    /// Applied to `params` before each forwarded request.
+    pub request: Vec<TransformRule>,
+    /// Applied to `result` on each successful response.
+    pub response: Vec<TransformRule>,
+}
+
+/// Apply `rules` to `target`, in order.
+pub fn apply(rules: &[TransformRule], target: &mut Value) {
+    for rule in rules {
+        match rule {
+            TransformRule::Set { path, value } => set(target, path, value.clone()),
+            TransformRule::Remove { path } => {
+                take(target, path);
+            }
+            TransformRule::Rename { from, to } => {
+                if let Some(value) = take(target, from) {
+                    set(target, to, value);
+                }
+            }
+        }
+    }
+}
+
+/// Undo JSON-pointer token escaping: `~1` is `/`, `~0` is `~`.
+fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+/// Write `value` at `path`. Missing intermediate objects are created; a path
+/// that runs through a non-container or past the end of an array is dropped
+/// rather than clobbering what is there.
+fn set(root: &mut Value, path: &str, value: Value) {
+    let Some(rest) = path.strip_prefix('/') else {
+        tracing::warn!(%path, "ignoring transform path without leading '/'");
+        return;
+    };
+    set_inner(root, rest, value);
+}
+
+fn set_inner(current: &mut Value, path: &str, value: Value) {
+    match path.split_once('/') {
+        None => {
+            let token = unescape(path);
+            match current {
+                Value::Object(map) => {
+                    map.insert(token, value);
+                }
+                Value::Array(items) => {
+                    if let Some(slot) = token.parse::<usize>().ok().and_then(|i| items.get_mut(i))
+                    {
+                        *slot = value;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some((head, rest)) => {
+            let token = unescape(head);
+            let next = match current {
+                Value::Object(map) => map
+                    .entry(token)
+                    .or_insert_with(|| Value::Object(serde_json::Map::new())),
+                Value::Array(items) => {
+                    match token.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                        Some(item) => item,
+                        None => return,
+                    }
+                }
+                _ => return,
+            };
+            set_inner(next, rest, value);
+        }
+    }
+}
+
+/// Remove and return the value at `path`, or `None` when it is missing.
+fn take(root: &mut Value, path: &str) -> Option<Value> {
+    let rest = path.strip_prefix('/')?;
+    // The parent prefix still holds escaped tokens, so `pointer_mut` can
+    // resolve it; only the leaf needs unescaping by hand.
+    let (parent, leaf) = match rest.rsplit_once('/') {
+        Some((parent, leaf)) => (root.pointer_mut(&format!("/{parent}"))?, leaf),
+        None => (root, rest),
+    };
+    let leaf = unescape(leaf);
+    match parent {
+        Value::Object(map) => map.remove(&leaf),
+        Value::Array(items) => {
+            let index = leaf.parse::<usize>().ok()?;
+            (index < items.len()).then(|| items.remove(index))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn set_creates_intermediate_objects() {
+        let mut target = json!({"name": "gen"});
+        apply(
+            &[TransformRule::Set {
+                path: "/arguments/model".into(),
+                value: json!("small-1"),
+            }],
+            &mut target,
+        );
+        assert_eq!(
+            target,
+            json!({"name": "gen", "arguments": {"model": "small-1"}})
+        );
+    }
+
+    #[test]
+    fn remove_is_a_noop_on_missing_paths() {
+        let mut target = json!({"arguments": {"secret": "x", "keep": 1}});
+        let rules = [
+            TransformRule::Remove {
+                path: "/arguments/secret".into(),
+            },
+            TransformRule::Remove {
+                path: "/arguments/absent".into(),
+            },
+        ];
+        apply(&rules, &mut target);
+        assert_eq!(target, json!({"arguments": {"keep": 1}}));
+    }
+
+    #[test]
+    fn rename_moves_a_value() {
+        let mut target = json!({"content": [{"body": "hi"}]});
+        apply(
+            &[TransformRule::Rename {
+                from: "/content/0/body".into(),
+                to: "/content/0/text".into(),
+            }],
+            &mut target,
+        );
+        assert_eq!(target, json!({"content": [{"text": "hi"}]}));
+    }
+
+    #[test]
+    fn escaped_tokens_address_keys_with_slashes() {
+        let mut target = json!({"a/b": 1});
+        apply(&[TransformRule::Remove { path: "/a~1b".into() }], &mut target);
+        assert_eq!(target, json!({}));
+    }
+
+    #[test]
+    fn rules_apply_in_order() {
+        let mut target = json!({});
+        let rules = [
+            TransformRule::Set {
+                path: "/model".into(),
+                value: json!("draft"),
+            },
+            TransformRule::Rename {
+                from: "/model".into(),
+                to: "/arguments/model".into(),
+            },
+        ];
+        apply(&rules, &mut target);
+        assert_eq!(target, json!({"arguments": {"model": "draft"}}));
+    }
+}
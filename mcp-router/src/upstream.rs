@@ -0,0 +1,2170 @@
+//! Upstream MCP servers: the `Upstream` transport trait, the stdio and HTTP
+//! implementations, and the registry that owns them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex as StdMutex, RwLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use mcp_core::rpc::{Id, Request, Response};
+use mcp_core::PROTOCOL_VERSION;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+/// Default cap on a single line read from a stdio child.
+pub const DEFAULT_MAX_LINE_BYTES: usize = 8 * 1024 * 1024;
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+use crate::config::{ServerConfig, StdioFraming, TransportConfig, UpstreamConfig};
+use crate::transform::{self, UpstreamTransforms};
+
+#[derive(Debug, Error)]
+pub enum UpstreamError {
+    #[error("unknown upstream: {0}")]
+    Unknown(String),
+    #[error("upstream i/o: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("upstream http: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("upstream protocol: {0}")]
+    Protocol(String),
+    #[error("upstream timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("circuit open, retry in {0:?}")]
+    CircuitOpen(Duration),
+    #[error("rate limited, retry in {0:?}")]
+    RateLimited(Duration),
+    #[error("invalid upstream name {0:?}: it contains the namespace separator")]
+    InvalidName(String),
+    #[error("upstream limit reached: max_upstreams is {0}")]
+    LimitReached(usize),
+}
+
+/// Invoked with `(upstream_name, notification)` when an upstream emits a
+/// JSON-RPC notification (a frame without an id) on its transport.
+pub type NotificationHandler = Arc<dyn Fn(&str, &Request) + Send + Sync>;
+
+/// Header and `_meta` key carrying the router-to-router hop count. Each
+/// [`HttpUpstream`] forward increments it; the dispatcher rejects a request
+/// whose count reaches `server.max_hops` so a mesh of routers pointed at each
+/// other cannot aggregate forever.
+pub const HOP_HEADER: &str = "mcp-hops";
+
+/// The hop count a request arrived with, read from `params._meta`.
+pub fn hop_count(params: &Value) -> u64 {
+    params
+        .get("_meta")
+        .and_then(|meta| meta.get(HOP_HEADER))
+        .and_then(Value::as_u64)
+        .unwrap_or(0)
+}
+
+/// A transport to one upstream MCP server.
+#[async_trait]
+pub trait Upstream: Send + Sync {
+    fn kind(&self) -> &'static str;
+
+    async fn call(&self, request: Request) -> Result<Response, UpstreamError>;
+
+    /// Non-secret config summary for admin listings.
+    fn describe(&self) -> Value {
+        json!({})
+    }
+
+    /// Install a sink for server-initiated notifications. Transports that
+    /// cannot surface notifications ignore this.
+    fn set_notification_handler(&self, _handler: NotificationHandler) {}
+
+    /// Stop the transport for shutdown. Returns true when it stopped cleanly
+    /// (or had nothing to stop), false when it had to be force-killed.
+    async fn shutdown(&self) -> bool {
+        true
+    }
+}
+
+// ---------------------------------------------------------------------------
+// stdio transport
+
+/// A child process speaking newline-delimited JSON-RPC on stdio. The child is
+/// spawned lazily on first use and respawned if it dies.
+pub struct StdioUpstream {
+    name: String,
+    command: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    max_line_bytes: usize,
+    framing: StdioFraming,
+    /// Reap the child after this long without a call; `None` disables.
+    idle_timeout: Option<Duration>,
+    protocol_version: String,
+    /// `clientInfo` offered in the `initialize` handshake, when set.
+    client_info: Option<Value>,
+    /// Filesystem roots offered in the `initialize` handshake, when any.
+    roots: Vec<Value>,
+    /// Version the child reported during the handshake, when it differs from
+    /// the one we offered.
+    negotiated: StdMutex<Option<String>>,
+    /// Admits one respawner at a time; held across spawn + handshake so two
+    /// concurrent callers finding a dead child never both spawn one. Kept
+    /// separate from `state` so the (possibly slow) handshake does not sit
+    /// on the per-call I/O lock.
+    lifecycle: Mutex<()>,
+    state: Mutex<Option<StdioState>>,
+    notifications: StdMutex<Option<NotificationHandler>>,
+}
+
+struct StdioState {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// When the child last served a call, for the idle reaper.
+    last_used: Instant,
+}
+
+impl StdioUpstream {
+    pub fn new(name: impl Into<String>, command: impl Into<String>, args: Vec<String>) -> Self {
+        StdioUpstream {
+            name: name.into(),
+            command: command.into(),
+            args,
+            env: HashMap::new(),
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            framing: StdioFraming::Ndjson,
+            idle_timeout: None,
+            protocol_version: PROTOCOL_VERSION.into(),
+            client_info: None,
+            roots: Vec::new(),
+            negotiated: StdMutex::new(None),
+            lifecycle: Mutex::new(()),
+            state: Mutex::new(None),
+            notifications: StdMutex::new(None),
+        }
+    }
+
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_max_line_bytes(mut self, max_line_bytes: usize) -> Self {
+        self.max_line_bytes = max_line_bytes;
+        self
+    }
+
+    pub fn with_framing(mut self, framing: StdioFraming) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub fn with_protocol_version(mut self, protocol_version: impl Into<String>) -> Self {
+        self.protocol_version = protocol_version.into();
+        self
+    }
+
+    /// Identify the router in the `initialize` handshake.
+    pub fn with_client_info(mut self, client_info: Value) -> Self {
+        self.client_info = Some(client_info);
+        self
+    }
+
+    /// Filesystem roots declared to the child in the `initialize` handshake.
+    pub fn with_roots(mut self, roots: Vec<Value>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Make sure a live, initialized child exists. Idempotent under
+    /// concurrency: the lifecycle lock admits one respawner, and everyone
+    /// queued behind it finds the fresh child already installed. The spawn
+    /// and handshake run against a detached state, so no per-call I/O is
+    /// blocked behind a slow initialize.
+    async fn ensure_process(&self) -> Result<(), UpstreamError> {
+        let _respawn = self.lifecycle.lock().await;
+        {
+            let mut state = self.state.lock().await;
+            if let Some(existing) = state.as_mut() {
+                // A child that exited will be respawned; one still running
+                // is fine.
+                if existing.child.try_wait()?.is_none() {
+                    return Ok(());
+                }
+                tracing::warn!(upstream = %self.name, "stdio child exited, respawning");
+                *state = None;
+            }
+        }
+        let fresh = self.spawn_and_initialize().await?;
+        *self.state.lock().await = Some(fresh);
+        Ok(())
+    }
+
+    /// Spawn a child and run the `initialize` handshake against it.
+    async fn spawn_and_initialize(&self) -> Result<StdioState, UpstreamError> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().expect("child stdin piped");
+        let stdout = BufReader::new(child.stdout.take().expect("child stdout piped"));
+        let mut fresh = StdioState {
+            child,
+            stdin,
+            stdout,
+            last_used: Instant::now(),
+        };
+
+        let mut params = json!({"protocolVersion": self.protocol_version});
+        if let Some(info) = &self.client_info {
+            params["clientInfo"] = info.clone();
+        }
+        if !self.roots.is_empty() {
+            params["roots"] = Value::Array(self.roots.clone());
+        }
+        let init = Request::new("initialize", params);
+        self.write_request(&mut fresh.stdin, &init).await?;
+        let response = self.read_response(&mut fresh.stdout, &init.id).await?;
+        if let Some(err) = response.error {
+            return Err(UpstreamError::Protocol(format!(
+                "initialize failed: {}",
+                err.message
+            )));
+        }
+        if let Some(version) = response
+            .result
+            .as_ref()
+            .and_then(|r| r.get("protocolVersion"))
+            .and_then(Value::as_str)
+        {
+            if version != self.protocol_version {
+                tracing::info!(
+                    upstream = %self.name,
+                    offered = %self.protocol_version,
+                    negotiated = %version,
+                    "upstream negotiated a different protocol version"
+                );
+            }
+            *self.negotiated.lock().expect("negotiated lock") = Some(version.to_string());
+        }
+        tracing::info!(upstream = %self.name, command = %self.command, "stdio upstream initialized");
+        Ok(fresh)
+    }
+
+    async fn write_request(
+        &self,
+        stdin: &mut ChildStdin,
+        request: &Request,
+    ) -> Result<(), UpstreamError> {
+        let body = serde_json::to_vec(request)
+            .map_err(|e| UpstreamError::Protocol(format!("encode request: {e}")))?;
+        let frame = match self.framing {
+            StdioFraming::Ndjson => {
+                let mut frame = body;
+                frame.push(b'\n');
+                frame
+            }
+            StdioFraming::Lsp => {
+                let mut frame = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+                frame.extend_from_slice(&body);
+                frame
+            }
+        };
+        stdin.write_all(&frame).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Read one frame in the configured framing, as its raw JSON text.
+    async fn read_frame(&self, stdout: &mut BufReader<ChildStdout>) -> Result<String, UpstreamError> {
+        match self.framing {
+            StdioFraming::Ndjson => self.read_line(stdout).await,
+            StdioFraming::Lsp => self.read_lsp_frame(stdout).await,
+        }
+    }
+
+    /// Read one LSP-style frame: header lines up to the blank separator, then
+    /// exactly `Content-Length` body bytes. The declared length is bounded by
+    /// `max_line_bytes`, same as an ndjson line.
+    async fn read_lsp_frame(
+        &self,
+        stdout: &mut BufReader<ChildStdout>,
+    ) -> Result<String, UpstreamError> {
+        let mut content_length: Option<usize> = None;
+        loop {
+            let line = self.read_line(stdout).await?;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                break;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            if key.eq_ignore_ascii_case("content-length") {
+                content_length = Some(value.trim().parse().map_err(|_| {
+                    UpstreamError::Protocol(format!(
+                        "bad Content-Length from {}: {value:?}",
+                        self.name
+                    ))
+                })?);
+            }
+        }
+        let len = content_length.ok_or_else(|| {
+            UpstreamError::Protocol(format!("frame from {} lacks Content-Length", self.name))
+        })?;
+        if len > self.max_line_bytes {
+            return Err(UpstreamError::Protocol(format!(
+                "frame from {} exceeds max_line_bytes ({} bytes)",
+                self.name, self.max_line_bytes
+            )));
+        }
+        let mut body = vec![0u8; len];
+        stdout.read_exact(&mut body).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Read one newline-terminated frame as raw bytes. Partial writes from
+    /// the child (a frame split across flushes) are accumulated until the
+    /// newline arrives; a frame growing past `max_line_bytes` is rejected
+    /// with a clear error instead of buffering without bound. Invalid UTF-8
+    /// is replaced rather than failing the connection.
+    async fn read_line(&self, stdout: &mut BufReader<ChildStdout>) -> Result<String, UpstreamError> {
+        let mut buf: Vec<u8> = Vec::new();
+        loop {
+            let available = stdout.fill_buf().await?;
+            if available.is_empty() {
+                if buf.is_empty() {
+                    return Err(UpstreamError::Protocol("stdio child closed stdout".into()));
+                }
+                // Final frame without a trailing newline.
+                break;
+            }
+            match available.iter().position(|&b| b == b'\n') {
+                Some(newline) => {
+                    buf.extend_from_slice(&available[..newline]);
+                    stdout.consume(newline + 1);
+                    break;
+                }
+                None => {
+                    buf.extend_from_slice(available);
+                    let taken = available.len();
+                    stdout.consume(taken);
+                }
+            }
+            if buf.len() > self.max_line_bytes {
+                return Err(UpstreamError::Protocol(format!(
+                    "line from {} exceeds max_line_bytes ({} bytes)",
+                    self.name, self.max_line_bytes
+                )));
+            }
+        }
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// Read frames until the response to `expected` arrives. Notification
+    /// frames (no id) that the child interleaves before its response are
+    /// handed to the notification sink rather than mis-parsed as the call
+    /// result, and a response with a different id — typically the answer to
+    /// an earlier call that timed out and left its reply in the pipe — is
+    /// dropped instead of being mis-correlated with this call.
+    async fn read_response(
+        &self,
+        stdout: &mut BufReader<ChildStdout>,
+        expected: &Id,
+    ) -> Result<Response, UpstreamError> {
+        loop {
+            let line = self.read_frame(stdout).await?;
+            let frame: Value = serde_json::from_str(&line)
+                .map_err(|e| UpstreamError::Protocol(format!("bad frame: {e}")))?;
+            if frame.get("method").is_some() && frame.get("id").is_none() {
+                match serde_json::from_value::<Request>(frame) {
+                    Ok(notification) => self.dispatch_notification(&notification),
+                    Err(err) => {
+                        tracing::warn!(upstream = %self.name, %err, "dropping malformed notification");
+                    }
+                }
+                continue;
+            }
+            let response = serde_json::from_value::<Response>(frame)
+                .map_err(|e| UpstreamError::Protocol(format!("bad response: {e}")))?;
+            if &response.id != expected {
+                tracing::warn!(
+                    upstream = %self.name,
+                    expected = ?expected,
+                    got = ?response.id,
+                    "dropping response with a stale id"
+                );
+                continue;
+            }
+            return Ok(response);
+        }
+    }
+
+    /// Start a background task that kills the child once it has sat idle
+    /// past the configured timeout; the next call respawns it lazily through
+    /// [`Self::ensure_process`]. No-op without an idle timeout. The task
+    /// holds a weak reference, so it winds down when the upstream is
+    /// replaced or removed.
+    pub fn spawn_idle_reaper(self: &Arc<Self>) {
+        let Some(idle) = self.idle_timeout else {
+            return;
+        };
+        let upstream = Arc::downgrade(self);
+        let cadence = (idle / 2).max(Duration::from_millis(10));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(cadence);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let Some(upstream) = upstream.upgrade() else {
+                    break;
+                };
+                let mut state = upstream.state.lock().await;
+                let expired = state
+                    .as_ref()
+                    .is_some_and(|live| live.last_used.elapsed() >= idle);
+                if expired {
+                    tracing::info!(upstream = %upstream.name, "reaping idle stdio child");
+                    // kill_on_drop takes the child down with the state.
+                    *state = None;
+                }
+            }
+        });
+    }
+
+    fn dispatch_notification(&self, notification: &Request) {
+        let handler = self.notifications.lock().expect("notifications lock").clone();
+        if let Some(handler) = handler {
+            handler(&self.name, notification);
+        } else {
+            tracing::debug!(
+                upstream = %self.name,
+                method = %notification.method,
+                "notification dropped (no handler installed)"
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Upstream for StdioUpstream {
+    fn kind(&self) -> &'static str {
+        "stdio"
+    }
+
+    async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+        // Two attempts: if a concurrent caller's failed I/O (or the idle
+        // reaper) tears the state down between our handshake and our turn on
+        // the pipe, respawn once rather than failing a call the child never
+        // saw.
+        for _ in 0..2 {
+            self.ensure_process().await?;
+            let mut state = self.state.lock().await;
+            let Some(live) = state.as_mut() else {
+                continue;
+            };
+
+            let outcome = async {
+                self.write_request(&mut live.stdin, &request).await?;
+                self.read_response(&mut live.stdout, &request.id).await
+            }
+            .await;
+
+            if outcome.is_err() {
+                // Drop the broken pipe pair so the next call respawns cleanly.
+                *state = None;
+            } else if let Some(live) = state.as_mut() {
+                live.last_used = Instant::now();
+            }
+            return outcome;
+        }
+        Err(UpstreamError::Protocol(format!(
+            "{}: stdio child unavailable after respawn",
+            self.name
+        )))
+    }
+
+    async fn shutdown(&self) -> bool {
+        // Hold the lifecycle lock so no racing caller respawns a child while
+        // this one is being taken down.
+        let _respawn = self.lifecycle.lock().await;
+        let Some(mut live) = self.state.lock().await.take() else {
+            return true;
+        };
+        // Closing stdin asks a well-behaved child to exit; give it a moment
+        // before falling back to the kill.
+        drop(live.stdin);
+        match tokio::time::timeout(Duration::from_millis(500), live.child.wait()).await {
+            Ok(_) => true,
+            Err(_) => {
+                tracing::warn!(upstream = %self.name, "stdio child ignored stdin close, killing");
+                let _ = live.child.start_kill();
+                false
+            }
+        }
+    }
+
+    fn describe(&self) -> Value {
+        let negotiated = self.negotiated.lock().expect("negotiated lock").clone();
+        json!({
+            "command": self.command,
+            "args": self.args,
+            "protocolVersion": negotiated.unwrap_or_else(|| self.protocol_version.clone()),
+        })
+    }
+
+    fn set_notification_handler(&self, handler: NotificationHandler) {
+        *self.notifications.lock().expect("notifications lock") = Some(handler);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTTP transport
+
+/// How long a replica sits out after a failed request before it is tried
+/// again.
+const REPLICA_COOLDOWN: Duration = Duration::from_secs(15);
+
+/// One URL in an HTTP upstream's replica pool, with its failure state.
+struct Replica {
+    url: String,
+    down_until: StdMutex<Option<Instant>>,
+}
+
+impl Replica {
+    fn new(url: String) -> Self {
+        Replica {
+            url,
+            down_until: StdMutex::new(None),
+        }
+    }
+
+    fn is_down(&self) -> bool {
+        matches!(*self.down_until.lock().expect("replica lock"),
+                 Some(until) if Instant::now() < until)
+    }
+
+    fn mark_down(&self) {
+        *self.down_until.lock().expect("replica lock") = Some(Instant::now() + REPLICA_COOLDOWN);
+    }
+
+    fn mark_up(&self) {
+        *self.down_until.lock().expect("replica lock") = None;
+    }
+}
+
+/// A remote MCP server reached by POSTing JSON-RPC to one or more replica
+/// URLs. Calls round-robin across the pool; a replica that fails to answer
+/// is benched for [`REPLICA_COOLDOWN`] and the call retries the next one.
+pub struct HttpUpstream {
+    name: String,
+    replicas: Vec<Replica>,
+    cursor: AtomicUsize,
+    bearer: Option<String>,
+    headers: HeaderMap,
+    /// Current protocol version: the configured default until the upstream
+    /// negotiates a different one through a pass-through `initialize`.
+    protocol_version: StdMutex<String>,
+    client: reqwest::Client,
+    notifications: StdMutex<Option<NotificationHandler>>,
+    /// Set when the provider answers 429: calls fail fast with
+    /// [`UpstreamError::RateLimited`] until the advertised reset passes.
+    rate_limited_until: StdMutex<Option<Instant>>,
+    /// Mints short-lived bearer tokens on demand; overrides `bearer`.
+    token_source: Option<TokenSource>,
+}
+
+/// A shell command that prints `{"token", "expires_in"}` JSON, plus the last
+/// token it minted. The async mutex doubles as single-flight: concurrent
+/// calls catching an expired token wait for one refresh instead of racing
+/// their own.
+struct TokenSource {
+    command: String,
+    cached: tokio::sync::Mutex<Option<(String, Instant)>>,
+}
+
+/// Cooldown applied to a 429 that carries no parseable reset header.
+const DEFAULT_RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(1);
+
+/// Parse a `Retry-After` value. Providers send whole seconds; the HTTP-date
+/// form the RFC also allows is not seen from API gateways and is ignored.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// Parse OpenAI's compact `x-ratelimit-reset-*` durations: a sequence of
+/// `<number><unit>` terms such as `120ms`, `7.66s` or `6m30s`.
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = Duration::ZERO;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let amount: f64 = rest[..split].parse().ok()?;
+        let tail = &rest[split..];
+        let (factor, tail) = if let Some(tail) = tail.strip_prefix("ms") {
+            (0.001, tail)
+        } else if let Some(tail) = tail.strip_prefix('s') {
+            (1.0, tail)
+        } else if let Some(tail) = tail.strip_prefix('m') {
+            (60.0, tail)
+        } else if let Some(tail) = tail.strip_prefix('h') {
+            (3600.0, tail)
+        } else {
+            return None;
+        };
+        total += Duration::from_secs_f64(amount * factor);
+        rest = tail;
+    }
+    (total > Duration::ZERO).then_some(total)
+}
+
+impl HttpUpstream {
+    pub fn new(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        bearer: Option<String>,
+        headers: &HashMap<String, String>,
+        protocol_version: impl Into<String>,
+        user_agent: &str,
+    ) -> Result<Self, UpstreamError> {
+        let name = name.into();
+        // Reject bad header names/values at registration so a typo in the
+        // config fails the mount rather than every later call.
+        let mut header_map = HeaderMap::new();
+        for (key, value) in headers {
+            let key = HeaderName::from_bytes(key.as_bytes()).map_err(|_| {
+                UpstreamError::Protocol(format!("{name}: invalid header name {key:?}"))
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|_| {
+                UpstreamError::Protocol(format!("{name}: invalid value for header {key}"))
+            })?;
+            header_map.insert(key, value);
+        }
+        let client = reqwest::Client::builder().user_agent(user_agent).build()?;
+        Ok(HttpUpstream {
+            name,
+            replicas: vec![Replica::new(url.into())],
+            cursor: AtomicUsize::new(0),
+            bearer,
+            headers: header_map,
+            protocol_version: StdMutex::new(protocol_version.into()),
+            client,
+            notifications: StdMutex::new(None),
+            rate_limited_until: StdMutex::new(None),
+            token_source: None,
+        })
+    }
+
+    /// Replace the single-URL pool with a set of replicas. A URL listed more
+    /// than once gets a proportionally larger share of the rotation.
+    pub fn with_replicas(mut self, urls: Vec<String>) -> Self {
+        if !urls.is_empty() {
+            self.replicas = urls.into_iter().map(Replica::new).collect();
+        }
+        self
+    }
+
+    /// Mint bearer tokens by running `command` instead of using a static
+    /// `bearer`; see [`TokenSource`].
+    pub fn with_token_command(mut self, command: Option<String>) -> Self {
+        self.token_source = command.map(|command| TokenSource {
+            command,
+            cached: tokio::sync::Mutex::new(None),
+        });
+        self
+    }
+
+    /// The bearer to send right now: the cached minted token while it is
+    /// fresh, a newly minted one once 90% of its lifetime has passed (so a
+    /// token never goes out on the wire moments before expiring), or the
+    /// static `bearer` when no `token_command` is configured.
+    async fn current_bearer(&self) -> Result<Option<String>, UpstreamError> {
+        let Some(source) = &self.token_source else {
+            return Ok(self.bearer.clone());
+        };
+        let mut cached = source.cached.lock().await;
+        if let Some((token, refresh_at)) = &*cached {
+            if Instant::now() < *refresh_at {
+                return Ok(Some(token.clone()));
+            }
+        }
+        let output = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&source.command)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(UpstreamError::Protocol(format!(
+                "{}: token command exited with {}",
+                self.name, output.status
+            )));
+        }
+        let minted: Value = serde_json::from_slice(&output.stdout).map_err(|err| {
+            UpstreamError::Protocol(format!("{}: token command output: {err}", self.name))
+        })?;
+        let Some(token) = minted.get("token").and_then(Value::as_str) else {
+            return Err(UpstreamError::Protocol(format!(
+                "{}: token command output has no \"token\"",
+                self.name
+            )));
+        };
+        let lifetime = minted
+            .get("expires_in")
+            .and_then(Value::as_f64)
+            .unwrap_or(300.0);
+        let refresh_at = Instant::now() + Duration::from_secs_f64(lifetime * 0.9);
+        *cached = Some((token.to_string(), refresh_at));
+        Ok(Some(token.to_string()))
+    }
+
+    /// POST the request to one replica, failing on transport errors and
+    /// non-2xx statuses. Body handling happens in `call` so a parse error on
+    /// a reachable replica is not mistaken for a dead one.
+    async fn send_to(
+        &self,
+        replica: &Replica,
+        request: &Request,
+        version: &str,
+        hops: u64,
+        bearer: Option<&str>,
+    ) -> Result<reqwest::Response, UpstreamError> {
+        let mut builder = self
+            .client
+            .post(&replica.url)
+            .header("Accept", "application/json, text/event-stream")
+            .header("MCP-Protocol-Version", version)
+            .header(HOP_HEADER, hops)
+            // Configured headers go last so they win over the defaults.
+            .headers(self.headers.clone())
+            .json(request);
+        if let Some(bearer) = bearer {
+            builder = builder.bearer_auth(bearer);
+        }
+        let resp = builder.send().await?;
+        if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            // Honor the provider's advertised reset: `Retry-After` (seconds)
+            // or OpenAI's `x-ratelimit-reset-{requests,tokens}` durations.
+            let headers = resp.headers();
+            let retry_in = headers
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_retry_after)
+                .or_else(|| {
+                    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+                        .iter()
+                        .filter_map(|name| headers.get(*name))
+                        .filter_map(|v| v.to_str().ok())
+                        .find_map(parse_reset_duration)
+                })
+                .unwrap_or(DEFAULT_RATE_LIMIT_COOLDOWN);
+            *self.rate_limited_until.lock().expect("rate limit lock") =
+                Some(Instant::now() + retry_in);
+            return Err(UpstreamError::RateLimited(retry_in));
+        }
+        if !resp.status().is_success() {
+            return Err(UpstreamError::Protocol(format!(
+                "{} returned {}",
+                self.name,
+                resp.status()
+            )));
+        }
+        Ok(resp)
+    }
+
+    /// Drain a `text/event-stream` body (streamable HTTP transport): each SSE
+    /// event carries one JSON-RPC message. Notifications are handed to the
+    /// notification sink as they arrive; the response whose id matches the
+    /// request is the call's result, and stray responses with other ids are
+    /// dropped rather than mis-correlated.
+    async fn read_sse_response(
+        &self,
+        mut resp: reqwest::Response,
+        expected: &Id,
+    ) -> Result<Response, UpstreamError> {
+        let mut buffer = String::new();
+        let mut last_response = None;
+        while let Some(chunk) = resp.chunk().await? {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buffer.find("\n\n") {
+                let event = buffer[..end].to_string();
+                buffer.drain(..end + 2);
+                self.handle_sse_event(&event, &mut last_response, expected);
+            }
+        }
+        // A final event without the terminating blank line still counts.
+        if !buffer.trim().is_empty() {
+            let event = std::mem::take(&mut buffer);
+            self.handle_sse_event(&event, &mut last_response, expected);
+        }
+        last_response.ok_or_else(|| {
+            UpstreamError::Protocol(format!(
+                "{}: event stream ended without a matching response",
+                self.name
+            ))
+        })
+    }
+
+    fn handle_sse_event(&self, event: &str, last_response: &mut Option<Response>, expected: &Id) {
+        let data = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|line| line.strip_prefix(' ').unwrap_or(line).trim_end_matches('\r'))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if data.is_empty() {
+            return;
+        }
+        let frame: Value = match serde_json::from_str(&data) {
+            Ok(frame) => frame,
+            Err(err) => {
+                tracing::warn!(upstream = %self.name, %err, "dropping malformed SSE frame");
+                return;
+            }
+        };
+        if frame.get("method").is_some() && frame.get("id").is_none() {
+            match serde_json::from_value::<Request>(frame) {
+                Ok(notification) => {
+                    let handler = self.notifications.lock().expect("notifications lock").clone();
+                    if let Some(handler) = handler {
+                        handler(&self.name, &notification);
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!(upstream = %self.name, %err, "dropping malformed notification");
+                }
+            }
+            return;
+        }
+        match serde_json::from_value::<Response>(frame) {
+            Ok(response) if &response.id == expected => *last_response = Some(response),
+            Ok(response) => {
+                tracing::warn!(
+                    upstream = %self.name,
+                    expected = ?expected,
+                    got = ?response.id,
+                    "dropping SSE response with a stale id"
+                );
+            }
+            Err(err) => {
+                tracing::warn!(upstream = %self.name, %err, "dropping malformed SSE response");
+            }
+        }
+    }
+}
+
+/// An in-memory upstream backed by an async closure, for embedding the
+/// router in another application: the handler runs in-process, so custom
+/// tools can be mounted without a subprocess or a socket. Register one with
+/// [`UpstreamRegistry::register_dyn`].
+pub struct FnUpstream<F> {
+    handler: F,
+}
+
+impl<F, Fut> FnUpstream<F>
+where
+    F: Fn(Request) -> Fut + Send + Sync,
+    Fut: std::future::Future<Output = Response> + Send,
+{
+    pub fn new(handler: F) -> Self {
+        FnUpstream { handler }
+    }
+}
+
+#[async_trait]
+impl<F, Fut> Upstream for FnUpstream<F>
+where
+    F: Fn(Request) -> Fut + Send + Sync,
+    Fut: std::future::Future<Output = Response> + Send,
+{
+    fn kind(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+        Ok((self.handler)(request).await)
+    }
+}
+
+#[async_trait]
+impl Upstream for HttpUpstream {
+    fn kind(&self) -> &'static str {
+        "http"
+    }
+
+    async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+        // Still inside a provider-advertised rate-limit window: fail fast
+        // rather than hammering it. The limit is account-wide, so it gates
+        // every replica, not just the one that answered 429.
+        {
+            let mut until = self.rate_limited_until.lock().expect("rate limit lock");
+            match *until {
+                Some(reset) if Instant::now() < reset => {
+                    return Err(UpstreamError::RateLimited(
+                        reset.saturating_duration_since(Instant::now()),
+                    ));
+                }
+                Some(_) => *until = None,
+                None => {}
+            }
+        }
+        let mut request = request;
+        let version = self.protocol_version.lock().expect("version lock").clone();
+        let is_initialize = request.method == "initialize";
+        // One hop further than the request arrived with, stamped into both
+        // the header and `_meta` so a downstream router sees it either way.
+        let hops = hop_count(&request.params) + 1;
+        if request.params.is_null() {
+            request.params = json!({});
+        }
+        let meta_indexable = request
+            .params
+            .get("_meta")
+            .map(Value::is_object)
+            .unwrap_or(true);
+        if request.params.is_object() && meta_indexable {
+            request.params["_meta"][HOP_HEADER] = json!(hops);
+        }
+        // Rotate through the pool, healthy replicas first. Benched replicas
+        // stay in the order as a last resort so a full outage still probes
+        // them instead of failing without trying anything.
+        let bearer = self.current_bearer().await?;
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let total = self.replicas.len();
+        let mut order: Vec<&Replica> = (0..total)
+            .map(|offset| &self.replicas[(start + offset) % total])
+            .collect();
+        order.sort_by_key(|replica| replica.is_down());
+        let mut resp = None;
+        let mut last_err = None;
+        for replica in order {
+            match self
+                .send_to(replica, &request, &version, hops, bearer.as_deref())
+                .await
+            {
+                Ok(response) => {
+                    replica.mark_up();
+                    resp = Some(response);
+                    break;
+                }
+                // A rate limit is the provider's, not this replica's: the
+                // other replicas share the same account, so trying them would
+                // only burn more of the budget.
+                Err(err @ UpstreamError::RateLimited(_)) => return Err(err),
+                Err(err) => {
+                    tracing::warn!(
+                        upstream = %self.name,
+                        url = %replica.url,
+                        %err,
+                        "replica failed, benching it"
+                    );
+                    replica.mark_down();
+                    last_err = Some(err);
+                }
+            }
+        }
+        let resp = match resp {
+            Some(resp) => resp,
+            None => return Err(last_err.expect("at least one replica was tried")),
+        };
+        let is_sse = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("text/event-stream"));
+        let response = if is_sse {
+            self.read_sse_response(resp, &request.id).await?
+        } else {
+            let response = resp.json::<Response>().await?;
+            // One POST, one response: an id that answers something else is a
+            // correlation bug on the server's side, not this call's result.
+            if response.id != request.id {
+                return Err(UpstreamError::Protocol(format!(
+                    "{}: response id {:?} does not match request id {:?}",
+                    self.name, response.id, request.id
+                )));
+            }
+            response
+        };
+        if is_initialize {
+            if let Some(negotiated) = response
+                .result
+                .as_ref()
+                .and_then(|r| r.get("protocolVersion"))
+                .and_then(Value::as_str)
+            {
+                if negotiated != version {
+                    tracing::info!(
+                        upstream = %self.name,
+                        offered = %version,
+                        %negotiated,
+                        "upstream negotiated a different protocol version"
+                    );
+                    *self.protocol_version.lock().expect("version lock") = negotiated.to_string();
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    fn describe(&self) -> Value {
+        // Header names only: values may carry credentials.
+        let headers: Vec<&str> = self.headers.keys().map(|k| k.as_str()).collect();
+        let mut description = json!({
+            "url": self.replicas[0].url,
+            "headers": headers,
+            "protocolVersion": *self.protocol_version.lock().expect("version lock"),
+        });
+        if self.replicas.len() > 1 {
+            let urls: Vec<&str> = self.replicas.iter().map(|r| r.url.as_str()).collect();
+            description["urls"] = json!(urls);
+        }
+        description
+    }
+
+    fn set_notification_handler(&self, handler: NotificationHandler) {
+        *self.notifications.lock().expect("notifications lock") = Some(handler);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// circuit breaker
+
+/// Trips open after `threshold` consecutive failures and rejects calls for
+/// `cooldown`, after which one probe call is allowed through.
+pub struct Breaker {
+    failures: AtomicU32,
+    open_until: StdMutex<Option<Instant>>,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl Breaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Breaker {
+            failures: AtomicU32::new(0),
+            open_until: StdMutex::new(None),
+            threshold,
+            cooldown,
+        }
+    }
+
+    pub fn check(&self) -> Result<(), UpstreamError> {
+        let mut open_until = self.open_until.lock().expect("breaker lock");
+        match *open_until {
+            Some(until) if Instant::now() < until => Err(UpstreamError::CircuitOpen(
+                until.saturating_duration_since(Instant::now()),
+            )),
+            Some(_) => {
+                // Cooldown elapsed: allow a probe, stay half-open.
+                *open_until = None;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn on_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+    }
+
+    pub fn on_failure(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.threshold {
+            *self.open_until.lock().expect("breaker lock") = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open_until
+            .lock()
+            .expect("breaker lock")
+            .map(|until| Instant::now() < until)
+            .unwrap_or(false)
+    }
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Breaker::new(5, Duration::from_secs(30))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// handle + registry
+
+/// An allow/deny list over upstream-local names. An empty allow list permits
+/// everything; a deny entry always wins.
+#[derive(Clone, Default)]
+pub struct NameFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl NameFilter {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        NameFilter { allow, deny }
+    }
+
+    pub fn permits(&self, name: &str) -> bool {
+        if self.deny.iter().any(|d| d == name) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|a| a == name)
+    }
+}
+
+/// The per-upstream catalog filters: which tools, prompts and resources the
+/// router exposes. Resources are matched on the upstream URI before the
+/// router re-encodes it.
+#[derive(Clone, Default)]
+pub struct UpstreamFilters {
+    pub tools: NameFilter,
+    pub prompts: NameFilter,
+    pub resources: NameFilter,
+}
+
+/// Outcome of the most recent liveness probe against one upstream.
+/// `healthy: None` means the upstream has not been probed yet.
+#[derive(Clone, Copy, Default)]
+pub struct HealthStatus {
+    pub healthy: Option<bool>,
+    pub checked_at: Option<Instant>,
+}
+
+/// Rolling operational counters for one upstream, updated on every attempted
+/// call. Cheaper to read than scraping Prometheus when an operator just wants
+/// a quick health view from the admin listing.
+#[derive(Default)]
+pub struct UpstreamStats {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    /// Summed call latency in microseconds, for the running average.
+    latency_micros: AtomicU64,
+    /// Exponential moving average of call latency in microseconds, plus when
+    /// it was last updated (for idle decay).
+    ema: StdMutex<Option<(f64, Instant)>>,
+    last_error: StdMutex<Option<(String, Instant)>>,
+}
+
+/// Weight of one new sample in the latency EMA.
+const EMA_ALPHA: f64 = 0.2;
+/// Idle time after which a stale EMA has halved, so an upstream that had a
+/// bad spell becomes eligible again without needing traffic to prove itself.
+const EMA_HALF_LIFE: Duration = Duration::from_secs(60);
+
+/// Halve `value` once per [`EMA_HALF_LIFE`] of idle time.
+fn decay(value: f64, idle: Duration) -> f64 {
+    value * 0.5f64.powf(idle.as_secs_f64() / EMA_HALF_LIFE.as_secs_f64())
+}
+
+impl UpstreamStats {
+    fn record(&self, elapsed: Duration, error: Option<&UpstreamError>) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.latency_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        let sample = elapsed.as_micros() as f64;
+        let mut ema = self.ema.lock().expect("stats lock");
+        *ema = Some(match *ema {
+            None => (sample, Instant::now()),
+            Some((value, at)) => {
+                let value = decay(value, at.elapsed());
+                (value + EMA_ALPHA * (sample - value), Instant::now())
+            }
+        });
+        drop(ema);
+        if let Some(err) = error {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            *self.last_error.lock().expect("stats lock") =
+                Some((err.to_string(), Instant::now()));
+        }
+    }
+
+    /// Decayed latency EMA in milliseconds; zero until the first call.
+    pub fn ema_latency_ms(&self) -> f64 {
+        self.ema
+            .lock()
+            .expect("stats lock")
+            .map(|(value, at)| decay(value, at.elapsed()) / 1000.0)
+            .unwrap_or(0.0)
+    }
+
+    /// JSON summary for the admin listing.
+    pub fn summary(&self) -> Value {
+        let calls = self.calls.load(Ordering::Relaxed);
+        let avg_latency_ms = if calls == 0 {
+            0.0
+        } else {
+            self.latency_micros.load(Ordering::Relaxed) as f64 / calls as f64 / 1000.0
+        };
+        let last_error = self.last_error.lock().expect("stats lock").clone();
+        json!({
+            "calls": calls,
+            "errors": self.errors.load(Ordering::Relaxed),
+            "avg_latency_ms": avg_latency_ms,
+            "ema_latency_ms": self.ema_latency_ms(),
+            "last_error": last_error.as_ref().map(|(message, _)| message.clone()),
+            "last_error_secs_ago": last_error.as_ref().map(|(_, at)| at.elapsed().as_secs()),
+        })
+    }
+}
+
+/// A registered upstream plus the per-upstream policy around it (timeout and
+/// circuit breaker).
+pub struct UpstreamHandle {
+    pub name: String,
+    pub kind: &'static str,
+    upstream: Arc<dyn Upstream>,
+    pub breaker: Breaker,
+    timeout: Duration,
+    /// This upstream's latency histogram, installed by the router once its
+    /// metrics registry exists.
+    latency: StdMutex<Option<prometheus::Histogram>>,
+    health: StdMutex<HealthStatus>,
+    /// Which of this upstream's tools, prompts and resources the router
+    /// exposes.
+    pub filters: UpstreamFilters,
+    /// Quota cost multiplier per tool; tools absent from the map cost 1.0.
+    pub cost_multipliers: HashMap<String, f64>,
+    /// Declarative params/result rewrites applied around every call.
+    pub transforms: UpstreamTransforms,
+    /// Rolling call/error/latency counters for the admin listing.
+    pub stats: UpstreamStats,
+    /// `serverInfo`/`capabilities` this upstream reported in its most recent
+    /// successful `initialize`, for the router's own initialize result.
+    init_info: StdMutex<Option<Value>>,
+    /// Id source for forwarded requests; see [`Self::call`].
+    next_id: AtomicI64,
+}
+
+impl UpstreamHandle {
+    pub async fn call(&self, mut request: Request) -> Result<Response, UpstreamError> {
+        self.breaker.check()?;
+        let is_initialize = request.method == "initialize";
+        transform::apply(&self.transforms.request, &mut request.params);
+        // Each forwarded request gets its own increasing id: several client
+        // requests can be in flight against one upstream at a time, and with
+        // a shared id (every router-minted request used to be id 0) a strict
+        // upstream sees duplicates. The client's id is restored on the way
+        // back, so the rewrite never leaks out of this handle.
+        let client_id = std::mem::replace(
+            &mut request.id,
+            Id::Int(self.next_id.fetch_add(1, Ordering::Relaxed)),
+        );
+        let timer = self
+            .latency
+            .lock()
+            .expect("latency lock")
+            .as_ref()
+            .map(|h| h.start_timer());
+        let started = Instant::now();
+        let mut outcome = tokio::time::timeout(self.timeout, self.upstream.call(request))
+            .await
+            .unwrap_or(Err(UpstreamError::Timeout(self.timeout)));
+        if let Some(timer) = timer {
+            timer.observe_duration();
+        }
+        self.stats.record(started.elapsed(), outcome.as_ref().err());
+        match &mut outcome {
+            Ok(response) => {
+                response.id = client_id;
+                self.breaker.on_success();
+                if is_initialize {
+                    if let Some(result) = &response.result {
+                        *self.init_info.lock().expect("init info lock") = Some(json!({
+                            "serverInfo": result.get("serverInfo").cloned().unwrap_or(Value::Null),
+                            "capabilities":
+                                result.get("capabilities").cloned().unwrap_or(Value::Null),
+                        }));
+                    }
+                }
+                if let Some(result) = response.result.as_mut() {
+                    transform::apply(&self.transforms.response, result);
+                }
+            }
+            Err(_) => self.breaker.on_failure(),
+        }
+        outcome
+    }
+
+    pub fn describe(&self) -> Value {
+        self.upstream.describe()
+    }
+
+    /// Quota weight for one of this upstream's tools (1.0 when unconfigured).
+    pub fn cost_multiplier(&self, tool: &str) -> f64 {
+        self.cost_multipliers.get(tool).copied().unwrap_or(1.0)
+    }
+
+    /// The `serverInfo`/`capabilities` this upstream reported during its own
+    /// `initialize`, if it has completed one.
+    pub fn initialize_info(&self) -> Option<Value> {
+        self.init_info.lock().expect("init info lock").clone()
+    }
+
+    /// Latest liveness probe outcome for this upstream.
+    pub fn health(&self) -> HealthStatus {
+        *self.health.lock().expect("health lock")
+    }
+
+    fn record_health(&self, healthy: bool) {
+        *self.health.lock().expect("health lock") = HealthStatus {
+            healthy: Some(healthy),
+            checked_at: Some(Instant::now()),
+        };
+    }
+}
+
+/// Owns every mounted upstream, keyed by name.
+pub struct UpstreamRegistry {
+    inner: RwLock<BTreeMap<String, Arc<UpstreamHandle>>>,
+    timeout: Duration,
+    protocol_version: String,
+    /// The configured namespace separator; upstream names containing it are
+    /// rejected at registration.
+    separator: String,
+    /// Most upstreams `register_config` accepts; zero means unlimited. Only
+    /// the config/admin path is bounded — programmatic registration of
+    /// in-process upstreams spawns nothing worth rationing.
+    max_upstreams: usize,
+    /// `User-Agent` for HTTP upstreams that do not override it.
+    user_agent: String,
+    /// `clientInfo` carried in router-minted `initialize` requests.
+    client_info: Value,
+    /// Filesystem roots carried in router-minted `initialize` requests.
+    roots: Vec<Value>,
+    notifications: RwLock<Option<NotificationHandler>>,
+    latency: RwLock<Option<prometheus::HistogramVec>>,
+}
+
+/// Whether a plugin-discovery candidate is a file the router could spawn.
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Windows has no execute bit; any regular file qualifies.
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    std::fs::metadata(path).map(|meta| meta.is_file()).unwrap_or(false)
+}
+
+impl UpstreamRegistry {
+    pub fn new(timeout: Duration) -> Self {
+        UpstreamRegistry {
+            inner: RwLock::new(BTreeMap::new()),
+            timeout,
+            protocol_version: PROTOCOL_VERSION.into(),
+            separator: "/".into(),
+            max_upstreams: 0,
+            user_agent: format!("mcp-router/{}", env!("CARGO_PKG_VERSION")),
+            client_info: json!({
+                "name": "mcp-router",
+                "version": env!("CARGO_PKG_VERSION"),
+            }),
+            roots: Vec::new(),
+            notifications: RwLock::new(None),
+            latency: RwLock::new(None),
+        }
+    }
+
+    /// Protocol version offered to upstreams that do not override it.
+    pub fn with_protocol_version(mut self, protocol_version: impl Into<String>) -> Self {
+        self.protocol_version = protocol_version.into();
+        self
+    }
+
+    /// The separator used in namespaced names, so registration can reject
+    /// upstream names that contain it.
+    pub fn with_namespace_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Cap how many upstreams `register_config` accepts; zero (the default)
+    /// means unlimited.
+    pub fn with_max_upstreams(mut self, max_upstreams: usize) -> Self {
+        self.max_upstreams = max_upstreams;
+        self
+    }
+
+    /// `User-Agent` sent by HTTP upstreams, unless one overrides it.
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// `clientInfo` the registry presents in its own `initialize` handshakes.
+    pub fn with_client_info(mut self, client_info: Value) -> Self {
+        self.client_info = client_info;
+        self
+    }
+
+    /// Filesystem roots the registry declares in its own `initialize`
+    /// handshakes, so upstreams like an fs server can scope themselves.
+    pub fn with_roots(mut self, roots: Vec<Value>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Params for a router-minted `initialize`, carrying the configured
+    /// `clientInfo` so upstream operators can tell deployments apart, and
+    /// the configured roots when there are any.
+    pub fn initialize_params(&self) -> Value {
+        let mut params = json!({
+            "protocolVersion": self.protocol_version,
+            "clientInfo": self.client_info,
+        });
+        if !self.roots.is_empty() {
+            params["roots"] = Value::Array(self.roots.clone());
+        }
+        params
+    }
+
+    /// Install the per-upstream latency histogram on every registered
+    /// upstream, current and future.
+    pub fn set_latency_histogram(&self, histogram: prometheus::HistogramVec) {
+        for handle in self.handles() {
+            *handle.latency.lock().expect("latency lock") =
+                Some(histogram.with_label_values(&[&handle.name]));
+        }
+        *self.latency.write().expect("registry lock") = Some(histogram);
+    }
+
+    /// Install the sink for upstream-initiated notifications on every
+    /// registered upstream, current and future.
+    pub fn set_notification_handler(&self, handler: NotificationHandler) {
+        for handle in self.handles() {
+            handle.upstream.set_notification_handler(handler.clone());
+        }
+        *self.notifications.write().expect("registry lock") = Some(handler);
+    }
+
+    /// Build a registry from the configured upstream list, taking the
+    /// protocol version, namespacing, limits and identification from the
+    /// server section.
+    pub fn from_config(
+        configs: &[UpstreamConfig],
+        timeout: Duration,
+        server: &ServerConfig,
+    ) -> Result<Self, UpstreamError> {
+        let registry = UpstreamRegistry::new(timeout)
+            .with_protocol_version(&server.protocol_version)
+            .with_namespace_separator(server.separator())
+            .with_max_upstreams(server.max_upstreams)
+            .with_user_agent(server.user_agent())
+            .with_client_info(server.client_info())
+            .with_roots(server.roots_json());
+        for cfg in configs {
+            registry.register_config(cfg)?;
+        }
+        Ok(registry)
+    }
+
+    /// Scan `dir` for executables named `mcp-*` and register each one that
+    /// answers an `initialize`/`tools/list` probe as a stdio upstream, named
+    /// after the binary minus the prefix (`mcp-fs` mounts as `fs`). Binaries
+    /// that fail the probe are skipped with a warning, as are names already
+    /// taken, so one broken plugin never shadows a configured upstream or
+    /// stops the rest. Returns how many were registered.
+    pub async fn discover_plugins(&self, dir: &Path) -> usize {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!(dir = %dir.display(), %err, "plugins dir not readable, skipping discovery");
+                return 0;
+            }
+        };
+        // Sorted so registration order (and its logs) are deterministic.
+        let mut paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+        paths.sort();
+        let mut registered = 0;
+        for path in paths {
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let Some(name) = file_name.strip_prefix("mcp-") else {
+                continue;
+            };
+            if name.is_empty() || !is_executable(&path) {
+                continue;
+            }
+            if self.get(name).is_some() {
+                tracing::warn!(plugin = %file_name, upstream = %name, "name already registered, skipping plugin");
+                continue;
+            }
+            let config = UpstreamConfig {
+                name: name.into(),
+                protocol_version: None,
+                allow_tools: Vec::new(),
+                deny_tools: Vec::new(),
+                allow_prompts: Vec::new(),
+                deny_prompts: Vec::new(),
+                allow_resources: Vec::new(),
+                deny_resources: Vec::new(),
+                cost_multipliers: HashMap::new(),
+                request_transforms: Vec::new(),
+                response_transforms: Vec::new(),
+                transport: TransportConfig::Stdio {
+                    command: path.to_string_lossy().into_owned(),
+                    args: Vec::new(),
+                    env: HashMap::new(),
+                    max_line_bytes: None,
+                    framing: StdioFraming::Ndjson,
+                    idle_timeout_ms: None,
+                },
+            };
+            if let Err(err) = self.register_config(&config) {
+                tracing::warn!(plugin = %file_name, %err, "plugin registration refused, skipping");
+                continue;
+            }
+            match self.probe_plugin(name).await {
+                Ok(tools) => {
+                    tracing::info!(plugin = %file_name, upstream = %name, tools, "registered discovered plugin");
+                    registered += 1;
+                }
+                Err(err) => {
+                    tracing::warn!(plugin = %file_name, %err, "plugin failed the handshake, skipping");
+                    self.remove(name);
+                }
+            }
+        }
+        registered
+    }
+
+    /// The discovery probe: `initialize` then `tools/list`, returning the
+    /// tool count so the registration log says what the plugin brought.
+    async fn probe_plugin(&self, name: &str) -> Result<usize, UpstreamError> {
+        let response = self
+            .call(name, Request::new("initialize", self.initialize_params()))
+            .await?;
+        if let Some(err) = response.error {
+            return Err(UpstreamError::Protocol(format!(
+                "initialize failed: {}",
+                err.message
+            )));
+        }
+        let response = self.call(name, Request::new("tools/list", json!({}))).await?;
+        if let Some(err) = response.error {
+            return Err(UpstreamError::Protocol(format!(
+                "tools/list failed: {}",
+                err.message
+            )));
+        }
+        Ok(response
+            .result
+            .as_ref()
+            .and_then(|result| result.get("tools"))
+            .and_then(Value::as_array)
+            .map_or(0, Vec::len))
+    }
+
+    /// Register an upstream described by config, replacing any same-named one.
+    ///
+    /// Names containing the namespace separator are rejected:
+    /// `split_namespace` treats its first occurrence in `server/tool` as the
+    /// boundary, so a separator in the server name would make every
+    /// namespaced reference ambiguous.
+    pub fn register_config(&self, cfg: &UpstreamConfig) -> Result<(), UpstreamError> {
+        if cfg.name.contains(&self.separator) {
+            return Err(UpstreamError::InvalidName(cfg.name.clone()));
+        }
+        if self.max_upstreams > 0 {
+            let inner = self.inner.read().expect("registry lock");
+            // Replacing a same-named upstream reuses its slot.
+            if !inner.contains_key(&cfg.name) && inner.len() >= self.max_upstreams {
+                return Err(UpstreamError::LimitReached(self.max_upstreams));
+            }
+        }
+        let protocol_version = cfg
+            .protocol_version
+            .as_deref()
+            .unwrap_or(&self.protocol_version);
+        let upstream: Arc<dyn Upstream> = match &cfg.transport {
+            TransportConfig::Stdio {
+                command,
+                args,
+                env,
+                max_line_bytes,
+                framing,
+                idle_timeout_ms,
+            } => {
+                // The command is exec'd directly, so whitespace never splits:
+                // a config that relies on it wanted `args` and would spawn a
+                // nonexistent binary (or worse, a lookalike) instead.
+                if command.is_empty() {
+                    return Err(UpstreamError::Protocol(format!(
+                        "{}: stdio command is empty",
+                        cfg.name
+                    )));
+                }
+                if command.chars().any(char::is_whitespace) {
+                    return Err(UpstreamError::Protocol(format!(
+                        "{}: stdio command {command:?} contains whitespace; pass arguments in `args`",
+                        cfg.name
+                    )));
+                }
+                let stdio = Arc::new(
+                    StdioUpstream::new(&cfg.name, command, args.clone())
+                        .with_env(env.clone())
+                        .with_max_line_bytes(max_line_bytes.unwrap_or(DEFAULT_MAX_LINE_BYTES))
+                        .with_framing(*framing)
+                        .with_idle_timeout(idle_timeout_ms.map(Duration::from_millis))
+                        .with_protocol_version(protocol_version)
+                        .with_client_info(self.client_info.clone())
+                        .with_roots(self.roots.clone()),
+                );
+                stdio.spawn_idle_reaper();
+                stdio
+            }
+            TransportConfig::Http {
+                url,
+                urls,
+                bearer,
+                token_command,
+                user_agent,
+                headers,
+            } => {
+                if url.is_empty() && urls.is_empty() {
+                    return Err(UpstreamError::Protocol(format!(
+                        "{}: neither url nor urls configured",
+                        cfg.name
+                    )));
+                }
+                let user_agent = user_agent.as_deref().unwrap_or(&self.user_agent);
+                Arc::new(
+                    HttpUpstream::new(
+                        &cfg.name,
+                        url,
+                        bearer.clone(),
+                        headers,
+                        protocol_version,
+                        user_agent,
+                    )?
+                    .with_replicas(urls.clone())
+                    .with_token_command(token_command.clone()),
+                )
+            }
+        };
+        self.register_filtered(
+            &cfg.name,
+            upstream,
+            UpstreamFilters {
+                tools: NameFilter::new(cfg.allow_tools.clone(), cfg.deny_tools.clone()),
+                prompts: NameFilter::new(cfg.allow_prompts.clone(), cfg.deny_prompts.clone()),
+                resources: NameFilter::new(
+                    cfg.allow_resources.clone(),
+                    cfg.deny_resources.clone(),
+                ),
+            },
+            cfg.cost_multipliers.clone(),
+            UpstreamTransforms {
+                request: cfg.request_transforms.clone(),
+                response: cfg.response_transforms.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn register(&self, name: &str, upstream: Arc<dyn Upstream>) {
+        self.register_filtered(
+            name,
+            upstream,
+            UpstreamFilters::default(),
+            HashMap::new(),
+            UpstreamTransforms::default(),
+        );
+    }
+
+    /// Register an upstream with explicit catalog filters, tool cost
+    /// multipliers and transforms.
+    pub fn register_filtered(
+        &self,
+        name: &str,
+        upstream: Arc<dyn Upstream>,
+        filters: UpstreamFilters,
+        cost_multipliers: HashMap<String, f64>,
+        transforms: UpstreamTransforms,
+    ) {
+        if let Some(handler) = self.notifications.read().expect("registry lock").clone() {
+            upstream.set_notification_handler(handler);
+        }
+        let latency = self
+            .latency
+            .read()
+            .expect("registry lock")
+            .as_ref()
+            .map(|histogram| histogram.with_label_values(&[name]));
+        let handle = Arc::new(UpstreamHandle {
+            name: name.to_string(),
+            kind: upstream.kind(),
+            upstream,
+            breaker: Breaker::default(),
+            timeout: self.timeout,
+            latency: StdMutex::new(latency),
+            health: StdMutex::new(HealthStatus::default()),
+            filters,
+            cost_multipliers,
+            transforms,
+            stats: UpstreamStats::default(),
+            init_info: StdMutex::new(None),
+            next_id: AtomicI64::new(1),
+        });
+        self.inner
+            .write()
+            .expect("registry lock")
+            .insert(name.to_string(), handle);
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.inner
+            .write()
+            .expect("registry lock")
+            .remove(name)
+            .is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<UpstreamHandle>> {
+        self.inner.read().expect("registry lock").get(name).cloned()
+    }
+
+    /// Pick which of several equivalent upstreams should serve the next call:
+    /// the one with the lowest latency EMA whose circuit breaker is closed.
+    /// A never-called upstream has an EMA of zero and so wins immediately,
+    /// which is what gets a fresh replacement into rotation; the idle decay
+    /// in [`UpstreamStats`] does the same for a recovered one.
+    pub fn route(&self, candidates: &[&str]) -> Option<Arc<UpstreamHandle>> {
+        candidates
+            .iter()
+            .filter_map(|name| self.get(name))
+            .filter(|handle| !handle.breaker.is_open())
+            .min_by(|a, b| {
+                a.stats
+                    .ema_latency_ms()
+                    .total_cmp(&b.stats.ema_latency_ms())
+            })
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.inner
+            .read()
+            .expect("registry lock")
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    pub fn handles(&self) -> Vec<Arc<UpstreamHandle>> {
+        self.inner
+            .read()
+            .expect("registry lock")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Shut down every upstream transport. Returns how many stopped cleanly
+    /// and how many had to be force-killed.
+    pub async fn shutdown_all(&self) -> (usize, usize) {
+        let mut clean = 0;
+        let mut killed = 0;
+        for handle in self.handles() {
+            if handle.upstream.shutdown().await {
+                clean += 1;
+            } else {
+                killed += 1;
+            }
+        }
+        (clean, killed)
+    }
+
+    /// Call a named upstream, applying its timeout and breaker.
+    pub async fn call(&self, name: &str, request: Request) -> Result<Response, UpstreamError> {
+        let handle = self
+            .get(name)
+            .ok_or_else(|| UpstreamError::Unknown(name.to_string()))?;
+        handle.call(request).await
+    }
+
+    /// Probe every upstream once and record the outcome on its handle. HTTP
+    /// upstreams get a lightweight `ping` first, falling back to `initialize`
+    /// for servers that answer `-32601`; stdio upstreams go straight to
+    /// `initialize`, since the probe goes through [`UpstreamHandle::call`]
+    /// and so respawns a dead child eagerly instead of waiting for the next
+    /// real request. Upstreams with an open breaker are skipped so the
+    /// checker does not eat the half-open probe slot or pile on failures.
+    pub async fn check_health(&self) {
+        for handle in self.handles() {
+            if handle.breaker.is_open() {
+                continue;
+            }
+            let probe = if handle.kind == "http" {
+                match handle.call(Request::new("ping", json!({}))).await {
+                    Ok(response) if response.error.is_none() => Ok(response),
+                    _ => {
+                        handle
+                            .call(Request::new("initialize", self.initialize_params()))
+                            .await
+                    }
+                }
+            } else {
+                handle
+                    .call(Request::new("initialize", self.initialize_params()))
+                    .await
+            };
+            let healthy = probe.is_ok();
+            handle.record_health(healthy);
+            if !healthy {
+                tracing::warn!(upstream = %handle.name, "health check failed");
+            }
+        }
+    }
+
+    /// Run [`Self::check_health`] every `interval` until the task is aborted.
+    pub fn spawn_health_checker(
+        self: &Arc<Self>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let registry = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                registry.check_health().await;
+            }
+        })
+    }
+
+    /// Register an in-memory [`FnUpstream`] under `name`, subject to the same
+    /// name validation as configured upstreams. The closure handles every
+    /// JSON-RPC method forwarded to the upstream, so it should at least
+    /// answer `tools/list` (to appear in the catalog) and `tools/call`.
+    pub fn register_dyn<F, Fut>(&self, name: &str, handler: F) -> Result<(), UpstreamError>
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Response> + Send + 'static,
+    {
+        if name.contains(&self.separator) {
+            return Err(UpstreamError::InvalidName(name.to_string()));
+        }
+        self.register(name, Arc::new(FnUpstream::new(handler)));
+        Ok(())
+    }
+
+    /// Register a closure-backed upstream, for unit tests.
+    #[cfg(test)]
+    pub fn register_test<F>(&self, name: &str, handler: F)
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static,
+    {
+        struct TestUpstream<F>(F);
+
+        #[async_trait]
+        impl<F> Upstream for TestUpstream<F>
+        where
+            F: Fn(Request) -> Response + Send + Sync,
+        {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+                Ok((self.0)(request))
+            }
+        }
+
+        self.register(name, Arc::new(TestUpstream(handler)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn registry_routes_to_named_upstream() {
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        registry.register_test("echo", |req| {
+            Response::success(req.id, json!({"method": req.method}))
+        });
+        let resp = registry
+            .call("echo", Request::new("tools/list", json!({})))
+            .await
+            .unwrap();
+        assert_eq!(resp.result.unwrap()["method"], "tools/list");
+    }
+
+    #[tokio::test]
+    async fn concurrent_forwarded_calls_get_distinct_ids() {
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        let seen: Arc<StdMutex<Vec<Id>>> = Arc::default();
+        let record = seen.clone();
+        registry.register_test("ids", move |req| {
+            record.lock().unwrap().push(req.id.clone());
+            Response::success(req.id, json!({}))
+        });
+        let mut first = Request::new("tools/list", json!({}));
+        first.id = Id::Int(7);
+        let mut second = Request::new("tools/list", json!({}));
+        second.id = Id::Str("beta".into());
+        let (first, second) = tokio::join!(
+            registry.call("ids", first),
+            registry.call("ids", second),
+        );
+        // Clients keep the ids they sent...
+        assert_eq!(first.unwrap().id, Id::Int(7));
+        assert_eq!(second.unwrap().id, Id::Str("beta".into()));
+        // ...while the upstream saw two router-minted, distinct ids.
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_ne!(seen[0], seen[1]);
+        assert!(!seen.contains(&Id::Int(7)), "{seen:?}");
+    }
+
+    #[tokio::test]
+    async fn unknown_upstream_is_an_error() {
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        let err = registry
+            .call("nope", Request::new("tools/list", json!({})))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UpstreamError::Unknown(name) if name == "nope"));
+    }
+
+    #[test]
+    fn names_with_slashes_are_rejected() {
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        let mut cfg = crate::config::Config::example().upstreams.remove(0);
+        cfg.name = "my/server".into();
+        let err = registry.register_config(&cfg).unwrap_err();
+        assert!(matches!(err, UpstreamError::InvalidName(name) if name == "my/server"));
+
+        // The same config under a slash-free name registers fine.
+        cfg.name = "my-server".into();
+        registry.register_config(&cfg).unwrap();
+        assert!(registry.get("my-server").is_some());
+    }
+
+    #[test]
+    fn registration_past_the_limit_is_rejected() {
+        let registry = UpstreamRegistry::new(Duration::from_secs(1)).with_max_upstreams(1);
+        let configs = crate::config::Config::example().upstreams;
+        registry.register_config(&configs[0]).unwrap();
+        let err = registry.register_config(&configs[1]).unwrap_err();
+        assert!(matches!(err, UpstreamError::LimitReached(1)), "{err}");
+
+        // Replacing the existing upstream does not need a free slot...
+        registry.register_config(&configs[0]).unwrap();
+        // ...and removing it makes room for a different one.
+        assert!(registry.remove(&configs[0].name));
+        registry.register_config(&configs[1]).unwrap();
+    }
+
+    #[test]
+    fn suspicious_stdio_commands_fail_registration() {
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        let mut cfg = crate::config::Config::example().upstreams.remove(0);
+        for command in ["", "sh script.sh"] {
+            if let crate::config::TransportConfig::Stdio { command: c, .. } = &mut cfg.transport {
+                *c = command.into();
+            }
+            let err = registry.register_config(&cfg).unwrap_err();
+            assert!(matches!(err, UpstreamError::Protocol(_)), "{err}");
+        }
+        assert!(registry.get(&cfg.name).is_none());
+    }
+
+    #[test]
+    fn reset_durations_parse_compact_forms() {
+        assert_eq!(parse_retry_after("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_reset_duration("120ms"), Some(Duration::from_millis(120)));
+        assert_eq!(
+            parse_reset_duration("7.5s"),
+            Some(Duration::from_millis(7500))
+        );
+        assert_eq!(
+            parse_reset_duration("6m30s"),
+            Some(Duration::from_secs(390))
+        );
+        assert_eq!(parse_reset_duration("0s"), None);
+        assert_eq!(parse_reset_duration("garbage"), None);
+    }
+
+    #[test]
+    fn breaker_opens_after_threshold_and_recovers() {
+        let breaker = Breaker::new(2, Duration::from_millis(20));
+        assert!(breaker.check().is_ok());
+        breaker.on_failure();
+        assert!(breaker.check().is_ok());
+        breaker.on_failure();
+        assert!(matches!(breaker.check(), Err(UpstreamError::CircuitOpen(_))));
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.check().is_ok());
+    }
+
+    #[tokio::test]
+    async fn health_check_records_probe_outcome() {
+        struct Broken;
+
+        #[async_trait]
+        impl Upstream for Broken {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, _request: Request) -> Result<Response, UpstreamError> {
+                Err(UpstreamError::Protocol("boom".into()))
+            }
+        }
+
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        registry.register_test("good", |req| Response::success(req.id, json!({})));
+        registry.register("bad", Arc::new(Broken));
+        assert!(registry.get("good").unwrap().health().healthy.is_none());
+
+        registry.check_health().await;
+        assert_eq!(registry.get("good").unwrap().health().healthy, Some(true));
+        assert_eq!(registry.get("bad").unwrap().health().healthy, Some(false));
+        assert!(registry.get("bad").unwrap().health().checked_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn handle_times_out_slow_upstreams() {
+        struct Slow;
+
+        #[async_trait]
+        impl Upstream for Slow {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(Response::success(request.id, json!({})))
+            }
+        }
+
+        let registry = UpstreamRegistry::new(Duration::from_millis(20));
+        registry.register("slow", Arc::new(Slow));
+        let err = registry
+            .call("slow", Request::new("ping", json!({})))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UpstreamError::Timeout(_)));
+    }
+
+    #[tokio::test]
+    async fn transforms_rewrite_params_and_results() {
+        use crate::transform::TransformRule;
+
+        // Echoes the params it received back as the result.
+        struct EchoParams;
+
+        #[async_trait]
+        impl Upstream for EchoParams {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+                Ok(Response::success(request.id, request.params))
+            }
+        }
+
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        registry.register_filtered(
+            "shaped",
+            Arc::new(EchoParams),
+            UpstreamFilters::default(),
+            HashMap::new(),
+            UpstreamTransforms {
+                request: vec![TransformRule::Set {
+                    path: "/arguments/model".into(),
+                    value: json!("small-1"),
+                }],
+                response: vec![TransformRule::Remove {
+                    path: "/arguments/internal".into(),
+                }],
+            },
+        );
+
+        let response = registry
+            .call(
+                "shaped",
+                Request::new(
+                    "tools/call",
+                    json!({"name": "gen", "arguments": {"internal": true}}),
+                ),
+            )
+            .await
+            .unwrap();
+        // The request transform injected the default model before the
+        // forward; the response transform stripped the internal field after.
+        assert_eq!(
+            response.result.unwrap(),
+            json!({"name": "gen", "arguments": {"model": "small-1"}})
+        );
+    }
+
+    #[test]
+    fn ema_halves_per_idle_half_life() {
+        assert!((decay(100.0, EMA_HALF_LIFE) - 50.0).abs() < 0.001);
+        assert!((decay(100.0, 2 * EMA_HALF_LIFE) - 25.0).abs() < 0.001);
+        assert_eq!(decay(100.0, Duration::ZERO), 100.0);
+    }
+
+    #[tokio::test]
+    async fn route_prefers_the_lower_latency_upstream() {
+        struct Delayed(Duration);
+
+        #[async_trait]
+        impl Upstream for Delayed {
+            fn kind(&self) -> &'static str {
+                "test"
+            }
+
+            async fn call(&self, request: Request) -> Result<Response, UpstreamError> {
+                tokio::time::sleep(self.0).await;
+                Ok(Response::success(request.id, json!({})))
+            }
+        }
+
+        let registry = UpstreamRegistry::new(Duration::from_secs(1));
+        registry.register("fast", Arc::new(Delayed(Duration::from_millis(2))));
+        registry.register("slow", Arc::new(Delayed(Duration::from_millis(40))));
+        for _ in 0..3 {
+            for name in ["fast", "slow"] {
+                registry
+                    .call(name, Request::new("ping", json!({})))
+                    .await
+                    .unwrap();
+            }
+        }
+        let picked = registry.route(&["slow", "fast"]).unwrap();
+        assert_eq!(picked.name, "fast");
+
+        // A tripped breaker takes the fast upstream out of the running.
+        let fast = registry.get("fast").unwrap();
+        for _ in 0..10 {
+            fast.breaker.on_failure();
+        }
+        let picked = registry.route(&["slow", "fast"]).unwrap();
+        assert_eq!(picked.name, "slow");
+    }
+}
@@ -0,0 +1,667 @@
+mod common;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+const ECHO_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13","serverInfo":{"name":"echo"}}}' ;;
+    *)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"error":{"code":-32601,"message":"unknown method"}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn raw_call_reaches_named_upstream() {
+    let state = Arc::new(common::test_state().await);
+    let _dir = common::register_script(&state, "echo", ECHO_SERVER, &[]);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("http://{addr}/api/upstreams/echo/call"))
+        .json(&json!({"method": "initialize", "params": {}}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["result"]["serverInfo"]["name"], "echo");
+}
+
+#[tokio::test]
+async fn raw_call_forwards_upstream_errors_verbatim() {
+    let state = Arc::new(common::test_state().await);
+    let _dir = common::register_script(&state, "echo", ECHO_SERVER, &[]);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("http://{addr}/api/upstreams/echo/call"))
+        .json(&json!({"method": "no/such/method"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["error"]["code"], -32601);
+}
+
+#[tokio::test]
+async fn configured_providers_are_seeded_at_boot() {
+    std::env::set_var("SEED_TEST_KEY", "sk-seeded");
+    let mut config = mcp_router::Config::default();
+    config.providers = vec![mcp_router::config::ProviderSeed {
+        slug: "openai".into(),
+        kind: "openai".into(),
+        api_key_env: Some("SEED_TEST_KEY".into()),
+        metadata: Some(json!({"default_model": "gpt-4o"})),
+    }];
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // No manual POST /api/providers needed.
+    let body: Value = client
+        .get(format!("http://{addr}/api/providers"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["providers"][0]["slug"], "openai", "{body}");
+    // Metadata is stored (and listed) as serialized JSON.
+    let metadata: Value =
+        serde_json::from_str(body["providers"][0]["metadata"].as_str().unwrap()).unwrap();
+    assert_eq!(metadata["default_model"], "gpt-4o");
+
+    // Reload is idempotent.
+    let resp = client
+        .post(format!("http://{addr}/api/providers/reload"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["seeded"], 1);
+    let body: Value = client
+        .get(format!("http://{addr}/api/providers"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["providers"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn provider_metadata_is_validated() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+    let url = format!("http://{addr}/api/providers");
+
+    // Well-formed metadata is accepted.
+    let resp = client
+        .post(&url)
+        .json(&json!({
+            "slug": "openai",
+            "kind": "openai",
+            "metadata": {"rate_limit_rpm": 60, "default_model": "gpt-4o", "region": "us"},
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // A known key with the wrong type is always a 400.
+    let resp = client
+        .post(&url)
+        .json(&json!({
+            "slug": "openai",
+            "kind": "openai",
+            "metadata": {"rate_limit_rpm": "lots"},
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // Unknown keys pass by default but are rejected under strict.
+    let with_extra = |strict: bool| {
+        json!({
+            "slug": "openai",
+            "kind": "openai",
+            "metadata": {"favourite_colour": "teal"},
+            "strict": strict,
+        })
+    };
+    let resp = client.post(&url).json(&with_extra(false)).send().await.unwrap();
+    assert_eq!(resp.status(), 201);
+    let resp = client.post(&url).json(&with_extra(true)).send().await.unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: Value = resp.json().await.unwrap();
+    assert!(
+        body["error"].as_str().unwrap().contains("favourite_colour"),
+        "{body}"
+    );
+}
+
+#[tokio::test]
+async fn raw_call_to_unknown_upstream_is_404() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("http://{addr}/api/upstreams/nope/call"))
+        .json(&json!({"method": "initialize"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+async fn bulk_subscriptions_apply_per_user_with_a_report() {
+    let state = Arc::new(common::test_state().await);
+    let store = state.store.as_ref().unwrap();
+    for user in ["ann", "bob", "cleo"] {
+        store.create_user(user, "").await.unwrap();
+    }
+    let addr = common::spawn_app(state.clone()).await;
+
+    let body: Value = reqwest::Client::new()
+        .post(format!("http://{addr}/api/subscriptions/bulk"))
+        .json(&json!({
+            "user_ids": ["ann", "bob", "cleo", "nobody"],
+            "tier": "pro",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 4, "{body}");
+    for result in &results[..3] {
+        assert_eq!(result["status"], "ok", "{result}");
+    }
+    assert_eq!(results[3]["status"], "unknown user");
+
+    for user in ["ann", "bob", "cleo"] {
+        let sub = store.get_subscription(user).await.unwrap().unwrap();
+        assert_eq!(sub.tier, "pro");
+    }
+    assert!(store.get_subscription("nobody").await.unwrap().is_none());
+}
+
+const TWO_TOOLS_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"read"},{"name":"write"}]}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn per_upstream_tools_listing_is_raw() {
+    let state = Arc::new(common::test_state().await);
+    let _dir = common::register_script(&state, "pair", TWO_TOOLS_SERVER, &[]);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    let body: Value = client
+        .get(format!("http://{addr}/api/upstreams/pair/tools"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let names: Vec<&str> = body["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap())
+        .collect();
+    // Raw names, no `pair/` namespace prefix.
+    assert_eq!(names, ["read", "write"]);
+
+    let resp = client
+        .get(format!("http://{addr}/api/upstreams/ghost/tools"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn upstream_listing_carries_runtime_stats() {
+    use std::collections::HashMap;
+
+    use mcp_router::config::{TransportConfig, UpstreamConfig};
+
+    let state = Arc::new(common::test_state().await);
+    let _dir = common::register_script(&state, "echo", ECHO_SERVER, &[]);
+    // A replica nobody listens on: calls fail fast with a connection error.
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "dead".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: "http://127.0.0.1:9/".into(),
+                urls: Vec::new(),
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // Two successful calls against the healthy upstream, one failed call
+    // against the dead one.
+    for _ in 0..2 {
+        let resp = client
+            .post(format!("http://{addr}/api/upstreams/echo/call"))
+            .json(&json!({"method": "initialize", "params": {}}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+    }
+    let resp = client
+        .post(format!("http://{addr}/api/upstreams/dead/call"))
+        .json(&json!({"method": "initialize", "params": {}}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::BAD_GATEWAY);
+
+    let body: Value = client
+        .get(format!("http://{addr}/api/upstreams"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let stats_for = |name: &str| {
+        body["upstreams"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|u| u["name"] == name)
+            .map(|u| u["stats"].clone())
+            .unwrap()
+    };
+    let echo = stats_for("echo");
+    assert_eq!(echo["calls"], 2, "{echo}");
+    assert_eq!(echo["errors"], 0);
+    assert!(echo["last_error"].is_null());
+    assert!(echo["avg_latency_ms"].as_f64().unwrap() >= 0.0);
+    assert!(echo["ema_latency_ms"].as_f64().unwrap() > 0.0, "{echo}");
+
+    let dead = stats_for("dead");
+    assert_eq!(dead["calls"], 1, "{dead}");
+    assert_eq!(dead["errors"], 1);
+    assert!(dead["last_error"].as_str().unwrap().contains("http"), "{dead}");
+    assert!(dead["last_error_secs_ago"].is_u64());
+}
+
+const CALC_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/call"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"content":[{"type":"text","text":"2"}]}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn recent_calls_list_newest_first_with_statuses() {
+    use std::collections::HashMap;
+
+    use mcp_router::config::{TransportConfig, UpstreamConfig};
+
+    let state = Arc::new(common::test_state().await);
+    let _dir = common::register_script(&state, "calc", CALC_SERVER, &[]);
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "dead".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: "http://127.0.0.1:9/".into(),
+                urls: Vec::new(),
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    let call = |tool: &str| {
+        let client = client.clone();
+        let body = json!({
+            "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": tool, "arguments": {"a": 1}},
+        });
+        async move {
+            client
+                .post(format!("http://{addr}/mcp"))
+                .json(&body)
+                .send()
+                .await
+                .unwrap()
+                .json::<Value>()
+                .await
+                .unwrap()
+        }
+    };
+    let ok = call("calc/add").await;
+    assert!(ok.get("error").is_none(), "{ok}");
+    let failed = call("dead/x").await;
+    assert_eq!(failed["error"]["code"], -32001, "{failed}");
+
+    let body: Value = client
+        .get(format!("http://{addr}/api/calls/recent"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let calls = body["calls"].as_array().unwrap();
+    assert_eq!(calls.len(), 2, "{body}");
+    // Newest first: the failure came last.
+    assert_eq!(calls[0]["tool"], "dead/x");
+    assert_eq!(calls[0]["status"], "-32001");
+    assert_eq!(calls[1]["tool"], "calc/add");
+    assert_eq!(calls[1]["status"], "ok");
+    assert!(calls[0]["latency_ms"].is_u64());
+    // Arguments are withheld unless explicitly enabled.
+    assert!(calls[0].get("arguments").is_none());
+
+    let body: Value = client
+        .get(format!("http://{addr}/api/calls/recent?limit=1"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["calls"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn recent_calls_arguments_are_opt_in_and_masked() {
+    let mut config = mcp_router::Config::default();
+    config.server.recent_calls_arguments = true;
+    let state = Arc::new(common::test_state_with(config).await);
+    let _dir = common::register_script(&state, "calc", CALC_SERVER, &[]);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    let resp: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "calc/add", "arguments": {"path": "/etc/passwd", "count": 3}},
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(resp.get("error").is_none(), "{resp}");
+
+    let body: Value = client
+        .get(format!("http://{addr}/api/calls/recent"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    // Structure survives, string content does not.
+    assert_eq!(body["calls"][0]["arguments"]["path"], "***", "{body}");
+    assert_eq!(body["calls"][0]["arguments"]["count"], 3);
+}
+
+#[tokio::test]
+async fn provider_test_reports_good_and_bad_keys() {
+    // A mock "models" endpoint: the right key gets a 200, anything else 401.
+    let mock = axum::Router::new().route(
+        "/v1/models",
+        axum::routing::get(|headers: axum::http::HeaderMap| async move {
+            let authorized = headers
+                .get("authorization")
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v == "Bearer sk-good");
+            if authorized {
+                (reqwest::StatusCode::OK, "{}")
+            } else {
+                (reqwest::StatusCode::UNAUTHORIZED, "{}")
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mock_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, mock).await.unwrap() });
+
+    let state = Arc::new(common::test_state().await);
+    let providers = state.providers.as_ref().unwrap();
+    let metadata = json!({"endpoint": format!("http://{mock_addr}/v1/models")});
+    providers
+        .put_provider("good", "openai", Some("sk-good"), Some(&metadata))
+        .await
+        .unwrap();
+    providers
+        .put_provider("bad", "openai", Some("sk-bad"), Some(&metadata))
+        .await
+        .unwrap();
+    providers
+        .put_provider("keyless", "openai", None, None)
+        .await
+        .unwrap();
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+    let test = |slug: &str| {
+        let client = client.clone();
+        let url = format!("http://{addr}/api/providers/{slug}/test");
+        async move { client.post(url).send().await.unwrap() }
+    };
+
+    let body: Value = test("good").await.json().await.unwrap();
+    assert_eq!(body["ok"], true, "{body}");
+    assert_eq!(body["status"], 200, "{body}");
+
+    let body: Value = test("bad").await.json().await.unwrap();
+    assert_eq!(body["ok"], false, "{body}");
+    assert_eq!(body["status"], 401, "{body}");
+
+    // No stored key is a caller error, not a probe failure.
+    assert_eq!(test("keyless").await.status(), 400);
+    assert_eq!(test("missing").await.status(), 404);
+}
+
+#[tokio::test]
+async fn revoked_tokens_stop_authenticating_and_drop_from_listing() {
+    let mut config = mcp_router::Config::default();
+    config.server.bearer = Some("root-token".into());
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    let issued: Value = client
+        .post(format!("http://{addr}/api/tokens"))
+        .bearer_auth("root-token")
+        .json(&json!({"user_id": "mallory"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = issued["id"].as_str().unwrap().to_string();
+    let secret = issued["token"].as_str().unwrap().to_string();
+
+    let rpc = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"});
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .bearer_auth(&secret)
+        .json(&rpc)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = client
+        .delete(format!("http://{addr}/api/tokens/{id}"))
+        .bearer_auth("root-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 204);
+
+    // Revocation takes effect immediately on the auth path...
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .bearer_auth(&secret)
+        .json(&rpc)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+
+    // ...and the token disappears from the listing, but a second revoke of
+    // the same id is a 404 rather than a silent success.
+    let tokens: Value = client
+        .get(format!("http://{addr}/api/tokens"))
+        .bearer_auth("root-token")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(
+        !tokens["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|t| t["id"] == id.as_str()),
+        "{tokens}"
+    );
+    let resp = client
+        .delete(format!("http://{addr}/api/tokens/{id}"))
+        .bearer_auth("root-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+async fn admin_routes_are_scoped_by_the_tenant_header() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .post(format!("http://{addr}/api/users"))
+        .header("x-mcp-tenant", "acme")
+        .json(&json!({"user_id": "alice", "name": "Acme Alice"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+
+    // A query in another tenant — or in the default one — sees nothing.
+    for tenant in [Some("globex"), None] {
+        let mut req = client.get(format!("http://{addr}/api/users"));
+        if let Some(tenant) = tenant {
+            req = req.header("x-mcp-tenant", tenant);
+        }
+        let body: Value = req.send().await.unwrap().json().await.unwrap();
+        assert_eq!(body["users"].as_array().unwrap().len(), 0, "{body}");
+    }
+
+    let body: Value = client
+        .get(format!("http://{addr}/api/users"))
+        .header("x-mcp-tenant", "acme")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["users"][0]["user_id"], "alice", "{body}");
+}
+
+#[tokio::test]
+async fn config_dump_redacts_secrets_but_keeps_structure() {
+    let mut config = mcp_router::Config::default();
+    config.server.bearer = Some("root-token".into());
+    config.upstreams = mcp_router::Config::example().upstreams;
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let body: Value = reqwest::Client::new()
+        .get(format!("http://{addr}/api/config"))
+        .bearer_auth("root-token")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["server"]["bearer"], "***", "{body}");
+    let upstreams = body["upstreams"].as_array().unwrap();
+    assert_eq!(upstreams.len(), 2, "{body}");
+    assert_eq!(upstreams[0]["name"], "fs");
+    // Non-secret fields come through as configured.
+    assert_eq!(upstreams[0]["command"], "mcp-fs");
+}
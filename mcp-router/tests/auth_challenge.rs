@@ -0,0 +1,67 @@
+mod common;
+
+use std::sync::Arc;
+
+use mcp_router::config::Config;
+use reqwest::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn unauthorized_responses_carry_a_bearer_challenge() {
+    let mut config = Config::default();
+    config.server.bearer = Some("s3cret".into());
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state).await;
+    let client = reqwest::Client::new();
+    let rpc = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"});
+
+    // No credentials at all: a bare challenge telling the client how to auth.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&rpc)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let challenge = resp
+        .headers()
+        .get("www-authenticate")
+        .expect("WWW-Authenticate header")
+        .to_str()
+        .unwrap();
+    assert_eq!(challenge, r#"Bearer realm="mcp-router""#);
+
+    // A wrong token is called out as invalid_token per RFC 6750.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .bearer_auth("wrong")
+        .json(&rpc)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let challenge = resp.headers()["www-authenticate"].to_str().unwrap();
+    assert!(challenge.contains(r#"error="invalid_token""#), "{challenge}");
+
+    // A non-Bearer Authorization header is a malformed request.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .header("authorization", "Basic dXNlcjpwYXNz")
+        .json(&rpc)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let challenge = resp.headers()["www-authenticate"].to_str().unwrap();
+    assert!(challenge.contains(r#"error="invalid_request""#), "{challenge}");
+
+    // The right token still gets through.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .bearer_auth("s3cret")
+        .json(&rpc)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -0,0 +1,129 @@
+//! Shared plumbing for integration tests: an in-memory router state and an
+//! ephemeral-port server.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use mcp_router::config::{Config, StdioFraming, TransportConfig, UpstreamConfig};
+use mcp_router::router::RouterState;
+use mcp_router::server::build_app;
+use mcp_router::store::{ProviderStore, SubscriptionStore};
+use mcp_router::upstream::UpstreamRegistry;
+
+/// A router state with an in-memory database and an empty registry.
+pub async fn test_state() -> RouterState {
+    test_state_with(Config::default()).await
+}
+
+pub async fn test_state_with(config: Config) -> RouterState {
+    let store = SubscriptionStore::new("sqlite::memory:")
+        .await
+        .expect("open in-memory store")
+        .with_default_tier(match config.database.default_tier.as_str() {
+            "" => None,
+            tier => Some(tier.to_string()),
+        });
+    store.run_migrations().await.expect("run migrations");
+    let providers = ProviderStore::new(store.pool().clone());
+    let timeout = Duration::from_secs(config.server.request_timeout_secs);
+    let registry = Arc::new(
+        UpstreamRegistry::new(timeout)
+            .with_protocol_version(&config.server.protocol_version)
+            .with_namespace_separator(config.server.separator())
+            .with_max_upstreams(config.server.max_upstreams)
+            .with_user_agent(config.server.user_agent())
+            .with_client_info(config.server.client_info())
+            .with_roots(config.server.roots_json()),
+    );
+    let state = RouterState::new(config, registry, Some(store), Some(providers));
+    state
+        .seed_providers()
+        .await
+        .expect("seed configured providers");
+    state
+}
+
+/// A router state with persistence disabled: no store, no providers.
+pub async fn test_state_no_persistence() -> RouterState {
+    let mut config = Config::default();
+    config.database.persistence = false;
+    let timeout = Duration::from_secs(config.server.request_timeout_secs);
+    let registry = Arc::new(
+        UpstreamRegistry::new(timeout)
+            .with_protocol_version(&config.server.protocol_version)
+            .with_namespace_separator(config.server.separator()),
+    );
+    RouterState::new(config, registry, None, None)
+}
+
+/// Register a scripted `sh` child as a stdio upstream. The script receives
+/// `extra_args` as positional parameters; the returned tempdir (holding the
+/// script) must be kept alive for the duration of the test.
+pub fn register_script(
+    state: &RouterState,
+    name: &str,
+    script: &str,
+    extra_args: &[String],
+) -> tempfile::TempDir {
+    register_script_with(state, name, script, extra_args, |_| {})
+}
+
+/// Like [`register_script`], with a hook to tweak the generated
+/// [`UpstreamConfig`] before registration.
+pub fn register_script_with(
+    state: &RouterState,
+    name: &str,
+    script: &str,
+    extra_args: &[String],
+    tweak: impl FnOnce(&mut UpstreamConfig),
+) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().expect("create tempdir");
+    let path = dir.path().join(format!("{name}.sh"));
+    std::fs::write(&path, script).expect("write fake server script");
+    let mut args = vec![path.to_string_lossy().into_owned()];
+    args.extend(extra_args.iter().cloned());
+    let mut config = UpstreamConfig {
+        name: name.into(),
+        protocol_version: None,
+        allow_tools: Vec::new(),
+        deny_tools: Vec::new(),
+        allow_prompts: Vec::new(),
+        deny_prompts: Vec::new(),
+        allow_resources: Vec::new(),
+        deny_resources: Vec::new(),
+        cost_multipliers: HashMap::new(),
+        request_transforms: Vec::new(),
+        response_transforms: Vec::new(),
+        transport: TransportConfig::Stdio {
+            command: "sh".into(),
+            args,
+            env: Default::default(),
+            max_line_bytes: None,
+            framing: StdioFraming::Ndjson,
+            idle_timeout_ms: None,
+        },
+    };
+    tweak(&mut config);
+    state
+        .registry
+        .register_config(&config)
+        .expect("register scripted upstream");
+    dir
+}
+
+/// Serve the app on an ephemeral port and return its address.
+pub async fn spawn_app(state: Arc<RouterState>) -> SocketAddr {
+    let app = build_app(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind ephemeral port");
+    let addr = listener.local_addr().expect("local addr");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.expect("serve test app");
+    });
+    addr
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use mcp_router::config::{StdioFraming, TransportConfig, UpstreamConfig};
+use mcp_router::router::encode_resource_template;
+use serde_json::{json, Value};
+
+/// The `mcp-fs` binary built alongside this suite by `cargo test --workspace`:
+/// the test executable lives in `target/debug/deps`, the binaries one up.
+fn fs_binary() -> PathBuf {
+    let mut path = std::env::current_exe().expect("test executable path");
+    path.pop();
+    path.pop();
+    path.push("mcp-fs");
+    path
+}
+
+#[tokio::test]
+async fn completion_routes_to_the_fs_upstream() {
+    let bin = fs_binary();
+    assert!(
+        bin.exists(),
+        "mcp-fs binary not found at {} (run via `cargo test --workspace`)",
+        bin.display()
+    );
+    let root = tempfile::tempdir().expect("create root");
+    std::fs::write(root.path().join("alpha.txt"), "a").unwrap();
+    std::fs::write(root.path().join("apple.txt"), "a").unwrap();
+    std::fs::write(root.path().join("beta.txt"), "b").unwrap();
+    std::fs::create_dir(root.path().join("archive")).unwrap();
+
+    let state = Arc::new(common::test_state().await);
+    let config = UpstreamConfig {
+        name: "docs".into(),
+        protocol_version: None,
+        allow_tools: Vec::new(),
+        deny_tools: Vec::new(),
+        allow_prompts: Vec::new(),
+        deny_prompts: Vec::new(),
+        allow_resources: Vec::new(),
+        deny_resources: Vec::new(),
+        cost_multipliers: HashMap::new(),
+        request_transforms: Vec::new(),
+        response_transforms: Vec::new(),
+        transport: TransportConfig::Stdio {
+            command: bin.to_string_lossy().into_owned(),
+            args: vec!["--root".into(), root.path().to_string_lossy().into_owned()],
+            env: Default::default(),
+            max_line_bytes: None,
+            framing: StdioFraming::Ndjson,
+            idle_timeout_ms: None,
+        },
+    };
+    state.registry.register_config(&config).expect("register mcp-fs");
+    let addr = common::spawn_app(state.clone()).await;
+
+    let template = encode_resource_template(
+        "docs",
+        &format!("file://{}/{{path}}", root.path().display()),
+    );
+    let resp: Value = reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": "completion/complete",
+            "params": {
+                "ref": {"type": "ref/resource", "uri": template},
+                "argument": {"name": "path", "value": "a"},
+            },
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let values: Vec<&str> = resp["result"]["completion"]["values"]
+        .as_array()
+        .unwrap_or_else(|| panic!("no completion values: {resp}"))
+        .iter()
+        .map(|v| v.as_str().unwrap())
+        .collect();
+    assert_eq!(values, ["alpha.txt", "apple.txt", "archive/"]);
+    assert_eq!(resp["result"]["completion"]["hasMore"], false);
+}
+
+#[tokio::test]
+async fn unnamespaced_refs_are_rejected() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // A prompt ref without a `server/` prefix can't be routed anywhere.
+    let resp: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": "completion/complete",
+            "params": {
+                "ref": {"type": "ref/prompt", "name": "summarize"},
+                "argument": {"name": "topic", "value": "ru"},
+            },
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(resp["error"]["code"], -32602, "{resp}");
+
+    // Same for a resource ref outside the router's uri scheme.
+    let resp: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 2, "method": "completion/complete",
+            "params": {
+                "ref": {"type": "ref/resource", "uri": "file:///tmp/{path}"},
+                "argument": {"name": "path", "value": "a"},
+            },
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(resp["error"]["code"], -32602, "{resp}");
+}
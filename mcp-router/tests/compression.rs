@@ -0,0 +1,70 @@
+mod common;
+
+use std::io::Read;
+use std::sync::Arc;
+
+use mcp_router::config::Config;
+use serde_json::{json, Value};
+
+fn discover() -> Value {
+    json!({"jsonrpc": "2.0", "id": 1, "method": "rpc.discover"})
+}
+
+#[tokio::test]
+async fn responses_are_gzipped_when_the_client_asks() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .header("Accept-Encoding", "gzip")
+        .json(&discover())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        resp.headers()
+            .get("content-encoding")
+            .and_then(|v| v.to_str().ok()),
+        Some("gzip")
+    );
+    // The compressed body round-trips back to the same JSON.
+    let compressed = resp.bytes().await.unwrap();
+    let mut body = String::new();
+    flate2::read::GzDecoder::new(&compressed[..])
+        .read_to_string(&mut body)
+        .expect("gzip body decodes");
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert!(body["result"]["methods"].is_array(), "{body}");
+
+    // A client that does not ask gets the plain bytes.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&discover())
+        .send()
+        .await
+        .unwrap();
+    assert!(resp.headers().get("content-encoding").is_none());
+    let body: Value = resp.json().await.unwrap();
+    assert!(body["result"]["methods"].is_array(), "{body}");
+}
+
+#[tokio::test]
+async fn compression_can_be_disabled_in_config() {
+    let mut config = Config::default();
+    config.server.compression = false;
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state).await;
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .header("Accept-Encoding", "gzip")
+        .json(&discover())
+        .send()
+        .await
+        .unwrap();
+    assert!(resp.headers().get("content-encoding").is_none());
+    let body: Value = resp.json().await.unwrap();
+    assert!(body["result"]["methods"].is_array(), "{body}");
+}
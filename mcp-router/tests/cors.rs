@@ -0,0 +1,55 @@
+mod common;
+
+use std::sync::Arc;
+
+use mcp_router::config::Config;
+
+async fn spawn_with_origins(origins: &[&str]) -> std::net::SocketAddr {
+    let mut config = Config::default();
+    config.server.allow_origins = origins.iter().map(|s| s.to_string()).collect();
+    let state = Arc::new(common::test_state_with(config).await);
+    common::spawn_app(state).await
+}
+
+async fn allowed_origin(addr: std::net::SocketAddr, origin: &str) -> Option<String> {
+    let resp = reqwest::Client::new()
+        .get(format!("http://{addr}/healthz"))
+        .header("Origin", origin)
+        .send()
+        .await
+        .unwrap();
+    resp.headers()
+        .get("access-control-allow-origin")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+#[tokio::test]
+async fn wildcard_patterns_match_subdomains() {
+    let addr = spawn_with_origins(&["https://*.example.com", "https://app.exact.io"]).await;
+
+    // Subdomains of the pattern are reflected back.
+    assert_eq!(
+        allowed_origin(addr, "https://tools.example.com").await.as_deref(),
+        Some("https://tools.example.com")
+    );
+    // The trailing dot keeps lookalike hosts out.
+    assert_eq!(allowed_origin(addr, "https://evil-example.com").await, None);
+    // The bare apex is not a subdomain of itself.
+    assert_eq!(allowed_origin(addr, "https://example.com").await, None);
+    // Exact entries keep working alongside patterns.
+    assert_eq!(
+        allowed_origin(addr, "https://app.exact.io").await.as_deref(),
+        Some("https://app.exact.io")
+    );
+    assert_eq!(allowed_origin(addr, "https://other.exact.io").await, None);
+}
+
+#[tokio::test]
+async fn star_allows_any_origin() {
+    let addr = spawn_with_origins(&["*"]).await;
+    assert_eq!(
+        allowed_origin(addr, "https://anything.test").await.as_deref(),
+        Some("*")
+    );
+}
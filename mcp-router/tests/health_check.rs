@@ -0,0 +1,66 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+
+/// Logs a line to the marker file (`$1`) on startup, then dies on the first
+/// `tools/call` so the next health probe finds an exited child.
+const FLAKY_SERVER: &str = r#"
+echo started >> "$1"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/call"'*)
+      exit 0 ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn dead_stdio_child_is_respawned_by_the_checker() {
+    let state = Arc::new(common::test_state().await);
+    let marker = tempfile::NamedTempFile::new().unwrap();
+    let marker_path = marker.path().to_string_lossy().into_owned();
+    let _srv = common::register_script(&state, "flaky", FLAKY_SERVER, std::slice::from_ref(&marker_path));
+
+    // First call spawns the child (one marker line), then kills it.
+    let err = state
+        .registry
+        .call(
+            "flaky",
+            mcp_core::rpc::Request::new("tools/call", serde_json::json!({})),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("stdio"), "{err}");
+    assert_eq!(std::fs::read_to_string(&marker_path).unwrap().lines().count(), 1);
+
+    // The background checker notices and respawns within its interval.
+    let _checker = state
+        .registry
+        .spawn_health_checker(Duration::from_millis(100));
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    loop {
+        let starts = std::fs::read_to_string(&marker_path).unwrap().lines().count();
+        if starts >= 2 {
+            break;
+        }
+        assert!(tokio::time::Instant::now() < deadline, "child never respawned");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // And the probe outcome shows up in the admin listing.
+    let addr = common::spawn_app(state.clone()).await;
+    let body: Value = reqwest::get(format!("http://{addr}/api/upstreams"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["upstreams"][0]["health"]["status"], "healthy");
+    assert!(body["upstreams"][0]["health"]["last_check_secs_ago"].is_u64());
+}
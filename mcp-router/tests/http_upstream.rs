@@ -0,0 +1,512 @@
+mod common;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::Json;
+use mcp_router::config::{TransportConfig, UpstreamConfig};
+use mcp_router::jsonrpc::{Request, Response};
+use serde_json::json;
+
+type SeenHeaders = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+/// A mock HTTP MCP server that records the headers of every request.
+async fn spawn_mock() -> (SocketAddr, SeenHeaders) {
+    let seen: SeenHeaders = Arc::new(Mutex::new(Vec::new()));
+    let app = axum::Router::new()
+        .route(
+            "/",
+            post(
+                |State(seen): State<SeenHeaders>,
+                 headers: HeaderMap,
+                 Json(request): Json<Request>| async move {
+                    let captured = headers
+                        .iter()
+                        .map(|(k, v)| {
+                            (k.as_str().to_string(), v.to_str().unwrap_or("").to_string())
+                        })
+                        .collect();
+                    seen.lock().unwrap().push(captured);
+                    let result = if request.method == "initialize" {
+                        json!({"protocolVersion": "2025-06-18"})
+                    } else {
+                        json!({"tools": []})
+                    };
+                    Json(Response::success(request.id, result))
+                },
+            ),
+        )
+        .with_state(seen.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (addr, seen)
+}
+
+#[tokio::test]
+async fn custom_headers_arrive_on_every_call() {
+    let (addr, seen) = spawn_mock().await;
+    let state = common::test_state().await;
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "managed".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: format!("http://{addr}/"),
+                urls: Vec::new(),
+                bearer: Some("tok".into()),
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::from([
+                    ("X-Org-Id".into(), "org_42".into()),
+                    ("MCP-Protocol-Version".into(), "2025-01-01".into()),
+                ]),
+            },
+        })
+        .unwrap();
+
+    for _ in 0..2 {
+        state
+            .registry
+            .call("managed", Request::new("tools/list", json!({})))
+            .await
+            .unwrap();
+    }
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    for headers in seen.iter() {
+        assert_eq!(headers.get("x-org-id").map(String::as_str), Some("org_42"));
+        // The configured value overrides the built-in protocol header.
+        assert_eq!(
+            headers.get("mcp-protocol-version").map(String::as_str),
+            Some("2025-01-01")
+        );
+        assert_eq!(
+            headers.get("authorization").map(String::as_str),
+            Some("Bearer tok")
+        );
+    }
+}
+
+#[tokio::test]
+async fn negotiated_protocol_version_sticks_for_later_calls() {
+    let (addr, seen) = spawn_mock().await;
+    let state = common::test_state().await;
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "remote".into(),
+            protocol_version: Some("2024-11-05".into()),
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: format!("http://{addr}/"),
+                urls: Vec::new(),
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+
+    state
+        .registry
+        .call("remote", Request::new("initialize", json!({})))
+        .await
+        .unwrap();
+    state
+        .registry
+        .call("remote", Request::new("tools/list", json!({})))
+        .await
+        .unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    // The configured override goes out with the handshake; the version the
+    // mock negotiates is used from then on.
+    assert_eq!(
+        seen[0].get("mcp-protocol-version").map(String::as_str),
+        Some("2024-11-05")
+    );
+    assert_eq!(
+        seen[1].get("mcp-protocol-version").map(String::as_str),
+        Some("2025-06-18")
+    );
+}
+
+#[tokio::test]
+async fn sse_replies_are_assembled_into_a_response() {
+    // A streamable-HTTP mock: every POST is answered with an event stream
+    // carrying a progress notification and then the real response.
+    let app = axum::Router::new().route(
+        "/",
+        post(|Json(request): Json<Request>| async move {
+            let id = serde_json::to_string(&request.id).unwrap();
+            let body = format!(
+                "data: {{\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\",\"params\":{{\"progress\":1}}}}\n\n\
+                 data: {{\"jsonrpc\":\"2.0\",\"id\":{id},\"result\":{{\"content\":[{{\"type\":\"text\",\"text\":\"streamed\"}}]}}}}\n\n"
+            );
+            (
+                [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+                body,
+            )
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let state = common::test_state().await;
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "streamer".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: format!("http://{addr}/"),
+                urls: Vec::new(),
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+
+    // The intermediate notification lands on the event hub...
+    let mut events = state.hub.subscribe();
+
+    let response = state
+        .registry
+        .call("streamer", Request::new("tools/call", json!({"name": "x"})))
+        .await
+        .unwrap();
+    // ...and the final frame becomes the call's response.
+    assert_eq!(
+        response.result.unwrap()["content"][0]["text"],
+        "streamed"
+    );
+
+    let event = tokio::time::timeout(std::time::Duration::from_secs(2), events.recv())
+        .await
+        .expect("progress event within 2s")
+        .unwrap();
+    assert_eq!(event.kind, "progress");
+    assert_eq!(event.payload["progress"], 1);
+}
+
+#[tokio::test]
+async fn a_429_reset_header_cools_the_upstream_down_then_lifts() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // First request is refused with 429 and a 300ms reset; the rest succeed.
+    let hits: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+    let app = axum::Router::new()
+        .route(
+            "/",
+            post(
+                |State(hits): State<Arc<AtomicUsize>>, Json(request): Json<Request>| async move {
+                    if hits.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return (
+                            axum::http::StatusCode::TOO_MANY_REQUESTS,
+                            [("x-ratelimit-reset-requests", "300ms")],
+                            Json(Response::success(request.id, json!(null))),
+                        );
+                    }
+                    (
+                        axum::http::StatusCode::OK,
+                        [("x-ratelimit-reset-requests", "0s")],
+                        Json(Response::success(request.id, json!({"tools": []}))),
+                    )
+                },
+            ),
+        )
+        .with_state(hits.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let state = common::test_state().await;
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "limited".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: format!("http://{addr}/"),
+                urls: Vec::new(),
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+
+    let err = state
+        .registry
+        .call("limited", Request::new("tools/list", json!({})))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, mcp_router::upstream::UpstreamError::RateLimited(_)),
+        "{err}"
+    );
+    // Inside the window the call fails fast without reaching the provider.
+    let err = state
+        .registry
+        .call("limited", Request::new("tools/list", json!({})))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, mcp_router::upstream::UpstreamError::RateLimited(_)),
+        "{err}"
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+    let resp = state
+        .registry
+        .call("limited", Request::new("tools/list", json!({})))
+        .await
+        .unwrap();
+    assert!(resp.result.unwrap()["tools"].is_array());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn invalid_header_name_fails_registration() {
+    let state = common::test_state().await;
+    let err = state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "broken".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: "http://127.0.0.1:1/".into(),
+                urls: Vec::new(),
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::from([("bad header".into(), "x".into())]),
+            },
+        })
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid header name"), "{err}");
+    assert!(state.registry.get("broken").is_none());
+}
+
+#[tokio::test]
+async fn pooled_replicas_fail_over_to_a_healthy_one() {
+    let (addr, seen) = spawn_mock().await;
+    let state = common::test_state().await;
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "pooled".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: String::new(),
+                // Nothing listens on port 9: the pool should bench it and
+                // keep answering from the live replica.
+                urls: vec!["http://127.0.0.1:9/".into(), format!("http://{addr}/")],
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+
+    for _ in 0..4 {
+        let resp = state
+            .registry
+            .call("pooled", Request::new("tools/list", json!({})))
+            .await
+            .unwrap();
+        assert!(resp.result.unwrap()["tools"].is_array());
+    }
+    assert_eq!(seen.lock().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn token_command_mints_and_rotates_the_bearer() {
+    let (addr, seen) = spawn_mock().await;
+    // Each run of the fake token command mints tok-1, tok-2, ... with a
+    // 300ms lifetime.
+    let dir = tempfile::tempdir().unwrap();
+    let counter = dir.path().join("mints");
+    let command = format!(
+        "n=$(cat {c} 2>/dev/null || echo 0); n=$((n+1)); echo $n > {c}; \
+         printf '{{\"token\":\"tok-%s\",\"expires_in\":0.3}}' $n",
+        c = counter.display()
+    );
+
+    let state = common::test_state().await;
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "oauth".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: format!("http://{addr}/"),
+                urls: Vec::new(),
+                // The minted token wins over the static bearer.
+                bearer: Some("stale-static".into()),
+                token_command: Some(command),
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+
+    for _ in 0..2 {
+        state
+            .registry
+            .call("oauth", Request::new("tools/list", json!({})))
+            .await
+            .unwrap();
+    }
+    // Both calls inside the lifetime reuse the first minted token.
+    {
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        for headers in seen.iter() {
+            assert_eq!(
+                headers.get("authorization").map(String::as_str),
+                Some("Bearer tok-1")
+            );
+        }
+    }
+
+    tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+    state
+        .registry
+        .call("oauth", Request::new("tools/list", json!({})))
+        .await
+        .unwrap();
+    let seen = seen.lock().unwrap();
+    assert_eq!(
+        seen.last().unwrap().get("authorization").map(String::as_str),
+        Some("Bearer tok-2")
+    );
+}
+
+#[tokio::test]
+async fn the_user_agent_names_the_deployment_unless_overridden() {
+    let (addr, seen) = spawn_mock().await;
+    let mut config = mcp_router::config::Config::default();
+    config.server.deployment_tag = "prod-eu".into();
+    let state = common::test_state_with(config).await;
+    for (name, user_agent) in [("tagged", None), ("fronted", Some("acme-gateway/2.0".into()))] {
+        state
+            .registry
+            .register_config(&UpstreamConfig {
+                name: name.into(),
+                protocol_version: None,
+                allow_tools: Vec::new(),
+                deny_tools: Vec::new(),
+                allow_prompts: Vec::new(),
+                deny_prompts: Vec::new(),
+                allow_resources: Vec::new(),
+                deny_resources: Vec::new(),
+                cost_multipliers: HashMap::new(),
+                request_transforms: Vec::new(),
+                response_transforms: Vec::new(),
+                transport: TransportConfig::Http {
+                    url: format!("http://{addr}/"),
+                    urls: Vec::new(),
+                    bearer: None,
+                    token_command: None,
+                    user_agent,
+                    headers: HashMap::new(),
+                },
+            })
+            .unwrap();
+        state
+            .registry
+            .call(name, Request::new("tools/list", json!({})))
+            .await
+            .unwrap();
+    }
+
+    let seen = seen.lock().unwrap();
+    let tagged = format!("mcp-router/{} (prod-eu)", env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        seen[0].get("user-agent").map(String::as_str),
+        Some(tagged.as_str())
+    );
+    // The per-upstream override replaces the whole string.
+    assert_eq!(
+        seen[1].get("user-agent").map(String::as_str),
+        Some("acme-gateway/2.0")
+    );
+}
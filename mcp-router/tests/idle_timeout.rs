@@ -0,0 +1,117 @@
+mod common;
+
+use std::time::Duration;
+
+use mcp_router::config::TransportConfig;
+use mcp_router::jsonrpc::Request;
+use serde_json::json;
+
+/// Appends a line to the marker file (`$1`) on every spawn, then serves.
+const MARKING_SERVER: &str = r#"
+echo started >> "$1"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"ping"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"ok":true}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn idle_children_are_reaped_and_respawn_on_demand() {
+    let state = common::test_state().await;
+    let marker = tempfile::tempdir().expect("create tempdir");
+    let marker_path = marker
+        .path()
+        .join("spawns")
+        .to_string_lossy()
+        .into_owned();
+    let _dir = common::register_script_with(
+        &state,
+        "sleepy",
+        MARKING_SERVER,
+        std::slice::from_ref(&marker_path),
+        |config| {
+            if let TransportConfig::Stdio {
+                idle_timeout_ms, ..
+            } = &mut config.transport
+            {
+                *idle_timeout_ms = Some(100);
+            }
+        },
+    );
+
+    let resp = state
+        .registry
+        .call("sleepy", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result.unwrap()["ok"], true);
+    let spawns = std::fs::read_to_string(&marker_path).unwrap();
+    assert_eq!(spawns.lines().count(), 1);
+
+    // Sit idle well past the timeout so the reaper takes the child down.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // The next call respawns transparently: same answer, one more spawn.
+    let resp = state
+        .registry
+        .call("sleepy", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result.unwrap()["ok"], true);
+    let spawns = std::fs::read_to_string(&marker_path).unwrap();
+    assert_eq!(spawns.lines().count(), 2, "spawns: {spawns:?}");
+}
+
+#[tokio::test]
+async fn concurrent_calls_across_a_respawn_all_succeed() {
+    let state = common::test_state().await;
+    let marker = tempfile::tempdir().expect("create tempdir");
+    let marker_path = marker
+        .path()
+        .join("spawns")
+        .to_string_lossy()
+        .into_owned();
+    let _dir = common::register_script_with(
+        &state,
+        "churny",
+        MARKING_SERVER,
+        std::slice::from_ref(&marker_path),
+        |config| {
+            if let TransportConfig::Stdio {
+                idle_timeout_ms, ..
+            } = &mut config.transport
+            {
+                *idle_timeout_ms = Some(100);
+            }
+        },
+    );
+
+    let burst = || async {
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let registry = state.registry.clone();
+                tokio::spawn(async move {
+                    registry.call("churny", Request::new("ping", json!({}))).await
+                })
+            })
+            .collect();
+        for task in tasks {
+            let resp = task.await.expect("no panic").expect("call succeeds");
+            assert_eq!(resp.result.unwrap()["ok"], true);
+        }
+    };
+
+    burst().await;
+    // Let the reaper take the child down, then hit the dead upstream with a
+    // concurrent burst: every call succeeds and exactly one respawn happens.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    burst().await;
+
+    let spawns = std::fs::read_to_string(&marker_path).unwrap();
+    assert_eq!(spawns.lines().count(), 2, "spawns: {spawns:?}");
+}
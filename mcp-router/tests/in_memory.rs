@@ -0,0 +1,85 @@
+//! Embedding: closure-backed upstreams mounted with `register_dyn` serve
+//! tools through the router with no subprocess behind them.
+
+mod common;
+
+use std::sync::Arc;
+
+use mcp_core::rpc::Response;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn closure_tools_are_listed_and_callable() {
+    let state = Arc::new(common::test_state().await);
+    state
+        .registry
+        .register_dyn("embed", |req| async move {
+            match req.method.as_str() {
+                "tools/list" => Response::success(
+                    req.id,
+                    json!({"tools": [{"name": "hello", "description": "In-process greeter"}]}),
+                ),
+                "tools/call" => {
+                    let who = req
+                        .params
+                        .pointer("/arguments/who")
+                        .and_then(Value::as_str)
+                        .unwrap_or("world");
+                    Response::success(
+                        req.id,
+                        json!({"content": [{"type": "text", "text": format!("hello {who}")}]}),
+                    )
+                }
+                _ => Response::success(req.id, json!({})),
+            }
+        })
+        .unwrap();
+    let addr = common::spawn_app(state).await;
+    let client = reqwest::Client::new();
+
+    let listed: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let names: Vec<&str> = listed["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|t| t["name"].as_str())
+        .collect();
+    assert!(names.contains(&"embed/hello"), "{listed}");
+
+    let called: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {"name": "embed/hello", "arguments": {"who": "embedder"}},
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        called["result"]["content"][0]["text"], "hello embedder",
+        "{called}"
+    );
+}
+
+#[tokio::test]
+async fn dyn_names_follow_the_same_separator_rule() {
+    let state = common::test_state().await;
+    let err = state
+        .registry
+        .register_dyn("bad/name", |req| async move {
+            Response::success(req.id, json!({}))
+        })
+        .unwrap_err();
+    assert!(err.to_string().contains("bad/name"), "{err}");
+}
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use mcp_router::config::Config;
+
+/// The `mcp-router` binary built alongside this suite by
+/// `cargo test --workspace`.
+fn router_binary() -> PathBuf {
+    let mut path = std::env::current_exe().expect("test executable path");
+    path.pop();
+    path.pop();
+    path.push("mcp-router");
+    path
+}
+
+#[test]
+fn init_writes_a_loadable_example_config() {
+    let bin = router_binary();
+    assert!(
+        bin.exists(),
+        "mcp-router binary not found at {} (run via `cargo test --workspace`)",
+        bin.display()
+    );
+    let dir = tempfile::tempdir().expect("create tempdir");
+    let output = dir.path().join("router.toml");
+
+    let run = std::process::Command::new(&bin)
+        .args(["init", "--output"])
+        .arg(&output)
+        .output()
+        .expect("run init");
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains("MCP_ROUTER_MASTER_KEY"), "{stdout}");
+
+    // The generated file round-trips through the normal loader and carries
+    // the example upstreams.
+    let config = Config::load_from(&output).expect("load generated config");
+    let names: Vec<&str> = config.upstreams.iter().map(|u| u.name.as_str()).collect();
+    assert!(names.contains(&"fs"), "upstreams: {names:?}");
+
+    // A second run refuses to clobber the file without --force.
+    let again = std::process::Command::new(&bin)
+        .args(["init", "--output"])
+        .arg(&output)
+        .output()
+        .expect("rerun init");
+    assert!(!again.status.success());
+    assert!(String::from_utf8_lossy(&again.stderr).contains("--force"));
+
+    let forced = std::process::Command::new(&bin)
+        .args(["init", "--force", "--output"])
+        .arg(&output)
+        .output()
+        .expect("forced init");
+    assert!(forced.status.success());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use mcp_router::config::{TransportConfig, UpstreamConfig};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn requests_at_the_hop_limit_are_rejected() {
+    let state = Arc::new(common::test_state().await);
+    let max_hops = state.config.server.max_hops;
+    let addr = common::spawn_app(state).await;
+    let client = reqwest::Client::new();
+
+    // The hop count arrives either as the `mcp-hops` header...
+    let body: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .header("mcp-hops", max_hops.to_string())
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["error"]["code"], -32004, "{body}");
+    assert_eq!(body["error"]["data"]["max_hops"], max_hops);
+
+    // ...or embedded in `params._meta` by a forwarding router.
+    let body: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list",
+                      "params": {"_meta": {"mcp-hops": max_hops + 3}}}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["error"]["code"], -32004, "{body}");
+
+    // One hop short of the limit still dispatches.
+    let body: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .header("mcp-hops", (max_hops - 1).to_string())
+        .json(&json!({"jsonrpc": "2.0", "id": 3, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body["result"]["tools"].is_array(), "{body}");
+}
+
+/// The worst mesh misconfiguration: the router registered as its own
+/// upstream. Every `tools/list` fan-out bounces back with one more hop, so
+/// the chain must abort at `max_hops` instead of recursing forever.
+#[tokio::test]
+async fn a_router_pointed_at_itself_aborts_at_the_limit() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+    state
+        .registry
+        .register_config(&UpstreamConfig {
+            name: "mirror".into(),
+            protocol_version: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allow_prompts: Vec::new(),
+            deny_prompts: Vec::new(),
+            allow_resources: Vec::new(),
+            deny_resources: Vec::new(),
+            cost_multipliers: HashMap::new(),
+            request_transforms: Vec::new(),
+            response_transforms: Vec::new(),
+            transport: TransportConfig::Http {
+                url: format!("http://{addr}/mcp"),
+                urls: Vec::new(),
+                bearer: None,
+                token_command: None,
+                user_agent: None,
+                headers: HashMap::new(),
+            },
+        })
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let body: Value = tokio::time::timeout(
+        Duration::from_secs(10),
+        async {
+            client
+                .post(format!("http://{addr}/mcp"))
+                .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap()
+        },
+    )
+    .await
+    .expect("loop was cut off instead of hanging");
+
+    // The innermost hop was rejected with -32004, the failed fan-out was
+    // skipped, and the outer listing came back empty.
+    assert_eq!(body["result"]["tools"], json!([]), "{body}");
+
+    // Exactly one request per hop reached the dispatcher: the client's own
+    // (hop 0) plus one forward per hop until the limit refused to go deeper.
+    let counts: HashMap<String, u64> = state.metrics.rpc_request_counts().into_iter().collect();
+    assert_eq!(
+        counts.get("tools/list").copied(),
+        Some(state.config.server.max_hops + 1)
+    );
+}
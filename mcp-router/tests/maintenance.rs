@@ -0,0 +1,73 @@
+mod common;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+const FAST_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"echo"}]}}' ;;
+    *'"method":"tools/call"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"content":[]}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn maintenance_blocks_calls_but_not_listings() {
+    let state = Arc::new(common::test_state().await);
+    let _dir = common::register_script(&state, "fast", FAST_SERVER, &[]);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+    let rpc = |body: Value| {
+        client
+            .post(format!("http://{addr}/mcp"))
+            .json(&body)
+            .send()
+    };
+    let call = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+                      "params": {"name": "fast/echo", "arguments": {}}});
+    let list = json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"});
+
+    // Flip maintenance on through the admin API.
+    let resp: Value = client
+        .post(format!("http://{addr}/api/maintenance"))
+        .json(&json!({"enabled": true}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(resp["maintenance"], true);
+
+    // Calls are refused with the maintenance code...
+    let body: Value = rpc(call.clone()).await.unwrap().json().await.unwrap();
+    assert_eq!(body["error"]["code"], -32000, "{body}");
+    assert_eq!(body["error"]["data"]["reason"], "maintenance");
+
+    // ...while listings and health stay up.
+    let body: Value = rpc(list.clone()).await.unwrap().json().await.unwrap();
+    assert!(body["result"]["tools"].is_array(), "{body}");
+    let health = client
+        .get(format!("http://{addr}/healthz"))
+        .send()
+        .await
+        .unwrap();
+    assert!(health.status().is_success());
+
+    // Toggling off restores normal service.
+    client
+        .post(format!("http://{addr}/api/maintenance"))
+        .json(&json!({"enabled": false}))
+        .send()
+        .await
+        .unwrap();
+    let body: Value = rpc(call).await.unwrap().json().await.unwrap();
+    assert!(body["result"].is_object(), "{body}");
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+const INIT_OK: &str =
+    r#"echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}'"#;
+
+fn call_server(sleep: &str) -> String {
+    format!(
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*) {INIT_OK} ;;
+    *'"method":"tools/call"'*)
+      {sleep}
+      echo '{{"jsonrpc":"2.0","id":'"$id"',"result":{{"content":[]}}}}' ;;
+  esac
+done
+"#
+    )
+}
+
+#[tokio::test]
+async fn ndjson_batch_streams_fast_results_first() {
+    let state = Arc::new(common::test_state().await);
+    let _slow = common::register_script(&state, "slow", &call_server("sleep 0.6"), &[]);
+    let _fast = common::register_script(&state, "fast", &call_server(""), &[]);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let batch = json!([
+        {"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+         "params": {"name": "slow/x", "arguments": {}}},
+        {"jsonrpc": "2.0", "id": 2, "method": "tools/call",
+         "params": {"name": "fast/y", "arguments": {}}},
+    ]);
+    let resp = reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .header("Accept", "application/x-ndjson")
+        .json(&batch)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/x-ndjson"
+    );
+
+    let body = resp.text().await.unwrap();
+    let lines: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2, "body: {body}");
+    // Completion order: the fast upstream's response streams out first.
+    assert_eq!(lines[0]["id"], 2);
+    assert_eq!(lines[1]["id"], 1);
+}
+
+#[tokio::test]
+async fn oversized_bodies_are_rejected() {
+    let mut config = mcp_router::Config::default();
+    config.server.max_request_bytes = 1024;
+    let state = Arc::new(common::test_state_with(config).await);
+    let _fast = common::register_script(&state, "fast", &call_server(""), &[]);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // Just under the limit goes through.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "fast/y", "arguments": {"pad": "x".repeat(700)}},
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert!(body["result"].is_object(), "{body}");
+
+    // Just over gets a 413 before the handler ever runs.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {"name": "fast/y", "arguments": {"pad": "x".repeat(1100)}},
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 413);
+}
+
+#[tokio::test]
+async fn request_id_is_echoed_or_generated() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // A caller-supplied id comes back untouched.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .header("X-Request-Id", "trace-abc-123")
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        resp.headers().get("x-request-id").unwrap(),
+        "trace-abc-123"
+    );
+
+    // Without one, the router mints a UUID.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap();
+    let generated = resp
+        .headers()
+        .get("x-request-id")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert_eq!(generated.len(), 36, "expected a uuid, got {generated:?}");
+}
+
+#[tokio::test]
+async fn malformed_json_is_a_400_but_rpc_errors_stay_200() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // Not JSON at all: a transport-level 400 carrying a parse error body.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .header("Content-Type", "application/json")
+        .body("{this is not json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["error"]["code"], -32700, "{body}");
+
+    // Well-formed JSON-RPC hitting an unknown method: still a 200, the
+    // failure lives in the JSON-RPC error member.
+    let resp = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "bogus"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["error"]["code"], -32601, "{body}");
+}
+
+#[tokio::test]
+async fn default_batch_stays_a_json_array() {
+    let state = Arc::new(common::test_state().await);
+    let _fast = common::register_script(&state, "fast", &call_server(""), &[]);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let batch = json!([
+        {"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+         "params": {"name": "fast/y", "arguments": {}}},
+        {"jsonrpc": "2.0", "id": 2, "method": "bogus"},
+    ]);
+    let resp = reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .json(&batch)
+        .send()
+        .await
+        .unwrap();
+    assert!(resp
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("application/json"));
+    let body: Value = resp.json().await.unwrap();
+    let entries = body.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["id"], 1);
+    assert!(entries[0]["result"].is_object());
+    assert_eq!(entries[1]["error"]["code"], -32601);
+}
@@ -0,0 +1,130 @@
+//! The method filter: `disabled_methods` hides methods from everyone, and
+//! `scope_methods` narrows what a database-issued API token may call.
+
+mod common;
+
+use std::sync::Arc;
+
+use mcp_router::config::Config;
+use serde_json::{json, Value};
+
+async fn rpc(addr: std::net::SocketAddr, bearer: &str, body: &Value) -> Value {
+    reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .bearer_auth(bearer)
+        .json(body)
+        .send()
+        .await
+        .expect("send rpc")
+        .json()
+        .await
+        .expect("parse rpc response")
+}
+
+#[tokio::test]
+async fn disabled_methods_answer_method_not_found() {
+    let mut config = Config::default();
+    config.server.disabled_methods = vec!["resources/read".into()];
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state).await;
+    let client = reqwest::Client::new();
+
+    let read = json!({
+        "jsonrpc": "2.0", "id": 1, "method": "resources/read",
+        "params": {"uri": "mcp+router://files/etc"},
+    });
+    let resp: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&read)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(resp["error"]["code"], -32601, "{resp}");
+    assert_eq!(
+        resp["error"]["message"],
+        "method disabled: resources/read",
+        "{resp}"
+    );
+
+    // Everything not listed is untouched.
+    let list = json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"});
+    let resp: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&list)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(resp["result"]["tools"].is_array(), "{resp}");
+}
+
+#[tokio::test]
+async fn scoped_tokens_get_per_scope_method_sets() {
+    let mut config = Config::default();
+    config.server.bearer = Some("root-token".into());
+    config.server.scope_methods = [(
+        "catalog".to_string(),
+        vec!["initialize".to_string(), "tools/list".to_string()],
+    )]
+    .into();
+    let state = Arc::new(common::test_state_with(config).await);
+    let store = state.store.as_ref().unwrap();
+    let (_, catalog) = store.issue_token("alice", "catalog").await.unwrap();
+    let (_, admin) = store.issue_token("bob", "admin").await.unwrap();
+    let addr = common::spawn_app(state).await;
+
+    let list = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"});
+    let call = json!({
+        "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+        "params": {"name": "files/read", "arguments": {}},
+    });
+
+    // The catalog-scoped token may list but not call.
+    let resp = rpc(addr, &catalog, &list).await;
+    assert!(resp["result"]["tools"].is_array(), "{resp}");
+    let resp = rpc(addr, &catalog, &call).await;
+    assert_eq!(resp["error"]["code"], -32601, "{resp}");
+    assert_eq!(
+        resp["error"]["message"],
+        "method not permitted for this token: tools/call",
+        "{resp}"
+    );
+
+    // A scope with no entry in `scope_methods` is unrestricted: the call
+    // reaches routing and fails on the missing upstream, not the filter.
+    let resp = rpc(addr, &admin, &call).await;
+    assert_eq!(resp["error"]["message"], "unknown upstream: files", "{resp}");
+
+    // So is the static bearer.
+    let resp = rpc(addr, "root-token", &call).await;
+    assert_eq!(resp["error"]["message"], "unknown upstream: files", "{resp}");
+}
+
+#[tokio::test]
+async fn scopes_in_the_request_body_are_ignored() {
+    let mut config = Config::default();
+    config.server.bearer = Some("root-token".into());
+    config.server.scope_methods = [("catalog".to_string(), vec!["tools/list".to_string()])].into();
+    let state = Arc::new(common::test_state_with(config).await);
+    let store = state.store.as_ref().unwrap();
+    let (_, catalog) = store.issue_token("alice", "catalog").await.unwrap();
+    let addr = common::spawn_app(state).await;
+
+    // A scoped client claiming a wider scope in `_meta` gains nothing: the
+    // stamp from the verified credential wins.
+    let forged = json!({
+        "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+        "params": {
+            "name": "files/read",
+            "arguments": {},
+            "_meta": {"scopes": ["admin"]},
+        },
+    });
+    let resp = rpc(addr, &catalog, &forged).await;
+    assert_eq!(resp["error"]["code"], -32601, "{resp}");
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use std::sync::Arc;
+
+use mcp_router::config::Config;
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn metrics_are_open_by_default() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state).await;
+    // Vec metrics only render once a label combination has a sample.
+    reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .json(&serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap();
+    let resp = reqwest::get(format!("http://{addr}/metrics")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.text().await.unwrap().contains("mcp_rpc_requests_total"));
+}
+
+#[tokio::test]
+async fn metrics_require_auth_when_configured() {
+    let mut config = Config::default();
+    config.server.metrics_require_auth = true;
+    config.server.metrics_bearer = Some("scrape-token".into());
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .get(format!("http://{addr}/metrics"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = client
+        .get(format!("http://{addr}/metrics"))
+        .bearer_auth("wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = client
+        .get(format!("http://{addr}/metrics"))
+        .bearer_auth("scrape-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
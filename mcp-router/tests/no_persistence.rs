@@ -0,0 +1,81 @@
+mod common;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+const FAKE_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"echo"}]}}' ;;
+    *'"method":"tools/call"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"content":[{"type":"text","text":"ok"}]}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn router_proxies_without_a_database() {
+    let state = Arc::new(common::test_state_no_persistence().await);
+    let _srv = common::register_script(&state, "echoer", FAKE_SERVER, &[]);
+    let addr = common::spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    // Aggregation still works.
+    let body: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["result"]["tools"][0]["name"], "echoer/echo");
+
+    // Tool calls go through with no quota enforcement, even for an
+    // identified user the router has never heard of.
+    let body: Value = client
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {
+                "name": "echoer/echo",
+                "arguments": {},
+                "_meta": {"user_id": "nobody"},
+            },
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body["result"]["content"].is_array(), "{body}");
+
+    // Database-backed admin routes answer 503 instead of panicking.
+    let resp = client
+        .get(format!("http://{addr}/api/users"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 503);
+    let resp = client
+        .get(format!("http://{addr}/api/providers"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 503);
+
+    // Registry management stays available.
+    let resp = client
+        .get(format!("http://{addr}/api/upstreams"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+/// A well-behaved plugin: answers `initialize` and lists one tool.
+const GREETER_PLUGIN: &str = r#"#!/bin/sh
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13","serverInfo":{"name":"greeter"}}}' ;;
+    *'"method":"tools/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"greet","inputSchema":{"type":"object"}}]}}' ;;
+  esac
+done
+"#;
+
+/// Dies before the handshake completes.
+const BROKEN_PLUGIN: &str = "#!/bin/sh\nexit 1\n";
+
+fn write_plugin(dir: &Path, name: &str, script: &str) {
+    let path = dir.join(name);
+    std::fs::write(&path, script).expect("write plugin");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .expect("mark plugin executable");
+    }
+}
+
+#[tokio::test]
+async fn plugins_in_the_directory_are_discovered_and_mounted() {
+    let state = Arc::new(common::test_state().await);
+    let dir = tempfile::tempdir().unwrap();
+    write_plugin(dir.path(), "mcp-greeter", GREETER_PLUGIN);
+    write_plugin(dir.path(), "mcp-broken", BROKEN_PLUGIN);
+    // No `mcp-` prefix: not a plugin, never probed.
+    std::fs::write(dir.path().join("README.txt"), "not a plugin").unwrap();
+
+    let found = state.registry.discover_plugins(dir.path()).await;
+    assert_eq!(found, 1);
+    assert_eq!(state.registry.names(), vec!["greeter".to_string()]);
+
+    // The discovered plugin serves through the router like any upstream.
+    let addr = common::spawn_app(state).await;
+    let body: Value = reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let tools: Vec<&str> = body["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|tool| tool["name"].as_str())
+        .collect();
+    assert_eq!(tools, vec!["greeter/greet"], "{body}");
+}
+
+#[tokio::test]
+async fn discovery_never_replaces_a_configured_upstream() {
+    let state = common::test_state().await;
+    let dir = tempfile::tempdir().unwrap();
+    write_plugin(dir.path(), "mcp-files", GREETER_PLUGIN);
+
+    // A configured upstream already owns the `files` name.
+    let _srv = common::register_script(
+        &state,
+        "files",
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}'
+done
+"#,
+        &[],
+    );
+
+    assert_eq!(state.registry.discover_plugins(dir.path()).await, 0);
+    assert_eq!(state.registry.names(), vec!["files".to_string()]);
+}
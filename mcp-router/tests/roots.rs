@@ -0,0 +1,95 @@
+mod common;
+
+use std::sync::Arc;
+
+use mcp_router::config::{Config, RootConfig};
+use serde_json::{json, Value};
+
+/// Answers `initialize`, logging each initialize line it receives to the
+/// file passed as `$1`.
+const ROOT_LOGGING_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      printf '%s\n' "$line" >> "$1"
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+  esac
+done
+"#;
+
+fn config_with_roots() -> Config {
+    let mut config = Config::default();
+    config.server.roots = vec![
+        RootConfig {
+            uri: "file:///data".into(),
+            name: Some("data".into()),
+        },
+        RootConfig {
+            uri: "file:///srv/shared".into(),
+            name: None,
+        },
+    ];
+    config
+}
+
+#[tokio::test]
+async fn roots_list_returns_the_configured_roots() {
+    let state = Arc::new(common::test_state_with(config_with_roots()).await);
+    let addr = common::spawn_app(state).await;
+    let body: Value = reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "roots/list"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let roots = body["result"]["roots"].as_array().unwrap();
+    assert_eq!(roots.len(), 2, "{body}");
+    assert_eq!(roots[0], json!({"uri": "file:///data", "name": "data"}));
+    // No name configured means no name key, not a null one.
+    assert_eq!(roots[1], json!({"uri": "file:///srv/shared"}));
+}
+
+#[tokio::test]
+async fn configured_and_client_roots_reach_a_stdio_upstream() {
+    let state = Arc::new(common::test_state_with(config_with_roots()).await);
+    let log = tempfile::NamedTempFile::new().unwrap();
+    let log_path = log.path().to_string_lossy().into_owned();
+    let _srv = common::register_script(
+        &state,
+        "fs",
+        ROOT_LOGGING_SERVER,
+        std::slice::from_ref(&log_path),
+    );
+
+    // The client's own initialize declares one more root; the handshake probe
+    // forwards it to the not-yet-spoken-to upstream together with the
+    // configured ones.
+    let addr = common::spawn_app(state).await;
+    let body: Value = reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {"roots": [{"uri": "file:///client/workspace"}]},
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body["result"]["protocolVersion"].is_string(), "{body}");
+
+    let seen = std::fs::read_to_string(&log_path).unwrap();
+    // The spawn handshake carries the configured roots...
+    assert!(seen.contains(r#""uri":"file:///data""#), "{seen}");
+    assert!(seen.contains(r#""uri":"file:///srv/shared""#), "{seen}");
+    // ...and the forwarded probe adds the client-declared one.
+    assert!(seen.contains(r#""uri":"file:///client/workspace""#), "{seen}");
+}
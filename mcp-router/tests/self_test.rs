@@ -0,0 +1,76 @@
+//! The `--self-test` preflight: exit zero with healthy upstreams, nonzero
+//! (naming the culprit) when one cannot initialize.
+
+use std::path::PathBuf;
+
+/// The `mcp-router` binary built alongside this suite by
+/// `cargo test --workspace`.
+fn router_binary() -> PathBuf {
+    let mut path = std::env::current_exe().expect("test executable path");
+    path.pop();
+    path.pop();
+    path.push("mcp-router");
+    path
+}
+
+const FAKE_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"probe"}]}}' ;;
+  esac
+done
+"#;
+
+fn write_config(dir: &std::path::Path, command: &str, args: &[String]) -> PathBuf {
+    let args_toml: Vec<String> = args.iter().map(|a| format!("{a:?}")).collect();
+    let config = format!(
+        "[server]\nbind = \"127.0.0.1:0\"\n\n\
+         [database]\npersistence = false\n\n\
+         [[upstreams]]\nname = \"probe\"\nkind = \"stdio\"\ncommand = {command:?}\nargs = [{}]\n",
+        args_toml.join(", ")
+    );
+    let path = dir.join("router.toml");
+    std::fs::write(&path, config).expect("write config");
+    path
+}
+
+#[test]
+fn self_test_passes_with_a_healthy_upstream() {
+    let dir = tempfile::tempdir().expect("create tempdir");
+    let script = dir.path().join("fake.sh");
+    std::fs::write(&script, FAKE_SERVER).expect("write fake server script");
+    let config = write_config(
+        dir.path(),
+        "sh",
+        &[script.to_string_lossy().into_owned()],
+    );
+
+    let run = std::process::Command::new(router_binary())
+        .args(["--self-test", "--config"])
+        .arg(&config)
+        .output()
+        .expect("run self-test");
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(stdout.contains("self-test passed"), "{stdout}");
+    assert!(stdout.contains("1 tools"), "{stdout}");
+}
+
+#[test]
+fn self_test_fails_on_a_broken_upstream() {
+    let dir = tempfile::tempdir().expect("create tempdir");
+    let config = write_config(dir.path(), "/nonexistent/mcp-server", &[]);
+
+    let run = std::process::Command::new(router_binary())
+        .args(["--self-test", "--config"])
+        .arg(&config)
+        .output()
+        .expect("run self-test");
+    assert!(!run.status.success(), "self-test should have failed");
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(stderr.contains("probe"), "{stderr}");
+}
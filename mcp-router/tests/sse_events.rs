@@ -0,0 +1,133 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use mcp_router::sse::RouterEvent;
+use serde_json::json;
+
+/// Read from an SSE response until `wanted` complete events arrived (or the
+/// timeout hits), returning the raw frame text.
+async fn read_events(resp: reqwest::Response, wanted: usize) -> String {
+    let mut stream = resp.bytes_stream();
+    let mut buf = String::new();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while buf.matches("\n\n").count() < wanted {
+        let chunk = tokio::time::timeout_at(deadline, stream.next())
+            .await
+            .expect("timed out waiting for SSE events");
+        match chunk {
+            Some(Ok(bytes)) => buf.push_str(&String::from_utf8_lossy(&bytes)),
+            _ => break,
+        }
+    }
+    buf
+}
+
+#[tokio::test]
+async fn filtered_stream_only_delivers_matching_events() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let resp = reqwest::get(format!(
+        "http://{addr}/events?event=tools_changed&server=fs"
+    ))
+    .await
+    .unwrap();
+    assert!(resp.status().is_success());
+
+    // Non-matching kind, non-matching server, then the one that should arrive.
+    state.hub.publish(RouterEvent::new(
+        "quota_warning",
+        Some("fs".into()),
+        json!({"which": "wrong-kind"}),
+    ));
+    state.hub.publish(RouterEvent::new(
+        "tools_changed",
+        Some("web".into()),
+        json!({"which": "wrong-server"}),
+    ));
+    state.hub.publish(RouterEvent::new(
+        "tools_changed",
+        Some("fs".into()),
+        json!({"which": "match"}),
+    ));
+
+    let frames = read_events(resp, 1).await;
+    assert!(frames.contains("event: tools_changed"), "frames: {frames}");
+    assert!(frames.contains("match"), "frames: {frames}");
+    assert!(!frames.contains("wrong-kind"), "frames: {frames}");
+    assert!(!frames.contains("wrong-server"), "frames: {frames}");
+}
+
+#[tokio::test]
+async fn per_upstream_stream_combines_with_event_filter() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let resp = reqwest::get(format!(
+        "http://{addr}/upstreams/fs/events?event=notification"
+    ))
+    .await
+    .unwrap();
+
+    state.hub.publish(RouterEvent::new(
+        "tools_changed",
+        Some("fs".into()),
+        json!({"which": "wrong-kind"}),
+    ));
+    state.hub.publish(RouterEvent::new(
+        "notification",
+        Some("fs".into()),
+        json!({"which": "match"}),
+    ));
+
+    let frames = read_events(resp, 1).await;
+    assert!(frames.contains("event: notification"), "frames: {frames}");
+    assert!(frames.contains("match"), "frames: {frames}");
+    assert!(!frames.contains("wrong-kind"), "frames: {frames}");
+}
+
+#[tokio::test]
+async fn unfiltered_stream_delivers_everything() {
+    let state = Arc::new(common::test_state().await);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let resp = reqwest::get(format!("http://{addr}/events")).await.unwrap();
+
+    state
+        .hub
+        .publish(RouterEvent::new("tools_changed", Some("fs".into()), json!({"n": 1})));
+    state
+        .hub
+        .publish(RouterEvent::new("quota_warning", None, json!({"n": 2})));
+
+    let frames = read_events(resp, 2).await;
+    assert!(frames.contains("event: tools_changed"), "frames: {frames}");
+    assert!(frames.contains("event: quota_warning"), "frames: {frames}");
+}
+
+#[tokio::test]
+async fn idle_streams_emit_keepalives_at_the_configured_cadence() {
+    let mut config = mcp_router::Config::default();
+    config.server.sse_keepalive_secs = 1;
+    let state = Arc::new(common::test_state_with(config).await);
+    let addr = common::spawn_app(state.clone()).await;
+
+    let resp = reqwest::get(format!("http://{addr}/events")).await.unwrap();
+    assert!(resp.status().is_success());
+
+    // Nothing is published; within a few intervals a comment frame (a line
+    // starting with `:`) must still arrive to hold the connection open.
+    let mut stream = resp.bytes_stream();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    let mut buf = String::new();
+    while !buf.lines().any(|line| line.starts_with(':')) {
+        let chunk = tokio::time::timeout_at(deadline, stream.next())
+            .await
+            .expect("timed out waiting for a keep-alive frame")
+            .expect("stream ended before a keep-alive frame");
+        buf.push_str(&String::from_utf8_lossy(&chunk.unwrap()));
+    }
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use mcp_router::config::{StdioFraming, TransportConfig};
+use mcp_router::jsonrpc::Request;
+use mcp_router::upstream::UpstreamError;
+use serde_json::json;
+
+const INIT_OK: &str =
+    r#"echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}'"#;
+
+#[tokio::test]
+async fn oversized_line_yields_clear_error() {
+    let state = common::test_state().await;
+    let script = format!(
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*) {INIT_OK} ;;
+    *)
+      printf '{{"jsonrpc":"2.0","id":'"$id"',"result":"'
+      head -c 65536 /dev/zero | tr '\0' 'a'
+      printf '"}}\n' ;;
+  esac
+done
+"#
+    );
+    let _dir = common::register_script_with(&state, "big", &script, &[], |config| {
+        if let TransportConfig::Stdio { max_line_bytes, .. } = &mut config.transport {
+            *max_line_bytes = Some(1024);
+        }
+    });
+
+    let err = state
+        .registry
+        .call("big", Request::new("tools/list", json!({})))
+        .await
+        .unwrap_err();
+    match err {
+        UpstreamError::Protocol(message) => {
+            assert!(message.contains("max_line_bytes"), "message: {message}")
+        }
+        other => panic!("expected protocol error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn response_split_across_flushes_is_reassembled() {
+    let state = common::test_state().await;
+    let script = format!(
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*) {INIT_OK} ;;
+    *)
+      printf '{{"jsonrpc":"2.0","id":'"$id"','
+      sleep 0.2
+      printf '"result":{{"ok":true}}}}\n' ;;
+  esac
+done
+"#
+    );
+    let _dir = common::register_script(&state, "chunky", &script, &[]);
+
+    let resp = state
+        .registry
+        .call("chunky", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result.unwrap()["ok"], true);
+}
+
+#[tokio::test]
+async fn lsp_framed_child_round_trips() {
+    let state = common::test_state().await;
+    // An LSP-framed echo peer: read headers up to the blank line, take
+    // exactly Content-Length body bytes, answer in the same framing.
+    let script = r#"
+respond() {
+  printf 'Content-Length: %s\r\n\r\n%s' "${#1}" "$1"
+}
+while :; do
+  len=
+  while IFS= read -r line; do
+    line=$(printf %s "$line" | tr -d '\r')
+    [ -z "$line" ] && break
+    case "$line" in
+      Content-Length:*) len=$(printf %s "${line#Content-Length:}" | tr -d ' ') ;;
+    esac
+  done
+  [ -z "$len" ] && exit 0
+  body=$(head -c "$len")
+  id=$(printf '%s' "$body" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$body" in
+    *'"method":"initialize"'*)
+      respond '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *)
+      respond '{"jsonrpc":"2.0","id":'"$id"',"result":{"framing":"lsp"}}' ;;
+  esac
+done
+"#;
+    let _dir = common::register_script_with(&state, "lsp", script, &[], |config| {
+        if let TransportConfig::Stdio { framing, .. } = &mut config.transport {
+            *framing = StdioFraming::Lsp;
+        }
+    });
+
+    let resp = state
+        .registry
+        .call("lsp", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(resp.result.unwrap()["framing"], "lsp");
+
+    // The frame boundary held: a second call on the same pipe still parses.
+    let resp = state
+        .registry
+        .call("lsp", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    assert!(resp.error.is_none());
+}
+
+#[tokio::test]
+async fn invalid_utf8_is_replaced_not_fatal() {
+    let state = common::test_state().await;
+    let script = format!(
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*) {INIT_OK} ;;
+    *)
+      printf '{{"jsonrpc":"2.0","id":'"$id"',"result":{{"text":"\377"}}}}\n' ;;
+  esac
+done
+"#
+    );
+    let _dir = common::register_script(&state, "binary", &script, &[]);
+
+    let resp = state
+        .registry
+        .call("binary", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    let text = resp.result.unwrap()["text"].as_str().unwrap().to_string();
+    assert!(text.contains('\u{FFFD}'), "text: {text:?}");
+
+    // The connection survives for the next call.
+    let resp = state
+        .registry
+        .call("binary", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    assert!(resp.error.is_none());
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use std::time::Duration;
+
+use mcp_router::jsonrpc::Request;
+use mcp_router::router::handle_jsonrpc;
+use serde_json::json;
+
+/// A scripted stdio MCP server: advertises `alpha` until it sees a
+/// `tools/call`, after which it emits `notifications/tools/list_changed`
+/// before the call response and advertises `beta` from then on.
+const FAKE_SERVER: &str = r#"
+state_dir="$1"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13","capabilities":{}}}' ;;
+    *'"method":"tools/list"'*)
+      if [ -f "$state_dir/changed" ]; then
+        echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"beta"}]}}'
+      else
+        echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"alpha"}]}}'
+      fi ;;
+    *'"method":"tools/call"'*)
+      touch "$state_dir/changed"
+      echo '{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}'
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"content":[]}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn tools_list_changed_invalidates_cache_and_reaches_subscribers() {
+    let state = common::test_state().await;
+    let state_dir = tempfile::tempdir().unwrap();
+    let _dir = common::register_script(
+        &state,
+        "fake",
+        FAKE_SERVER,
+        &[state_dir.path().to_string_lossy().into_owned()],
+    );
+
+    // Initial catalog is cached.
+    let resp = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+    let tools = resp.result.unwrap()["tools"].clone();
+    assert_eq!(tools[0]["name"], "fake/alpha");
+
+    // The call makes the child emit list_changed before its response.
+    let mut events = state.hub.subscribe();
+    let resp = handle_jsonrpc(
+        &state,
+        Request::new("tools/call", json!({"name": "fake/poke", "arguments": {}})),
+    )
+    .await;
+    assert!(resp.error.is_none(), "tools/call failed: {resp:?}");
+
+    let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out waiting for tools_changed event")
+        .unwrap();
+    assert_eq!(event.kind, "tools_changed");
+    assert_eq!(event.server.as_deref(), Some("fake"));
+
+    // The cache was invalidated, so the next listing reflects the change
+    // even though the TTL has not elapsed.
+    let resp = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+    let tools = resp.result.unwrap()["tools"].clone();
+    assert_eq!(tools[0]["name"], "fake/beta");
+}
+
+#[tokio::test]
+async fn progress_notifications_reach_subscribers_before_the_result() {
+    let state = common::test_state().await;
+    let _dir = common::register_script(
+        &state,
+        "slowtool",
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/call"'*)
+      echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"tok-1","progress":1,"total":2}}'
+      echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"tok-1","progress":2,"total":2}}'
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"content":[{"type":"text","text":"done"}]}}' ;;
+  esac
+done
+"#,
+        &[],
+    );
+
+    let mut events = state.hub.subscribe();
+    let resp = handle_jsonrpc(
+        &state,
+        Request::new(
+            "tools/call",
+            json!({
+                "name": "slowtool/work",
+                "arguments": {},
+                "_meta": {"progressToken": "tok-1"},
+            }),
+        ),
+    )
+    .await;
+    assert!(resp.error.is_none(), "tools/call failed: {resp:?}");
+    assert_eq!(resp.result.unwrap()["content"][0]["text"], "done");
+
+    for step in 1..=2 {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for progress event")
+            .unwrap();
+        assert_eq!(event.kind, "progress");
+        assert_eq!(event.server.as_deref(), Some("slowtool"));
+        assert_eq!(event.payload["progressToken"], "tok-1");
+        assert_eq!(event.payload["progress"], step);
+    }
+}
+
+#[tokio::test]
+async fn other_notifications_are_forwarded_verbatim() {
+    let state = common::test_state().await;
+    let _dir = common::register_script(
+        &state,
+        "chatty",
+        r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"ping"'*)
+      echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info"}}'
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{}}' ;;
+  esac
+done
+"#,
+        &[],
+    );
+
+    let mut events = state.hub.subscribe();
+    let resp = state
+        .registry
+        .call("chatty", Request::new("ping", json!({})))
+        .await
+        .unwrap();
+    assert!(resp.error.is_none());
+
+    let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("timed out waiting for notification event")
+        .unwrap();
+    assert_eq!(event.kind, "notification");
+    assert_eq!(event.server.as_deref(), Some("chatty"));
+    assert_eq!(event.payload["method"], "notifications/message");
+}
+
+/// A child that answers `tools/list` with a stray notification, then a
+/// response carrying a stale id, then the real response.
+const STALE_ID_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/list"'*)
+      echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info"}}'
+      echo '{"jsonrpc":"2.0","id":999999,"result":{"tools":[{"name":"stale"}]}}'
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"fresh"}]}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn stray_frames_before_the_response_are_not_mis_correlated() {
+    let state = common::test_state().await;
+    let _dir = common::register_script(&state, "noisy", STALE_ID_SERVER, &[]);
+
+    // Neither the notification nor the stale-id response is returned as the
+    // call result; the reader keeps going until the matching id arrives.
+    let resp = handle_jsonrpc(&state, Request::new("tools/list", json!({}))).await;
+    let tools = resp.result.unwrap()["tools"].clone();
+    assert_eq!(tools[0]["name"], "noisy/fresh", "{tools}");
+}
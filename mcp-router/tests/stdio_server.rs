@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// The `mcp-router` binary built alongside this suite by
+/// `cargo test --workspace`.
+fn router_binary() -> PathBuf {
+    let mut path = std::env::current_exe().expect("test executable path");
+    path.pop();
+    path.pop();
+    path.push("mcp-router");
+    path
+}
+
+#[tokio::test]
+async fn stdio_mode_answers_initialize_and_tools_list() {
+    let bin = router_binary();
+    assert!(
+        bin.exists(),
+        "mcp-router binary not found at {} (run via `cargo test --workspace`)",
+        bin.display()
+    );
+    let dir = tempfile::tempdir().expect("create tempdir");
+    let config = dir.path().join("router.toml");
+    std::fs::write(&config, "[server]\nstdio_server = true\n").unwrap();
+
+    let mut child = tokio::process::Command::new(&bin)
+        .arg("--config")
+        .arg(&config)
+        .arg("--no-persistence")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("spawn mcp-router");
+    let mut stdin = child.stdin.take().expect("child stdin");
+    let mut lines = BufReader::new(child.stdout.take().expect("child stdout")).lines();
+
+    async fn ask(
+        stdin: &mut tokio::process::ChildStdin,
+        lines: &mut tokio::io::Lines<BufReader<tokio::process::ChildStdout>>,
+        frame: &str,
+    ) -> Value {
+        stdin.write_all(frame.as_bytes()).await.expect("write frame");
+        stdin.write_all(b"\n").await.expect("write newline");
+        stdin.flush().await.expect("flush");
+        let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line())
+            .await
+            .expect("response before timeout")
+            .expect("read line")
+            .expect("stdout open");
+        serde_json::from_str(&line).expect("json frame")
+    }
+
+    let init = ask(
+        &mut stdin,
+        &mut lines,
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+    )
+    .await;
+    assert_eq!(init["result"]["serverInfo"]["name"], "mcp-router", "{init}");
+
+    let tools = ask(
+        &mut stdin,
+        &mut lines,
+        r#"{"jsonrpc":"2.0","id":2,"method":"tools/list","params":{}}"#,
+    )
+    .await;
+    assert!(tools["result"]["tools"].is_array(), "{tools}");
+}
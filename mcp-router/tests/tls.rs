@@ -0,0 +1,51 @@
+mod common;
+
+use std::sync::Arc;
+
+use mcp_router::config::TlsConfig;
+use mcp_router::server::{build_app, serve_tls};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn tls_listener_completes_handshake_and_initialize() {
+    // Self-signed cert for localhost, written where the server expects PEMs.
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let cert_path = dir.path().join("cert.pem");
+    let key_path = dir.path().join("key.pem");
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+    let state = Arc::new(common::test_state().await);
+    let app = build_app(state);
+    let tls = TlsConfig {
+        cert_path: cert_path.to_string_lossy().into_owned(),
+        key_path: key_path.to_string_lossy().into_owned(),
+    };
+    let handle = axum_server::Handle::new();
+    let server = handle.clone();
+    tokio::spawn(async move {
+        serve_tls(app, "127.0.0.1:0".parse().unwrap(), &tls, server)
+            .await
+            .unwrap();
+    });
+    let addr = handle.listening().await.expect("tls listener bound");
+
+    // Plain HTTP against the TLS port fails the handshake.
+    assert!(reqwest::get(format!("http://{addr}/healthz")).await.is_err());
+
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let body: Value = client
+        .post(format!("https://{addr}/mcp"))
+        .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["result"]["serverInfo"]["name"], "mcp-router", "{body}");
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+const THREE_TOOL_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"tools/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"read"},{"name":"write"},{"name":"delete"}]}}' ;;
+    *'"method":"tools/call"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"content":[{"type":"text","text":"ran"}]}}' ;;
+  esac
+done
+"#;
+
+async fn rpc(addr: std::net::SocketAddr, body: Value) -> Value {
+    reqwest::Client::new()
+        .post(format!("http://{addr}/mcp"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+const PROMPT_RESOURCE_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p'); [ -n "$id" ] || id=0
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2024-05-13"}}' ;;
+    *'"method":"prompts/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"prompts":[{"name":"summarize"},{"name":"jailbreak"}]}}' ;;
+    *'"method":"resources/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"resources":[{"uri":"file:///ok.txt"},{"uri":"file:///secret.txt"}]}}' ;;
+    *'"method":"resources/read"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"contents":[{"text":"data"}]}}' ;;
+  esac
+done
+"#;
+
+#[tokio::test]
+async fn denied_tools_are_hidden_and_rejected() {
+    let state = Arc::new(common::test_state().await);
+    let _srv = common::register_script_with(&state, "fs", THREE_TOOL_SERVER, &[], |cfg| {
+        cfg.allow_tools = vec!["read".into(), "write".into()];
+        cfg.deny_tools = vec!["write".into()];
+    });
+    let addr = common::spawn_app(state.clone()).await;
+
+    // Only `read` survives: `delete` misses the allowlist, `write` is denied.
+    let body = rpc(addr, json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"})).await;
+    let names: Vec<&str> = body["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|t| t["name"].as_str())
+        .collect();
+    assert_eq!(names, vec!["fs/read"], "{body}");
+
+    // Calling a filtered tool directly looks like an unknown tool.
+    for tool in ["fs/write", "fs/delete"] {
+        let body = rpc(
+            addr,
+            json!({
+                "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+                "params": {"name": tool, "arguments": {}},
+            }),
+        )
+        .await;
+        assert_eq!(body["error"]["code"], -32601, "{body}");
+    }
+
+    // The allowed tool still goes through.
+    let body = rpc(
+        addr,
+        json!({
+            "jsonrpc": "2.0", "id": 3, "method": "tools/call",
+            "params": {"name": "fs/read", "arguments": {}},
+        }),
+    )
+    .await;
+    assert_eq!(body["result"]["content"][0]["text"], "ran", "{body}");
+}
+
+#[tokio::test]
+async fn denied_prompts_and_resources_are_hidden_and_rejected() {
+    let state = Arc::new(common::test_state().await);
+    let _srv = common::register_script_with(&state, "docs", PROMPT_RESOURCE_SERVER, &[], |cfg| {
+        cfg.deny_prompts = vec!["jailbreak".into()];
+        cfg.deny_resources = vec!["file:///secret.txt".into()];
+    });
+    let addr = common::spawn_app(state.clone()).await;
+
+    let body = rpc(addr, json!({"jsonrpc": "2.0", "id": 1, "method": "prompts/list"})).await;
+    let names: Vec<&str> = body["result"]["prompts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|p| p["name"].as_str())
+        .collect();
+    assert_eq!(names, vec!["docs/summarize"], "{body}");
+
+    let body = rpc(addr, json!({"jsonrpc": "2.0", "id": 2, "method": "resources/list"})).await;
+    let uris: Vec<&str> = body["result"]["resources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|r| r["uri"].as_str())
+        .collect();
+    assert_eq!(uris.len(), 1, "{body}");
+    assert!(uris[0].contains("ok"), "{body}");
+
+    let body = rpc(
+        addr,
+        json!({
+            "jsonrpc": "2.0", "id": 3, "method": "prompts/get",
+            "params": {"name": "docs/jailbreak"},
+        }),
+    )
+    .await;
+    assert_eq!(body["error"]["code"], -32601, "{body}");
+
+    // Direct read of the denied resource, using the router's own encoding.
+    let denied = mcp_router::router::encode_resource_uri("docs", "file:///secret.txt");
+    let body = rpc(
+        addr,
+        json!({
+            "jsonrpc": "2.0", "id": 4, "method": "resources/read",
+            "params": {"uri": denied},
+        }),
+    )
+    .await;
+    assert_eq!(body["error"]["code"], -32602, "{body}");
+
+    // The permitted resource still reads fine.
+    let allowed = mcp_router::router::encode_resource_uri("docs", "file:///ok.txt");
+    let body = rpc(
+        addr,
+        json!({
+            "jsonrpc": "2.0", "id": 5, "method": "resources/read",
+            "params": {"uri": allowed},
+        }),
+    )
+    .await;
+    assert_eq!(body["result"]["contents"][0]["text"], "data", "{body}");
+}
@@ -0,0 +1,46 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn initialize_completes_over_a_unix_socket() {
+    let state = Arc::new(common::test_state().await);
+    let app = mcp_router::server::build_app(state);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("router.sock");
+    let serve_path = path.clone();
+    tokio::spawn(async move {
+        mcp_router::server::serve_unix(app, &serve_path, std::future::pending())
+            .await
+            .unwrap();
+    });
+    for _ in 0..100 {
+        if path.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The socket is not world-accessible.
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o660, "mode {mode:o}");
+
+    let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#;
+    let request = format!(
+        "POST /mcp HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.contains("protocolVersion"), "{response}");
+}
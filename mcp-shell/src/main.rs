@@ -0,0 +1,200 @@
+//! `mcp-shell`: run allowlisted commands as an MCP tool.
+//!
+//! Exposes a single `shell/run` tool over newline-delimited JSON-RPC on
+//! stdio. Only commands named in the `--allow` list may run, arguments are
+//! passed as an array (never a shell string, so tool arguments cannot inject
+//! commands), and stdout, stderr and the exit code all come back in the
+//! result.
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use mcp_core::rpc::{code, Request, Response};
+use serde_json::{json, Value};
+
+#[derive(Parser)]
+#[command(name = "mcp-shell", about = "Allowlisted shell-command MCP server")]
+struct Args {
+    /// Command the server may execute; repeat for each allowed command.
+    #[arg(long = "allow")]
+    allow: Vec<String>,
+
+    /// Kill a command still running after this many seconds.
+    #[arg(long, default_value_t = 30)]
+    timeout_secs: u64,
+}
+
+struct ShellServer {
+    allow: Vec<String>,
+    timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let server = ShellServer {
+        allow: args.allow,
+        timeout: Duration::from_secs(args.timeout_secs),
+    };
+    mcp_core::stdio::serve_lines(|req| server.handle(req)).await
+}
+
+impl ShellServer {
+    async fn handle(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        match req.method.as_str() {
+            "initialize" => Response::success(
+                id,
+                json!({
+                    "protocolVersion": mcp_core::PROTOCOL_VERSION,
+                    "serverInfo": {"name": "mcp-shell", "version": env!("CARGO_PKG_VERSION")},
+                    "capabilities": {"tools": {}},
+                }),
+            ),
+            "tools/list" => Response::success(id, json!({"tools": self.tools()})),
+            "tools/call" => self.tool_call(req).await,
+            "prompts/list" => Response::success(id, json!({"prompts": []})),
+            "resources/list" => Response::success(id, json!({"resources": []})),
+            other => Response::error(
+                id,
+                code::METHOD_NOT_FOUND,
+                format!("unknown method: {other}"),
+            ),
+        }
+    }
+
+    fn tools(&self) -> Value {
+        json!([
+            {
+                "name": "shell/run",
+                "description": format!(
+                    "Run an allowlisted command (allowed: {})",
+                    self.allow.join(", ")
+                ),
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "command": {"type": "string"},
+                        "args": {"type": "array", "items": {"type": "string"}},
+                    },
+                    "required": ["command"],
+                },
+            },
+        ])
+    }
+
+    async fn tool_call(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let name = req.params.get("name").and_then(Value::as_str).unwrap_or("");
+        if name != "shell/run" {
+            return Response::error(id, code::INTERNAL_ERROR, format!("unknown tool: {name}"));
+        }
+        let args = req
+            .params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        match self.run(&args).await {
+            Ok(result) => Response::success(id, result),
+            Err(message) => Response::error(id, code::INTERNAL_ERROR, message),
+        }
+    }
+
+    async fn run(&self, args: &Value) -> Result<Value, String> {
+        let command = args
+            .get("command")
+            .and_then(Value::as_str)
+            .ok_or("missing command")?;
+        if !self.allow.iter().any(|allowed| allowed == command) {
+            return Err(format!(
+                "command not allowed: {command:?} (allowed: {})",
+                self.allow.join(", ")
+            ));
+        }
+        let argv: Vec<&str> = match args.get("args") {
+            None => Vec::new(),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| item.as_str().ok_or("args must be an array of strings"))
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err("args must be an array of strings".into()),
+        };
+
+        let child = tokio::process::Command::new(command)
+            .args(&argv)
+            .stdin(Stdio::null())
+            .output();
+        let output = tokio::time::timeout(self.timeout, child)
+            .await
+            .map_err(|_| format!("{command} timed out after {:?}", self.timeout))?
+            .map_err(|e| format!("running {command}: {e}"))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        Ok(json!({
+            "content": [{"type": "text", "text": stdout}],
+            "stdout": stdout,
+            "stderr": stderr,
+            // None when the command died to a signal.
+            "exit_code": output.status.code(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(allow: &[&str]) -> ShellServer {
+        ShellServer {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn allowed_commands_run_with_captured_output() {
+        let server = server(&["echo"]);
+        let req = Request::new(
+            "tools/call",
+            json!({"name": "shell/run", "arguments": {"command": "echo", "args": ["hello"]}}),
+        );
+        let result = server.handle(req).await.result.expect("tool result");
+        assert_eq!(result["stdout"], "hello\n");
+        assert_eq!(result["stderr"], "");
+        assert_eq!(result["exit_code"], 0);
+        assert_eq!(result["content"][0]["text"], "hello\n");
+    }
+
+    #[tokio::test]
+    async fn disallowed_commands_are_rejected() {
+        let server = server(&["ls", "cat"]);
+        let req = Request::new(
+            "tools/call",
+            json!({"name": "shell/run", "arguments": {"command": "rm", "args": ["-rf", "/"]}}),
+        );
+        let err = server.handle(req).await.error.expect("rejected");
+        assert!(err.message.contains("command not allowed"), "{}", err.message);
+        assert!(err.message.contains("ls, cat"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn failing_commands_report_their_exit_code() {
+        let server = server(&["cat"]);
+        let req = Request::new(
+            "tools/call",
+            json!({
+                "name": "shell/run",
+                "arguments": {"command": "cat", "args": ["/definitely/not/a/file"]},
+            }),
+        );
+        let result = server.handle(req).await.result.expect("tool result");
+        assert_eq!(result["exit_code"], 1);
+        assert!(
+            result["stderr"].as_str().unwrap().contains("No such file"),
+            "{result}"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! `mcp-sqlite`: a read-only SQLite MCP server for one database file.
+//!
+//! Exposes `sqlite/query` (SELECT only) and `sqlite/schema` tools over
+//! newline-delimited JSON-RPC on stdio. The connection is opened read-only
+//! with `PRAGMA query_only`, so even a statement that slips past the SELECT
+//! check cannot write.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use base64::Engine;
+use clap::Parser;
+use mcp_core::rpc::{code, Request, Response};
+use serde_json::{json, Map, Value};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+
+#[derive(Parser)]
+#[command(name = "mcp-sqlite", about = "Read-only SQLite MCP server")]
+struct Args {
+    /// SQLite database file to serve.
+    #[arg(long)]
+    db: PathBuf,
+}
+
+struct SqliteServer {
+    pool: SqlitePool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let server = SqliteServer {
+        pool: open_read_only(&args.db).await?,
+    };
+    mcp_core::stdio::serve_lines(|req| server.handle(req)).await
+}
+
+/// Open the database in query-only mode: no writes, no schema changes.
+async fn open_read_only(db: &PathBuf) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::new()
+        .filename(db)
+        .read_only(true)
+        .pragma("query_only", "ON");
+    Ok(SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?)
+}
+
+impl SqliteServer {
+    async fn handle(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        match req.method.as_str() {
+            "initialize" => Response::success(
+                id,
+                json!({
+                    "protocolVersion": mcp_core::PROTOCOL_VERSION,
+                    "serverInfo": {"name": "mcp-sqlite", "version": env!("CARGO_PKG_VERSION")},
+                    "capabilities": {"tools": {}},
+                }),
+            ),
+            "tools/list" => Response::success(id, json!({"tools": self.tools()})),
+            "tools/call" => self.tool_call(req).await,
+            "prompts/list" => Response::success(id, json!({"prompts": []})),
+            "resources/list" => Response::success(id, json!({"resources": []})),
+            other => Response::error(
+                id,
+                code::METHOD_NOT_FOUND,
+                format!("unknown method: {other}"),
+            ),
+        }
+    }
+
+    fn tools(&self) -> Value {
+        json!([
+            {
+                "name": "sqlite/query",
+                "description": "Run a read-only SELECT and return rows as JSON objects",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {"sql": {"type": "string"}},
+                    "required": ["sql"],
+                },
+            },
+            {
+                "name": "sqlite/schema",
+                "description": "The CREATE statements for every table and view",
+                "inputSchema": {"type": "object", "properties": {}},
+            },
+        ])
+    }
+
+    async fn tool_call(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let name = req.params.get("name").and_then(Value::as_str).unwrap_or("");
+        let args = req
+            .params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        match name {
+            "sqlite/query" => match self.query(&args).await {
+                Ok(rows) => Response::success(
+                    id,
+                    json!({
+                        "content": [{"type": "text", "text": rows.to_string()}],
+                        "rows": rows,
+                    }),
+                ),
+                Err(message) => Response::error(id, code::INTERNAL_ERROR, message),
+            },
+            "sqlite/schema" => match self.schema().await {
+                Ok(text) => Response::success(
+                    id,
+                    json!({"content": [{"type": "text", "text": text}]}),
+                ),
+                Err(message) => Response::error(id, code::INTERNAL_ERROR, message),
+            },
+            other => Response::error(id, code::INTERNAL_ERROR, format!("unknown tool: {other}")),
+        }
+    }
+
+    async fn query(&self, args: &Value) -> Result<Value, String> {
+        let sql = args
+            .get("sql")
+            .and_then(Value::as_str)
+            .ok_or("missing sql")?;
+        if !sql.trim_start().to_ascii_lowercase().starts_with("select") {
+            return Err("only SELECT statements are allowed".into());
+        }
+        let rows = sqlx::query(sql)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("query failed: {e}"))?;
+        Ok(Value::Array(rows.iter().map(row_to_json).collect()))
+    }
+
+    async fn schema(&self) -> Result<String, String> {
+        let rows = sqlx::query(
+            "SELECT sql FROM sqlite_master \
+             WHERE type IN ('table', 'view') AND sql IS NOT NULL ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("reading schema: {e}"))?;
+        let statements: Vec<String> = rows
+            .iter()
+            .map(|row| row.get::<String, _>("sql"))
+            .collect();
+        Ok(statements.join(";\n"))
+    }
+}
+
+/// One row as a JSON object keyed by column name. Blobs are base64-encoded;
+/// anything SQLite reports with an unexpected type comes back as null.
+fn row_to_json(row: &SqliteRow) -> Value {
+    let mut object = Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        let value = match row.try_get_raw(i) {
+            Ok(raw) if !raw.is_null() => match raw.type_info().name() {
+                "INTEGER" => row.try_get::<i64, _>(i).map(Value::from).ok(),
+                "REAL" => row.try_get::<f64, _>(i).map(Value::from).ok(),
+                "TEXT" => row.try_get::<String, _>(i).map(Value::from).ok(),
+                "BLOB" => row
+                    .try_get::<Vec<u8>, _>(i)
+                    .map(|bytes| {
+                        Value::from(base64::engine::general_purpose::STANDARD.encode(bytes))
+                    })
+                    .ok(),
+                _ => None,
+            },
+            _ => None,
+        };
+        object.insert(column.name().to_string(), value.unwrap_or(Value::Null));
+    }
+    Value::Object(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temp database with a `people` table and two rows, served read-only.
+    async fn temp_server() -> (tempfile::TempDir, SqliteServer) {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let path = dir.path().join("test.db");
+        let seed = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename(&path)
+                .create_if_missing(true),
+        )
+        .await
+        .expect("open seed connection");
+        sqlx::query("CREATE TABLE people (name TEXT, age INTEGER)")
+            .execute(&seed)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO people VALUES ('ada', 36), ('alan', 41)")
+            .execute(&seed)
+            .await
+            .unwrap();
+        seed.close().await;
+        let server = SqliteServer {
+            pool: open_read_only(&path).await.expect("open read-only"),
+        };
+        (dir, server)
+    }
+
+    #[tokio::test]
+    async fn select_returns_rows_as_objects() {
+        let (_dir, server) = temp_server().await;
+        let req = Request::new(
+            "tools/call",
+            json!({
+                "name": "sqlite/query",
+                "arguments": {"sql": "SELECT name, age FROM people ORDER BY name"},
+            }),
+        );
+        let result = server.handle(req).await.result.expect("rows");
+        assert_eq!(
+            result["rows"],
+            json!([{"name": "ada", "age": 36}, {"name": "alan", "age": 41}])
+        );
+    }
+
+    #[tokio::test]
+    async fn insert_is_rejected() {
+        let (_dir, server) = temp_server().await;
+        let req = Request::new(
+            "tools/call",
+            json!({
+                "name": "sqlite/query",
+                "arguments": {"sql": "INSERT INTO people VALUES ('eve', 1)"},
+            }),
+        );
+        let err = server.handle(req).await.error.expect("rejected");
+        assert!(err.message.contains("only SELECT"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn schema_lists_create_statements() {
+        let (_dir, server) = temp_server().await;
+        let req = Request::new("tools/call", json!({"name": "sqlite/schema"}));
+        let result = server.handle(req).await.result.expect("schema");
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("CREATE TABLE people"), "schema: {text:?}");
+    }
+}
@@ -0,0 +1,95 @@
+//! `mcp-webfetch`: fetch a URL and return its body as tool output.
+
+use anyhow::Result;
+use clap::Parser;
+use mcp_core::rpc::{code, Request, Response};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "mcp-webfetch", about = "HTTP fetch MCP server")]
+struct Args {
+    /// Per-request timeout in seconds.
+    #[arg(long, default_value_t = 30)]
+    timeout_secs: u64,
+}
+
+struct WebFetch {
+    client: reqwest::Client,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let server = WebFetch {
+        client: reqwest::Client::builder()
+            .timeout(Duration::from_secs(args.timeout_secs))
+            .user_agent(concat!("mcp-webfetch/", env!("CARGO_PKG_VERSION")))
+            .build()?,
+    };
+    mcp_core::stdio::serve_lines(|req| server.handle(req)).await
+}
+
+impl WebFetch {
+    async fn handle(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        match req.method.as_str() {
+            "initialize" => Response::success(
+                id,
+                json!({
+                    "protocolVersion": mcp_core::PROTOCOL_VERSION,
+                    "serverInfo": {"name": "mcp-webfetch", "version": env!("CARGO_PKG_VERSION")},
+                    "capabilities": {"tools": {}},
+                }),
+            ),
+            "tools/list" => Response::success(
+                id,
+                json!({"tools": [{
+                    "name": "webfetch/get",
+                    "description": "Fetch a URL over HTTP GET and return the body",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {"url": {"type": "string"}},
+                        "required": ["url"],
+                    },
+                }]}),
+            ),
+            "tools/call" => self.tool_call(req).await,
+            "prompts/list" => Response::success(id, json!({"prompts": []})),
+            "resources/list" => Response::success(id, json!({"resources": []})),
+            other => Response::error(
+                id,
+                code::METHOD_NOT_FOUND,
+                format!("unknown method: {other}"),
+            ),
+        }
+    }
+
+    async fn tool_call(&self, req: Request) -> Response {
+        let id = req.id.clone();
+        let name = req.params.get("name").and_then(Value::as_str).unwrap_or("");
+        if name != "webfetch/get" {
+            return Response::error(id, code::METHOD_NOT_FOUND, format!("unknown tool: {name}"));
+        }
+        let Some(url) = req.params.pointer("/arguments/url").and_then(Value::as_str) else {
+            return Response::error(id, code::INVALID_PARAMS, "missing url");
+        };
+        match self.fetch(url).await {
+            Ok((status, body)) => Response::success(
+                id,
+                json!({
+                    "content": [{"type": "text", "text": body}],
+                    "isError": status >= 400,
+                }),
+            ),
+            Err(err) => Response::error(id, code::INTERNAL_ERROR, format!("fetch {url}: {err}")),
+        }
+    }
+
+    async fn fetch(&self, url: &str) -> Result<(u16, String)> {
+        let resp = self.client.get(url).send().await?;
+        let status = resp.status().as_u16();
+        let body = resp.text().await?;
+        Ok((status, body))
+    }
+}